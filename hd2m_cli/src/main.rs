@@ -0,0 +1,281 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+
+// Separations below this are reported as unreliable.
+const LOW_SEPARATION: f32 = 0.05;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Command-line tools for the HD2M project")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Finds the template scale, panel crop and threshold from a screenshot of an open stratagem
+    /// panel, and writes them to a config the GUI picks up.
+    Calibrate(CalibrateArgs),
+    /// Matches a screenshot and draws the arrows found, the rows and the rejected candidates over
+    /// it.
+    Overlay(OverlayArgs),
+}
+
+#[derive(Debug, clap::Args)]
+struct CalibrateArgs {
+    /// Screenshot of the whole screen with the stratagem panel open.
+    screenshot: PathBuf,
+    /// Codes shown in the screenshot from the top, e.g. `drd,ulr` for down-right-down and
+    /// up-left-right.
+    #[arg(short, long)]
+    expected: Option<String>,
+    /// Where to write the config.
+    #[arg(short, long, default_value = "hd2m.toml")]
+    output: PathBuf,
+    /// Directory with `up.png`, `down.png`, `right.png` and `left.png` to use instead of the
+    /// bundled templates.
+    #[arg(long)]
+    templates: Option<PathBuf>,
+    /// Screen size the templates were captured at.
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["WIDTH", "HEIGHT"],
+        default_values_t = [2560, 1440]
+    )]
+    base_screen_size: Vec<usize>,
+    /// Template scales to try, defaults to 70% to 140% of the scale inferred from the width.
+    #[arg(long, value_delimiter = ',')]
+    scales: Option<Vec<f64>>,
+    /// Part of the screenshot to look for the panel in, as ratios of the screen size.
+    #[arg(
+        long,
+        num_args = 4,
+        value_names = ["X", "Y", "WIDTH", "HEIGHT"],
+        default_values_t = [0.0, 0.0, 0.4, 0.8]
+    )]
+    search_region: Vec<f64>,
+    #[arg(long, value_enum, default_value_t = Backend::NativeFft)]
+    backend: Backend,
+}
+
+#[derive(Debug, clap::Args)]
+struct OverlayArgs {
+    /// Screenshot of the whole screen with the stratagem panel open.
+    screenshot: PathBuf,
+    /// Where to write the overlay, in the format of its extension.
+    #[arg(short, long, default_value = "overlay.png")]
+    output: PathBuf,
+    /// Config written by `calibrate`, for its templates scale, crop and threshold. The panel
+    /// layout and the default threshold of the GUI are used without it.
+    #[arg(short, long)]
+    calibration: Option<PathBuf>,
+    /// Threshold to use instead of the calibrated one or the default.
+    #[arg(short, long)]
+    threshold: Option<f32>,
+    /// Directory with `up.png`, `down.png`, `right.png` and `left.png` to use instead of the
+    /// bundled templates.
+    #[arg(long)]
+    templates: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = Backend::NativeFft)]
+    backend: Backend,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Backend {
+    Native,
+    NativeFft,
+}
+
+impl From<Backend> for hd2m_cv::MatchingBackend {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Native => hd2m_cv::MatchingBackend::Native,
+            Backend::NativeFft => hd2m_cv::MatchingBackend::NativeFft,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Calibrate(args) => run_calibrate(args),
+        Command::Overlay(args) => run_overlay(args),
+    }
+}
+
+// Loads the templates from `dir`, or the bundled ones without it.
+fn load_templates(dir: Option<&Path>) -> Result<[image::RgbaImage; 4]> {
+    let load_template = |direction: hd2m_cv::Direction| -> Result<image::RgbaImage> {
+        match dir {
+            Some(dir) => {
+                Ok(image::open(dir.join(format!("{}.png", direction.label())))?.to_rgba8())
+            }
+            None => hd2m_cv::load_bundled_template(direction),
+        }
+    };
+    Ok([
+        load_template(hd2m_cv::Direction::Up)?,
+        load_template(hd2m_cv::Direction::Down)?,
+        load_template(hd2m_cv::Direction::Right)?,
+        load_template(hd2m_cv::Direction::Left)?,
+    ])
+}
+
+fn run_calibrate(args: CalibrateArgs) -> Result<()> {
+    let screenshot = image::open(&args.screenshot)?.to_rgba8();
+    let [up, down, right, left] = load_templates(args.templates.as_deref())?;
+    let config = hd2m_cv::Hd2mCvManagerConfig {
+        template_up_image: up,
+        template_down_image: down,
+        template_right_image: right,
+        template_left_image: left,
+        template_source_hashes: None,
+        base_screen_size: (args.base_screen_size[0], args.base_screen_size[1]),
+        hud_scale: None,
+        search_options: None,
+        backend: Some(args.backend.into()),
+        template_cache: None,
+        tone_map_options: None,
+    };
+    let options = hd2m_cv::CalibrationOptions {
+        expected: args
+            .expected
+            .as_deref()
+            .map(hd2m_cv::parse_codes)
+            .transpose()?,
+        scales: args.scales,
+        search_region: (
+            args.search_region[0],
+            args.search_region[1],
+            args.search_region[2],
+            args.search_region[3],
+        ),
+        ..Default::default()
+    };
+
+    let calibration = hd2m_cv::calibrate(&config, &screenshot, &options)?;
+    calibration.save(&args.output)?;
+    print_calibration(&calibration, &args.output);
+    Ok(())
+}
+
+fn run_overlay(args: OverlayArgs) -> Result<()> {
+    let screenshot = image::open(&args.screenshot)?.to_rgba8();
+    let calibration = args
+        .calibration
+        .as_ref()
+        .map(hd2m_cv::Hd2mCvCalibration::load)
+        .transpose()?;
+    let [up, down, right, left] = load_templates(args.templates.as_deref())?;
+    let manager = hd2m_cv::Hd2mCvManager::new(hd2m_cv::Hd2mCvManagerConfig {
+        template_up_image: up,
+        template_down_image: down,
+        template_right_image: right,
+        template_left_image: left,
+        template_source_hashes: None,
+        base_screen_size: calibration
+            .as_ref()
+            .map_or(hd2m_cv::BUNDLED_TEMPLATE_SCREEN_SIZE, |c| {
+                c.base_screen_size
+            }),
+        hud_scale: calibration.as_ref().map(|c| c.hud_scale),
+        // Same as the GUI.
+        search_options: Some(calibration.as_ref().map_or(
+            hd2m_cv::Hd2mCvSearchOptions {
+                threshold: Some(0.9),
+                ..Default::default()
+            },
+            |c| c.search_options(),
+        )),
+        backend: Some(args.backend.into()),
+        template_cache: None,
+        tone_map_options: None,
+    })?;
+    if let Some(threshold) = args.threshold {
+        manager.set_search_options(hd2m_cv::Hd2mCvSearchOptions {
+            threshold: Some(threshold),
+            ..Default::default()
+        });
+    }
+    // Keeps the response maps for the rejected candidates.
+    manager.set_debug(true);
+
+    let (width, height) = (screenshot.width() as usize, screenshot.height() as usize);
+    manager.use_screen_size(width, height)?;
+    let roi = match &calibration {
+        Some(calibration) => calibration.crop_rect(width, height),
+        None => manager
+            .layout()
+            .ok_or(anyhow::anyhow!("No screen size set"))?
+            .panel_roi(),
+    };
+    let (x, y, roi_width, roi_height) = roi;
+    let panel = image::imageops::crop_imm(
+        &screenshot,
+        x as u32,
+        y as u32,
+        roi_width as u32,
+        roi_height as u32,
+    )
+    .to_image();
+    let res = manager.run_match_rgba(&panel)?;
+
+    let template_size = manager.template_size().unwrap_or_default();
+    let rejected = hd2m_cv::find_rejected_in_snapshots(
+        &manager.debug_snapshots(),
+        &res.commands,
+        template_size.0 as f64,
+    );
+    let codes: Vec<_> = res
+        .commands
+        .iter()
+        .map(|row| {
+            let code: Vec<_> = row.iter().map(|desc| desc.direction).collect();
+            hd2m_cv::format_code(&code)
+        })
+        .collect();
+    println!("Threshold: {:.3}", res.threshold);
+    println!("Codes: {}", codes.join(", "));
+    println!("Rejected candidates: {}", rejected.len());
+    hd2m_cv::save_detection_overlay(
+        &screenshot,
+        &hd2m_cv::DetectionOverlay {
+            roi: Some(roi),
+            rows: res.commands,
+            rejected,
+            template_size,
+        },
+        &args.output,
+    )?;
+    println!("Written to {}", args.output.display());
+    Ok(())
+}
+
+fn print_calibration(calibration: &hd2m_cv::Hd2mCvCalibration, output: &Path) {
+    let (width, height) = calibration.screen_size;
+    let (x, y, crop_width, crop_height) = calibration.crop_rect(width, height);
+    println!("Screen size: {width}x{height}");
+    println!(
+        "Base screen size: {}x{}",
+        calibration.base_screen_size.0, calibration.base_screen_size.1
+    );
+    println!(
+        "Template scale: {:.3} (HUD scale: {:.2})",
+        calibration.scale, calibration.hud_scale
+    );
+    println!("Crop: {crop_width}x{crop_height} at ({x}, {y})");
+    println!(
+        "Threshold: {:.3} (separation: {:.3})",
+        calibration.threshold, calibration.separation
+    );
+    println!("Codes: {}", calibration.codes.join(", "));
+    if calibration.separation < LOW_SEPARATION {
+        println!(
+            "Warning: the codes only read over a narrow threshold range, consider a sharper \
+             screenshot or passing the expected codes with `--expected`"
+        );
+    }
+    println!("Written to {}", output.display());
+}
@@ -0,0 +1,81 @@
+#![allow(dead_code)]
+
+use hd2m_cv::{
+    render_panel_fixture, Hd2mCvManager, Hd2mCvManagerConfig, HudLayout, MatchDescriptor,
+    MatchingBackend, PanelFixtureConfig, PanelFixtureTemplates, TemplateMatcher,
+    BUNDLED_TEMPLATE_SCREEN_SIZE,
+};
+
+pub const TEMPLATE_LABELS: [&str; 4] = ["up", "down", "right", "left"];
+
+// `source.png` is a 1080p capture, while the templates were taken at 1440p.
+pub const BASE_SCREEN_SIZE: (usize, usize) = (2560, 1440);
+
+pub const RESOLUTIONS: [(&str, u32, u32); 3] = [
+    ("1080p", 1920, 1080),
+    ("1440p", 2560, 1440),
+    ("4k", 3840, 2160),
+];
+
+pub fn load_image(name: &str) -> image::RgbaImage {
+    image::open(format!("{}/examples/{name}", env!("CARGO_MANIFEST_DIR")))
+        .unwrap()
+        .to_rgba8()
+}
+
+pub fn load_matcher(backend: MatchingBackend) -> TemplateMatcher {
+    let descriptors: Vec<MatchDescriptor> = TEMPLATE_LABELS
+        .iter()
+        .map(|&label| MatchDescriptor::new(label.to_owned(), load_image(&format!("{label}.png"))))
+        .collect();
+    TemplateMatcher::with_backend(&descriptors, backend)
+        .unwrap()
+        .with_resized_scale(1920.0 / BASE_SCREEN_SIZE.0 as f64)
+        .unwrap()
+}
+
+pub fn load_manager(backend: MatchingBackend) -> Hd2mCvManager {
+    Hd2mCvManager::new(Hd2mCvManagerConfig {
+        search_options: Some(hd2m_cv::Hd2mCvSearchOptions {
+            threshold: Some(0.6),
+            ..Default::default()
+        }),
+        backend: Some(backend),
+        ..PanelFixtureTemplates::bundled().unwrap().manager_config()
+    })
+    .unwrap()
+}
+
+/// A frame rendered at `width` x `height` with the fixture renderer, so that every resolution has
+/// arrows drawn at its own scale rather than resampled from a capture of another one.
+pub fn render_frame(width: u32, height: u32) -> image::RgbaImage {
+    use hd2m_cv::Direction::*;
+    let templates = PanelFixtureTemplates::bundled().unwrap();
+    // A loadout of the usual size, with sequences of every length.
+    let fixture = render_panel_fixture(
+        &templates,
+        &PanelFixtureConfig {
+            sequences: vec![
+                vec![Down, Down, Right],
+                vec![Down, Left, Down, Up, Right],
+                vec![Down, Right, Up, Left],
+                vec![Right, Right, Up],
+                vec![Up, Down, Right, Left, Up],
+                vec![Down, Up, Left, Down, Up, Right, Down, Up],
+            ],
+            screen_size: (width, height),
+            noise_amplitude: Some(4),
+            seed: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    fixture.image
+}
+
+/// Crops the stratagem panel out of a frame the same way the GUI does.
+pub fn crop_panel(frame: &image::RgbaImage) -> image::RgbaImage {
+    let size = (frame.width() as usize, frame.height() as usize);
+    let (x, y, width, height) = HudLayout::new(size, BUNDLED_TEMPLATE_SCREEN_SIZE).panel_roi();
+    image::imageops::crop_imm(frame, x as u32, y as u32, width as u32, height as u32).to_image()
+}
@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use hd2m_cv::{
+    fft_size_for, match_template_ccorr_normed, match_templates_ccorr_normed_fft, MatchingBackend,
+    TemplateSpectrum,
+};
+use ndarray as nd;
+
+mod common;
+
+fn bench_match_template(c: &mut Criterion) {
+    let source = common::load_image("source.png");
+    let crop = common::crop_panel(&source);
+    let matcher = common::load_matcher(MatchingBackend::Native);
+    let templates: Vec<nd::Array2<f32>> = common::TEMPLATE_LABELS
+        .iter()
+        .map(|label| matcher.template_for(label).unwrap().clone())
+        .collect();
+
+    let mut group = c.benchmark_group("match_template");
+    for (name, frame) in [("crop", &crop), ("full", &source)] {
+        let edges = matcher.backend().pre_process_rgba(frame).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("native", name), &edges, |b, edges| {
+            b.iter(|| {
+                templates
+                    .iter()
+                    .map(|t| match_template_ccorr_normed(&edges.view(), &t.view()).unwrap())
+                    .collect::<Vec<_>>()
+            })
+        });
+
+        // Spectra are cached per scale by the manager, so they are not part of the measurement.
+        let spectra: Vec<TemplateSpectrum> = templates
+            .iter()
+            .map(|t| TemplateSpectrum::new(&t.view(), fft_size_for(edges.dim())).unwrap())
+            .collect();
+        group.bench_with_input(BenchmarkId::new("native_fft", name), &edges, |b, edges| {
+            b.iter(|| match_templates_ccorr_normed_fft(&edges.view(), &spectra).unwrap())
+        });
+
+        #[cfg(feature = "opencv")]
+        group.bench_with_input(BenchmarkId::new("opencv", name), &edges, |b, edges| {
+            b.iter(|| {
+                templates
+                    .iter()
+                    .map(|t| {
+                        MatchingBackend::OpenCv
+                            .match_template(&edges.view(), &t.view())
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_match_template);
+criterion_main!(benches);
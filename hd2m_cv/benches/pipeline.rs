@@ -0,0 +1,112 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use hd2m_cv::{find_direction_commands, MatchingBackend};
+
+mod common;
+
+fn backends() -> Vec<(&'static str, MatchingBackend)> {
+    vec![
+        ("native", MatchingBackend::Native),
+        ("native_fft", MatchingBackend::NativeFft),
+        #[cfg(feature = "opencv")]
+        ("opencv", MatchingBackend::OpenCv),
+    ]
+}
+
+fn bench_find_direction_commands(c: &mut Criterion) {
+    let crop = common::crop_panel(&common::load_image("source.png"));
+    let results = common::load_matcher(MatchingBackend::Native)
+        .match_templates(&crop)
+        .unwrap();
+    let [up, down, right, left] = &results[..] else {
+        panic!("Expected 4 match results");
+    };
+
+    c.bench_function("find_direction_commands", |b| {
+        b.iter(|| {
+            find_direction_commands(
+                &up.to_search_layout(),
+                &down.to_search_layout(),
+                &right.to_search_layout(),
+                &left.to_search_layout(),
+                Some(0.6),
+                Some(30),
+                Some(18.0),
+            )
+            .unwrap()
+        })
+    });
+}
+
+#[cfg(feature = "opencv")]
+fn bench_convert_response_mat_to_array2(c: &mut Criterion) {
+    use hd2m_cv::TryIntoCv;
+    use ndarray as nd;
+
+    let crop = common::crop_panel(&common::load_image("source.png"));
+    let results = common::load_matcher(MatchingBackend::Native)
+        .match_templates(&crop)
+        .unwrap();
+    let response = results[0].response().clone().insert_axis(nd::Axis(2));
+    let mat: opencv::core::Mat = response.try_into_cv().unwrap();
+
+    c.bench_function("convert_response_mat_to_array2", |b| {
+        b.iter(|| hd2m_cv::convert_response_mat_to_array2(&mat).unwrap())
+    });
+}
+
+fn bench_match_templates(c: &mut Criterion) {
+    let crop = common::crop_panel(&common::load_image("source.png"));
+
+    let mut group = c.benchmark_group("match_templates");
+    for (name, backend) in backends() {
+        let matcher = common::load_matcher(backend);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &crop, |b, crop| {
+            b.iter(|| matcher.match_templates(crop).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_run_match(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run_match");
+    // A single end-to-end run takes hundreds of milliseconds at 4K.
+    group.sample_size(10);
+    for (resolution, width, height) in common::RESOLUTIONS {
+        let crop = common::crop_panel(&common::render_frame(width, height));
+        for (name, backend) in backends() {
+            let manager = common::load_manager(backend);
+            manager
+                .use_screen_size(width as usize, height as usize)
+                .unwrap();
+            group.bench_with_input(
+                BenchmarkId::new(format!("rgba/{name}"), resolution),
+                &crop,
+                |b, crop| b.iter(|| manager.run_match_rgba(crop).unwrap()),
+            );
+
+            #[cfg(feature = "opencv")]
+            {
+                let mat = hd2m_cv::image_to_mat(&crop, hd2m_cv::ChannelOrder::Rgb).unwrap();
+                group.bench_with_input(
+                    BenchmarkId::new(format!("mat/{name}"), resolution),
+                    &mat,
+                    |b, mat| b.iter(|| manager.run_match_mat(mat).unwrap()),
+                );
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_find_direction_commands,
+    bench_match_templates,
+    bench_run_match
+);
+#[cfg(feature = "opencv")]
+criterion_group!(opencv_benches, bench_convert_response_mat_to_array2);
+#[cfg(feature = "opencv")]
+criterion_main!(benches, opencv_benches);
+#[cfg(not(feature = "opencv"))]
+criterion_main!(benches);
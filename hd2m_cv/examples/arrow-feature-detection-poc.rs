@@ -8,7 +8,8 @@ use opencv::{self as cv, core::Mat, features2d, prelude::*};
 fn main() -> Result<()> {
     let source_img = image::open("./examples/source.png")?;
     let source_img_mat: cv::core::Mat = source_img.to_luma_alpha8().try_into_cv()?;
-    let source_img_mat2: cv::core::Mat = source_img.to_rgba8().try_into_cv()?;
+    let source_img_mat2 =
+        hd2m_cv::image_to_mat(&source_img.to_rgba8(), hd2m_cv::ChannelOrder::Rgb)?;
 
     let up_img = image::open("./examples/up.png")?;
     let up_img_mat: cv::core::Mat = up_img.to_luma_alpha8().try_into_cv()?;
@@ -107,7 +108,7 @@ fn main() -> Result<()> {
     // Print or use the matching points as needed
     println!("Matching points in target image: {:?}", matching_points);
 
-    let i: RgbaImage = dst_img.try_into_cv()?;
+    let i: RgbaImage = hd2m_cv::mat_to_image(&dst_img, hd2m_cv::ChannelOrder::Rgb)?;
     i.save(format!("./result.png").as_str())?;
     // image::RgbaImage::try_from_cv(, format!("./result{num}.png").as_str())?;
 
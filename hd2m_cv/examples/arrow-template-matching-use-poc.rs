@@ -2,8 +2,8 @@ use std::time::Duration;
 
 use anyhow::Result;
 use hd2m_cv::{
-    convert_image_to_mat_grayscale, convert_mat_to_array2, find_direction_commands,
-    match_template_with_mask, Direction, TryIntoCv,
+    convert_image_to_mat_grayscale, convert_response_mat_to_array2, find_direction_commands,
+    match_template_with_mask, Direction,
 };
 use image::{RgbImage, RgbaImage};
 use ndarray::*;
@@ -13,28 +13,29 @@ fn main() -> Result<()> {
     let source_img = image::open("./examples/source2.png")?;
     // let source_img = source_img.resize(2560, 1440, image::imageops::FilterType::Lanczos3);
     let source_mat = convert_image_to_mat_grayscale(&source_img.to_rgba8())?;
-    let output_source_mat: cv::core::Mat = source_img.to_rgba8().try_into_cv()?;
+    let output_source_mat =
+        hd2m_cv::image_to_mat(&source_img.to_rgba8(), hd2m_cv::ChannelOrder::Rgb)?;
 
     let start = std::time::Instant::now();
     let up_img = image::open("./examples/up.png")?;
     let up_mat = convert_image_to_mat_grayscale(&up_img.to_rgba8())?;
     let up_match_result = match_template_with_mask(&source_mat, &up_mat, None)?;
-    let up_tm_array = convert_mat_to_array2(&up_match_result)?;
+    let up_tm_array = convert_response_mat_to_array2(&up_match_result)?;
 
     let down_img = image::open("./examples/down.png")?;
     let down_mat = convert_image_to_mat_grayscale(&down_img.to_rgba8())?;
     let down_match_result = match_template_with_mask(&source_mat, &down_mat, None)?;
-    let down_tm_array = convert_mat_to_array2(&down_match_result)?;
+    let down_tm_array = convert_response_mat_to_array2(&down_match_result)?;
 
     let right_img = image::open("./examples/right.png")?;
     let right_mat = convert_image_to_mat_grayscale(&right_img.to_rgba8())?;
     let right_match_result = match_template_with_mask(&source_mat, &right_mat, None)?;
-    let right_tm_array = convert_mat_to_array2(&right_match_result)?;
+    let right_tm_array = convert_response_mat_to_array2(&right_match_result)?;
 
     let left_img = image::open("./examples/left.png")?;
     let left_mat = convert_image_to_mat_grayscale(&left_img.to_rgba8())?;
     let left_match_result = match_template_with_mask(&source_mat, &left_mat, None)?;
-    let left_tm_array = convert_mat_to_array2(&left_match_result)?;
+    let left_tm_array = convert_response_mat_to_array2(&left_match_result)?;
 
     println!("Elapsed (matching): {:?}", start.elapsed());
     println!(
@@ -49,16 +50,16 @@ fn main() -> Result<()> {
 
     let start = std::time::Instant::now();
     let res = find_direction_commands(
-        &up_tm_array.view(),
-        &down_tm_array.view(),
-        &right_tm_array.view(),
-        &left_tm_array.view(),
+        &up_tm_array.t(),
+        &down_tm_array.t(),
+        &right_tm_array.t(),
+        &left_tm_array.t(),
         Some(0.987),
         Some(30),
         Some(20.0),
     )?;
 
-    let mut dst_img: Mat = source_img.try_into_cv()?;
+    let mut dst_img = hd2m_cv::image_to_mat(&source_img.to_rgba8(), hd2m_cv::ChannelOrder::Rgb)?;
     for (i, row) in res.iter().enumerate() {
         for im in row.iter() {
             let color = match im.direction {
@@ -80,7 +81,7 @@ fn main() -> Result<()> {
             )?;
         }
     }
-    let i: RgbImage = dst_img.try_into_cv()?;
+    let i: RgbImage = hd2m_cv::mat_to_image(&dst_img, hd2m_cv::ChannelOrder::Rgb)?;
     // let i: RgbaImage = dst_img.try_into_cv()?;
     i.save(format!("./result.png").as_str())?;
 
@@ -130,7 +131,7 @@ fn report_min_max_log(
         min_val, max_val, min_loc, max_loc
     );
 
-    let i: RgbaImage = dst_img.try_into_cv()?;
+    let i: RgbaImage = hd2m_cv::mat_to_image(&dst_img, hd2m_cv::ChannelOrder::Rgb)?;
     i.save(format!("./result{num}.png").as_str())?;
 
     Ok((min_val, max_val, min_loc, max_loc))
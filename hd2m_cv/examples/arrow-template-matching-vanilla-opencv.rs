@@ -13,7 +13,8 @@ fn main() -> Result<()> {
     // upscale
     // let source_img = source_img.resize(2560, 1440, image::imageops::FilterType::Lanczos3);
     // let source_img_mat: cv::core::Mat = source_img.to_luma_alpha8().try_into_cv()?;
-    let source_img_mat2: cv::core::Mat = source_img.to_rgba8().try_into_cv()?;
+    let source_img_mat2 =
+        hd2m_cv::image_to_mat(&source_img.to_rgba8(), hd2m_cv::ChannelOrder::Rgb)?;
 
     let up_img = image::open("./examples/up.png")?;
     let down_img = image::open("./examples/down.png")?;
@@ -97,7 +98,7 @@ fn find_min_max_log(
         0,
     )?;
 
-    let i: RgbaImage = dst_img.try_into_cv()?;
+    let i: RgbaImage = hd2m_cv::mat_to_image(&dst_img, hd2m_cv::ChannelOrder::Rgb)?;
     i.save(format!("./result{num}.png").as_str())?;
     // image::RgbaImage::try_from_cv(, format!("./result{num}.png").as_str())?;
 
@@ -2,16 +2,21 @@ use anyhow::Result;
 use hd2m_cv::Direction;
 
 fn main() -> Result<()> {
-    let mut manager = hd2m_cv::Hd2mCvManager::new(hd2m_cv::Hd2mCvManagerConfig {
+    let manager = hd2m_cv::Hd2mCvManager::new(hd2m_cv::Hd2mCvManagerConfig {
         template_up_image: image::open("./examples/up.png")?.to_rgba8(),
         template_down_image: image::open("./examples/down.png")?.to_rgba8(),
         template_right_image: image::open("./examples/right.png")?.to_rgba8(),
         template_left_image: image::open("./examples/left.png")?.to_rgba8(),
+        template_source_hashes: None,
         base_screen_size: (2560, 1440),
+        hud_scale: None,
         search_options: Some(hd2m_cv::Hd2mCvSearchOptions {
             threshold: Some(0.6),
             ..Default::default()
         }),
+        backend: None,
+        template_cache: None,
+        tone_map_options: None,
     })?;
 
     let start = std::time::Instant::now();
@@ -21,10 +26,12 @@ fn main() -> Result<()> {
     let res = manager.run_match_rgba(&source_img.to_rgba8())?;
     println!(
         "Res: {:?}",
-        res.iter()
+        res.commands
+            .iter()
             .map(|e| e.iter().map(|e| e.direction).collect::<Vec<Direction>>())
             .collect::<Vec<_>>()
     );
+    println!("Threshold: {}", res.threshold);
     println!();
     println!("Elapsed: {:?}", start.elapsed());
 
@@ -0,0 +1,38 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ndarray as nd;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    width: u8,
+    height: u8,
+    values: Vec<f32>,
+    threshold: Option<f32>,
+    search_chunk_size: Option<u8>,
+    discarding_distance_threshold: Option<f64>,
+}
+
+fuzz_target!(|input: Input| {
+    let (width, height) = (input.width as usize % 64, input.height as usize % 64);
+    // Fills the four maps from the fuzzed values, repeating them if there are not enough.
+    let maps: Vec<nd::Array2<f32>> = (0..4)
+        .map(|i| {
+            nd::Array2::from_shape_fn((width, height), |(x, y)| match input.values.len() {
+                0 => 0.0,
+                len => input.values[((i * width + x) * height + y) % len],
+            })
+        })
+        .collect();
+
+    let _ = hd2m_cv::find_direction_commands(
+        &maps[0].view(),
+        &maps[1].view(),
+        &maps[2].view(),
+        &maps[3].view(),
+        input.threshold,
+        input.search_chunk_size.map(|size| size as usize),
+        input.discarding_distance_threshold,
+    );
+});
@@ -0,0 +1,190 @@
+use crate::{Hd2mCvManager, Hd2mCvMatchResult};
+use anyhow::Result;
+#[cfg(feature = "opencv")]
+use opencv as cv;
+use rayon::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Frame that [Hd2mCvManager] can match in batches and streams.
+pub trait MatchTarget {
+    fn run_match(&self, manager: &Hd2mCvManager) -> Result<Hd2mCvMatchResult>;
+
+    /// Same as [Self::run_match], for a frame unrelated to the ones matched before it, see
+    /// [Hd2mCvManager::run_match_rgba_independent].
+    fn run_match_independent(&self, manager: &Hd2mCvManager) -> Result<Hd2mCvMatchResult>;
+}
+
+impl MatchTarget for image::RgbaImage {
+    fn run_match(&self, manager: &Hd2mCvManager) -> Result<Hd2mCvMatchResult> {
+        manager.run_match_rgba(self)
+    }
+
+    fn run_match_independent(&self, manager: &Hd2mCvManager) -> Result<Hd2mCvMatchResult> {
+        manager.run_match_rgba_independent(self)
+    }
+}
+
+#[cfg(feature = "opencv")]
+impl MatchTarget for cv::core::Mat {
+    fn run_match(&self, manager: &Hd2mCvManager) -> Result<Hd2mCvMatchResult> {
+        manager.run_match_mat(self)
+    }
+
+    fn run_match_independent(&self, manager: &Hd2mCvManager) -> Result<Hd2mCvMatchResult> {
+        manager.run_match_mat_independent(self)
+    }
+}
+
+impl<T: MatchTarget + ?Sized> MatchTarget for &T {
+    fn run_match(&self, manager: &Hd2mCvManager) -> Result<Hd2mCvMatchResult> {
+        (**self).run_match(manager)
+    }
+
+    fn run_match_independent(&self, manager: &Hd2mCvManager) -> Result<Hd2mCvMatchResult> {
+        (**self).run_match_independent(manager)
+    }
+}
+
+/// Result of one of many frames matched by [Hd2mCvManager::run_match_batch] or
+/// [Hd2mCvManager::run_match_stream].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hd2mCvFrameResult {
+    /// Index of the frame in the input.
+    pub index: usize,
+    pub result: Hd2mCvMatchResult,
+    /// Time spent matching the frame alone.
+    pub elapsed: Duration,
+}
+
+impl Hd2mCvManager {
+    /// Matches the frames in parallel on the rayon thread pool.
+    ///
+    /// All frames share the templates of the current screen size, along with whatever the backend
+    /// prepared for their frame size. Since they run in no particular order, each frame is matched
+    /// independently of the others, see [MatchTarget::run_match_independent]. The results are in
+    /// the order of `frames`, and the first error fails the whole batch.
+    pub fn run_match_batch<T: MatchTarget + Sync>(
+        &self,
+        frames: &[T],
+    ) -> Result<Vec<Hd2mCvFrameResult>> {
+        frames
+            .par_iter()
+            .enumerate()
+            .map(|(index, frame)| self.run_match_frame(index, || frame.run_match_independent(self)))
+            .collect()
+    }
+
+    /// Matches the frames one by one as the returned iterator is consumed, e.g. when replaying a
+    /// video that doesn't fit in memory.
+    ///
+    /// Changes to the screen size apply from the next frame.
+    pub fn run_match_stream<'a, T: MatchTarget + 'a>(
+        &'a self,
+        frames: impl IntoIterator<Item = T> + 'a,
+    ) -> impl Iterator<Item = Result<Hd2mCvFrameResult>> + 'a {
+        frames
+            .into_iter()
+            .enumerate()
+            .map(move |(index, frame)| self.run_match_frame(index, || frame.run_match(self)))
+    }
+
+    fn run_match_frame(
+        &self,
+        index: usize,
+        run_match: impl FnOnce() -> Result<Hd2mCvMatchResult>,
+    ) -> Result<Hd2mCvFrameResult> {
+        let start = Instant::now();
+        let result = run_match()?;
+        Ok(Hd2mCvFrameResult {
+            index,
+            result,
+            elapsed: start.elapsed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        render_panel_fixture, Direction, Hd2mCvManagerConfig, MatchingBackend, PanelFixtureConfig,
+        PanelFixtureTemplates,
+    };
+
+    #[test]
+    fn test_run_match_batch() -> Result<()> {
+        let templates = PanelFixtureTemplates::bundled()?;
+        let manager = Hd2mCvManager::new(Hd2mCvManagerConfig {
+            search_options: Some(crate::Hd2mCvSearchOptions {
+                threshold: Some(0.7),
+                ..Default::default()
+            }),
+            backend: Some(MatchingBackend::NativeFft),
+            ..templates.manager_config()
+        })?;
+        // Not usable before the screen size is set.
+        assert!(manager
+            .run_match_batch(&[image::RgbaImage::new(8, 8)])
+            .is_err());
+        manager.use_screen_size(2560, 1440)?;
+
+        let panel = render_panel_fixture(
+            &templates,
+            &PanelFixtureConfig {
+                sequences: vec![
+                    vec![Direction::Down, Direction::Right, Direction::Down],
+                    vec![Direction::Up, Direction::Left, Direction::Right],
+                ],
+                screen_size: (2560, 1440),
+                ..Default::default()
+            },
+        )?
+        .image;
+        let frames: Vec<_> = [
+            (100, 100, 200, 100),
+            (100, 170, 200, 100),
+            (100, 100, 240, 160),
+        ]
+        .into_iter()
+        .map(|(x, y, width, height)| {
+            image::imageops::crop_imm(&panel, x, y, width, height).to_image()
+        })
+        .collect();
+        let expected = frames
+            .iter()
+            .map(|frame| manager.run_match_rgba(frame))
+            .collect::<Result<Vec<_>>>()?;
+        assert!(expected.iter().all(|res| !res.commands.is_empty()));
+
+        let batch = manager.run_match_batch(&frames)?;
+        let stream = manager
+            .run_match_stream(&frames)
+            .collect::<Result<Vec<_>>>()?;
+        for results in [batch, stream] {
+            assert_eq!(
+                results.iter().map(|res| res.index).collect::<Vec<_>>(),
+                [0, 1, 2]
+            );
+            assert_eq!(
+                results
+                    .into_iter()
+                    .map(|res| res.result)
+                    .collect::<Vec<_>>(),
+                expected
+            );
+        }
+
+        // The frames of a batch neither reuse nor update what is kept across consecutive frames.
+        manager.set_search_options(crate::Hd2mCvSearchOptions {
+            skip_unchanged_frames: Some(true),
+            ..Default::default()
+        });
+        let batch = manager.run_match_batch(&[&frames[0], &frames[0], &frames[1]])?;
+        assert_eq!(batch[1].result, expected[0]);
+        assert_eq!(
+            manager.frame_cache_stats(),
+            crate::Hd2mCvFrameCacheStats::default()
+        );
+        Ok(())
+    }
+}
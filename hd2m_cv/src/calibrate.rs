@@ -0,0 +1,460 @@
+use crate::{
+    find_direction_commands,
+    scale::{PANEL_ARROW_PITCH, PANEL_ROW_PITCH},
+    Direction, DirectionDescriptor, Hd2mCvManagerConfig, Hd2mCvSearchOptions, HudLayout,
+    TemplateMatcher, TemplateMatcherResult,
+};
+use anyhow::{bail, ensure, Result};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+
+// Thresholds tried at each scale to read the codes, from `0.2` to `0.98`.
+const THRESHOLD_STEPS: usize = 40;
+const THRESHOLD_MIN: f32 = 0.2;
+const THRESHOLD_STEP: f32 = 0.02;
+// Shortest stratagem code, used to tell panels from stray matches without the expected codes.
+const MIN_CODE_LENGTH: usize = 3;
+
+/// What [calibrate] searches over.
+#[derive(Debug, Clone)]
+pub struct CalibrationOptions {
+    /// Codes shown in the screenshot, from the top of the panel. When given, only the settings that
+    /// read them back exactly are considered.
+    pub expected: Option<Vec<Vec<Direction>>>,
+    /// Template scales to try. Defaults to 70% to 140% of the scale of the [HudLayout] of the
+    /// screenshot.
+    pub scales: Option<Vec<f64>>,
+    /// Part of the screenshot to look for the panel in, as `(x, y, width, height)` ratios.
+    pub search_region: (f64, f64, f64, f64),
+    /// Longest code the crop has to fit.
+    pub max_code_length: usize,
+    /// Most stratagems the crop has to fit.
+    pub max_rows: usize,
+}
+
+impl Default for CalibrationOptions {
+    fn default() -> Self {
+        Self {
+            expected: None,
+            scales: None,
+            search_region: (0.0, 0.0, 0.4, 0.8),
+            max_code_length: 8,
+            max_rows: 8,
+        }
+    }
+}
+
+/// Settings found by [calibrate], stored as TOML.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hd2mCvCalibration {
+    /// Size of the calibrated screenshot.
+    pub screen_size: (usize, usize),
+    /// Screen size the templates are native to, see [Hd2mCvManagerConfig::base_screen_size].
+    pub base_screen_size: (usize, usize),
+    /// In-game HUD scale the screenshot was taken with, see [Hd2mCvManagerConfig::hud_scale].
+    pub hud_scale: f64,
+    /// Scale of the templates at `screen_size`.
+    pub scale: f64,
+    /// Region of the HUD holding the panel as `(x, y, width, height)`, measured at the base screen
+    /// size and a HUD scale of `1.0` so that it follows the HUD on other screens, see
+    /// [Self::crop_rect].
+    pub base_crop: (f64, f64, f64, f64),
+    pub threshold: f32,
+    /// Width of the threshold range that reads the same codes, the larger the safer. Zero means a
+    /// single threshold step reads them.
+    pub separation: f32,
+    /// Codes read from the screenshot with these settings, see [format_code].
+    pub codes: Vec<String>,
+}
+
+impl Hd2mCvCalibration {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Same as [Self::load], but returns `None` when there is no file at `path`.
+    pub fn load_if_exists(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(config) => Ok(Some(toml::from_str(&config)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Returns the crop in pixels as `(x, y, width, height)` for a screen of `width` x `height`,
+    /// placed by the [HudLayout] of that screen with the calibrated HUD scale.
+    pub fn crop_rect(&self, width: usize, height: usize) -> (usize, usize, usize, usize) {
+        HudLayout::new((width, height), self.base_screen_size)
+            .with_hud_scale(self.hud_scale)
+            .screen_rect(self.base_crop)
+    }
+
+    /// Returns the search options with the calibrated threshold.
+    pub fn search_options(&self) -> Hd2mCvSearchOptions {
+        Hd2mCvSearchOptions {
+            threshold: Some(self.threshold),
+            ..Default::default()
+        }
+    }
+}
+
+/// Parses written codes like `"drd,uldr"`, one letter per arrow and one code per comma.
+pub fn parse_codes(codes: &str) -> Result<Vec<Vec<Direction>>> {
+    codes
+        .split(',')
+        .map(|code| {
+            code.trim()
+                .chars()
+                .map(|letter| {
+                    Direction::from_letter(letter)
+                        .ok_or(anyhow::anyhow!("Unknown direction `{letter}` in `{code}`"))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Writes a code with the letters of [Direction::letter].
+pub fn format_code(code: &[Direction]) -> String {
+    code.iter().map(Direction::letter).collect()
+}
+
+/// Finds the template scale, panel crop and threshold that best read the stratagem panel in
+/// `screenshot`, using the templates and backend of `config`.
+///
+/// Each scale is tried over a range of thresholds, and the reading that holds over the widest range
+/// of thresholds wins, with the threshold put in the middle of that range.
+pub fn calibrate(
+    config: &Hd2mCvManagerConfig,
+    screenshot: &RgbaImage,
+    options: &CalibrationOptions,
+) -> Result<Hd2mCvCalibration> {
+    let (width, height) = (screenshot.width() as usize, screenshot.height() as usize);
+    ensure!(
+        config.base_screen_size.0 > 0 && config.base_screen_size.1 > 0,
+        "Base screen size must not be empty"
+    );
+    let region = ratio_rect(options.search_region, width, height);
+    ensure!(
+        region.2 > 0 && region.3 > 0,
+        "Search region {:?} is empty",
+        options.search_region
+    );
+    let region_image = image::imageops::crop_imm(
+        screenshot,
+        region.0 as u32,
+        region.1 as u32,
+        region.2 as u32,
+        region.3 as u32,
+    )
+    .to_image();
+
+    let matcher = TemplateMatcher::with_cache(
+        &config.template_descriptors(),
+        config.backend.unwrap_or_default(),
+        config.template_cache.clone(),
+    )?;
+    // The edges don't depend on the scale, only the templates do.
+    let edges = matcher.backend().pre_process_rgba(&region_image)?;
+    let layout_scale = HudLayout::new((width, height), config.base_screen_size).scale();
+    let scales = options.scales.clone().unwrap_or_else(|| {
+        (0..=14)
+            .map(|i| layout_scale * (0.7 + i as f64 * 0.05))
+            .collect()
+    });
+
+    let mut best: Option<Candidate> = None;
+    for scale in scales {
+        ensure!(scale > 0.0, "Scale must be positive, but got {scale}");
+        let scaled = matcher.with_resized_scale(scale)?;
+        let template_size = scaled
+            .template_size()
+            .ok_or(anyhow::anyhow!("No template registered"))?;
+        if template_size.0 > edges.ncols() || template_size.1 > edges.nrows() {
+            continue;
+        }
+        let results = scaled.match_templates_pre_processed(&edges.view())?;
+        let Some(candidate) = find_best_reading(
+            &results,
+            scale,
+            template_size,
+            options.expected.as_deref(),
+            options.max_rows,
+            options.max_code_length,
+        )?
+        else {
+            continue;
+        };
+        if best
+            .as_ref()
+            .is_none_or(|best| candidate.separation > best.separation)
+        {
+            best = Some(candidate);
+        }
+    }
+    let Some(best) = best else {
+        if options.expected.is_some() {
+            bail!("No scale reads the expected codes from the screenshot");
+        }
+        bail!("No arrows found in the screenshot");
+    };
+
+    // Grows the crop from the arrows found to fit the longest code and the most rows.
+    let (template_width, template_height) = best.template_size;
+    let positions = best.commands.iter().flatten().map(|desc| desc.position);
+    let min_x = positions.clone().map(|p| p.x).min().unwrap_or(0) + region.0;
+    let min_y = positions.clone().map(|p| p.y).min().unwrap_or(0) + region.1;
+    let max_x = positions.clone().map(|p| p.x).max().unwrap_or(0) + region.0;
+    let max_y = positions.map(|p| p.y).max().unwrap_or(0) + region.1;
+    let arrow_pitch = PANEL_ARROW_PITCH * best.scale;
+    let row_pitch = PANEL_ROW_PITCH * best.scale;
+    let left = min_x.saturating_sub(template_width);
+    let top = min_y.saturating_sub(template_height);
+    let right = (max_x as f64)
+        .max(min_x as f64 + arrow_pitch * options.max_code_length.saturating_sub(1) as f64)
+        as usize
+        + template_width * 2;
+    let bottom = (max_y as f64)
+        .max(min_y as f64 + row_pitch * options.max_rows.saturating_sub(1) as f64)
+        as usize
+        + template_height * 2;
+    let (right, bottom) = (right.min(width), bottom.min(height));
+
+    Ok(Hd2mCvCalibration {
+        screen_size: (width, height),
+        base_screen_size: config.base_screen_size,
+        hud_scale: best.scale / layout_scale,
+        scale: best.scale,
+        base_crop: (
+            left as f64 / best.scale,
+            top as f64 / best.scale,
+            (right - left) as f64 / best.scale,
+            (bottom - top) as f64 / best.scale,
+        ),
+        threshold: best.threshold,
+        separation: best.separation,
+        codes: best
+            .commands
+            .iter()
+            .map(|row| format_code(&row.iter().map(|desc| desc.direction).collect::<Vec<_>>()))
+            .collect(),
+    })
+}
+
+#[derive(Debug)]
+struct Candidate {
+    scale: f64,
+    template_size: (usize, usize),
+    commands: Vec<Vec<DirectionDescriptor>>,
+    threshold: f32,
+    separation: f32,
+}
+
+// Reads the codes over the threshold range and keeps the reading that holds the longest.
+fn find_best_reading(
+    results: &[TemplateMatcherResult],
+    scale: f64,
+    template_size: (usize, usize),
+    expected: Option<&[Vec<Direction>]>,
+    max_rows: usize,
+    max_code_length: usize,
+) -> Result<Option<Candidate>> {
+    let [res_up, res_down, res_right, res_left] = results else {
+        bail!("Expected 4 match results, but got {}", results.len());
+    };
+
+    // Each reading with the lowest and highest threshold it holds for. Goes from the top, since
+    // lower thresholds only add candidates and are slower to search.
+    let mut readings: Vec<(Vec<Vec<DirectionDescriptor>>, f32, f32)> = Vec::new();
+    for step in (0..THRESHOLD_STEPS).rev() {
+        let threshold = THRESHOLD_MIN + step as f32 * THRESHOLD_STEP;
+        // Same as the manager sets for a screen size.
+        let commands = find_direction_commands(
+            &res_up.to_search_layout(),
+            &res_down.to_search_layout(),
+            &res_right.to_search_layout(),
+            &res_left.to_search_layout(),
+            Some(threshold),
+            Some(template_size.1 * 2),
+            Some(template_size.0 as f64 + 3.0),
+        )?;
+        if commands.len() > max_rows {
+            break;
+        }
+        match readings.last_mut() {
+            Some((last, low, _)) if *last == commands => *low = threshold,
+            _ => readings.push((commands, threshold, threshold)),
+        }
+    }
+
+    let mut best: Option<Candidate> = None;
+    for (commands, low, high) in readings {
+        if commands.is_empty() {
+            continue;
+        }
+        if let Some(expected) = expected {
+            let read = commands
+                .iter()
+                .map(|row| row.iter().map(|desc| desc.direction).collect::<Vec<_>>());
+            if !read.eq(expected.iter().cloned()) {
+                continue;
+            }
+        } else if commands
+            .iter()
+            .any(|row| row.len() < MIN_CODE_LENGTH || row.len() > max_code_length)
+        {
+            // Otherwise a few stray arrows tend to hold the longest.
+            continue;
+        }
+        let separation = high - low;
+        let arrows = commands.iter().map(Vec::len).sum::<usize>();
+        let is_better = best.as_ref().is_none_or(|best| {
+            let best_arrows = best.commands.iter().map(Vec::len).sum::<usize>();
+            separation > best.separation || (separation == best.separation && arrows > best_arrows)
+        });
+        if is_better {
+            best = Some(Candidate {
+                scale,
+                template_size,
+                commands,
+                threshold: (low + high) / 2.0,
+                separation,
+            });
+        }
+    }
+    Ok(best)
+}
+
+fn ratio_rect(
+    (x, y, width, height): (f64, f64, f64, f64),
+    screen_width: usize,
+    screen_height: usize,
+) -> (usize, usize, usize, usize) {
+    let left = ((x * screen_width as f64) as usize).min(screen_width);
+    let top = ((y * screen_height as f64) as usize).min(screen_height);
+    let right = (((x + width) * screen_width as f64) as usize).min(screen_width);
+    let bottom = (((y + height) * screen_height as f64) as usize).min(screen_height);
+    (
+        left,
+        top,
+        right.saturating_sub(left),
+        bottom.saturating_sub(top),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_panel_fixture, Hd2mCvManager, PanelFixtureConfig, PanelFixtureTemplates};
+
+    #[test]
+    fn test_parse_codes() -> Result<()> {
+        assert_eq!(
+            parse_codes("drd, UL")?,
+            vec![
+                vec![Direction::Down, Direction::Right, Direction::Down],
+                vec![Direction::Up, Direction::Left],
+            ]
+        );
+        assert_eq!(format_code(&parse_codes("uldr")?[0]), "uldr");
+        assert!(parse_codes("dx").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_if_exists() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("hd2m.toml");
+        assert!(Hd2mCvCalibration::load_if_exists(&path)?.is_none());
+
+        fs::write(&path, "threshold = ")?;
+        assert!(Hd2mCvCalibration::load_if_exists(&path).is_err());
+        // A directory can't be read as the config either.
+        assert!(Hd2mCvCalibration::load_if_exists(dir.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_calibrate() -> Result<()> {
+        let expected = parse_codes("drd,ulr")?;
+        let templates = PanelFixtureTemplates::bundled()?;
+        let fixture = render_panel_fixture(
+            &templates,
+            &PanelFixtureConfig {
+                sequences: expected.clone(),
+                screen_size: (2560, 1440),
+                // Calibration has to find it.
+                hud_scale: Some(1.1),
+                ..Default::default()
+            },
+        )?;
+        let config = templates.manager_config();
+
+        let calibration = calibrate(
+            &config,
+            &fixture.image,
+            &CalibrationOptions {
+                expected: Some(expected),
+                scales: Some(vec![0.9, 1.1, 1.3]),
+                search_region: (0.0, 0.0, 0.12, 0.2),
+                ..Default::default()
+            },
+        )?;
+        // Edge matching peaks broadly around the actual scale.
+        assert!(
+            (calibration.hud_scale - 1.1).abs() <= 0.1,
+            "{calibration:?}"
+        );
+        assert_eq!(calibration.codes, vec!["drd", "ulr"]);
+        assert!(calibration.separation > 0.0, "{calibration:?}");
+
+        // The crop holds every arrow.
+        let (x, y, width, height) = calibration.crop_rect(2560, 1440);
+        for desc in fixture.descriptors.iter().flatten() {
+            assert!(desc.position.x >= x && desc.position.x < x + width);
+            assert!(desc.position.y >= y && desc.position.y < y + height);
+        }
+        // It follows the HUD on other screens, which an ultrawide one doesn't stretch.
+        assert_eq!(calibration.crop_rect(3440, 1440), (x, y, width, height));
+        let (x_1080p, y_1080p, width_1080p, height_1080p) = calibration.crop_rect(1920, 1080);
+        assert!(x_1080p.abs_diff(x * 3 / 4) <= 1 && y_1080p.abs_diff(y * 3 / 4) <= 1);
+        assert!(width_1080p.abs_diff(width * 3 / 4) <= 1);
+        assert!(height_1080p.abs_diff(height * 3 / 4) <= 1);
+
+        // The manager reads the codes back with the calibrated settings.
+        let manager = Hd2mCvManager::new(Hd2mCvManagerConfig {
+            hud_scale: Some(calibration.hud_scale),
+            search_options: Some(calibration.search_options()),
+            ..config
+        })?;
+        manager.use_screen_size(2560, 1440)?;
+        let crop = image::imageops::crop_imm(
+            &fixture.image,
+            x as u32,
+            y as u32,
+            width as u32,
+            height as u32,
+        )
+        .to_image();
+        let res = manager.run_match_rgba(&crop)?;
+        assert_eq!(
+            res.commands
+                .iter()
+                .map(|row| format_code(&row.iter().map(|desc| desc.direction).collect::<Vec<_>>()))
+                .collect::<Vec<_>>(),
+            calibration.codes
+        );
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("hd2m.toml");
+        calibration.save(&path)?;
+        assert_eq!(Hd2mCvCalibration::load(&path)?, calibration);
+        Ok(())
+    }
+}
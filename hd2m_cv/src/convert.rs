@@ -1,28 +1,23 @@
-use crate::TryIntoCv;
-use anyhow::Result;
+use crate::TryFromCv;
+use anyhow::{ensure, Result};
 use ndarray as nd;
 use opencv as cv;
 
-pub fn convert_mat_to_array2(mat: &cv::core::Mat) -> Result<nd::Array2<f32>> {
-    let arr3: nd::Array3<f32> = mat.try_into_cv()?;
-    let flatten = convert_array3_to_array2(&arr3);
-    Ok(flatten)
+/// Converts the single-channel result of `match_template` into its `(row, col)` response map,
+/// see [crate::extract_response_map].
+pub fn convert_response_mat_to_array2(mat: &cv::core::Mat) -> Result<nd::Array2<f32>> {
+    let view = nd::ArrayView3::<f32>::try_from_cv(mat)?;
+    Ok(crate::extract_response_map(view)?.to_owned())
 }
 
-pub fn convert_array3_to_array2<T>(array3: &nd::Array3<T>) -> nd::Array2<T>
-where
-    T: Clone,
-{
-    // Get the dimensions of the input array
-    let (depth, height, width) = array3.dim();
-
-    // Slice the array to merge the first element of the third dimension into the second dimension
-    let merged_array = array3.slice(nd::s![1.., .., ..]).reversed_axes();
-
-    // Reshape the merged array to a 2-dimensional array
-    let array2 = merged_array
-        .into_shape((height, (depth - 1) * width))
-        .expect("Failed to reshape merged array");
-
-    array2.to_owned()
+/// Converts the single-channel result of `match_template` into its `(col, row)` response map,
+/// leaving out its first row.
+///
+/// Kept with the layout it always had for existing callers, [convert_response_mat_to_array2]
+/// returns the whole map in `(row, col)` order.
+#[deprecated(note = "use `convert_response_mat_to_array2`, which returns whole `(row, col)` maps")]
+pub fn convert_mat_to_array2(mat: &cv::core::Mat) -> Result<nd::Array2<f32>> {
+    let map = convert_response_mat_to_array2(mat)?;
+    ensure!(map.nrows() > 0, "Expect a non-empty response map");
+    Ok(map.slice(nd::s![1.., ..]).t().to_owned())
 }
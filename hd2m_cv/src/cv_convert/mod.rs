@@ -1,8 +1,15 @@
 mod traits;
 pub use traits::*;
 
+#[cfg(feature = "opencv")]
 pub mod with_opencv;
+#[cfg(feature = "opencv")]
+pub use with_opencv::MatView;
+#[cfg(feature = "opencv")]
 pub mod with_opencv_image;
+#[cfg(feature = "opencv")]
+pub use with_opencv_image::{image_to_mat, mat_to_image, ChannelOrder, GrayPixel};
+#[cfg(feature = "opencv")]
 pub mod with_opencv_ndarray;
 
 #[cfg(feature = "windows-capture")]
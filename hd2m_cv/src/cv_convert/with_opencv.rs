@@ -43,6 +43,130 @@ mod element_type {
     }
 }
 
+pub use mat_view::*;
+mod mat_view {
+    use super::*;
+    use std::{marker::PhantomData, ops::Deref};
+
+    /// `Mat` header over elements it borrows rather than owns, so it can't outlive them.
+    ///
+    /// Only derefs to `&Mat`, so OpenCV reads the elements without ever writing to them.
+    #[derive(Debug)]
+    pub struct MatView<'a> {
+        mat: cv::Mat,
+        _data: PhantomData<&'a [u8]>,
+    }
+
+    impl<'a> MatView<'a> {
+        /// Makes a `rows` x `cols` header with `channels` channels over `data`, which must hold
+        /// exactly that many elements.
+        pub fn from_slice<T>(
+            data: &'a [T],
+            rows: usize,
+            cols: usize,
+            channels: usize,
+        ) -> anyhow::Result<Self>
+        where
+            T: OpenCvElement,
+        {
+            anyhow::ensure!(
+                data.len() == rows * cols * channels,
+                "Expect {} elements, but get {} elements",
+                rows * cols * channels,
+                data.len()
+            );
+            Self::from_slice_with_step(data, rows, cols, channels, cols * channels)
+        }
+
+        /// Same as [Self::from_slice], with the rows starting `step` elements apart rather than
+        /// right after each other, as in frames padded to an alignment.
+        pub fn from_slice_with_step<T>(
+            data: &'a [T],
+            rows: usize,
+            cols: usize,
+            channels: usize,
+            step: usize,
+        ) -> anyhow::Result<Self>
+        where
+            T: OpenCvElement,
+        {
+            let row_len = cols * channels;
+            anyhow::ensure!(
+                step >= row_len,
+                "Step of {step} elements is shorter than rows of {row_len} elements"
+            );
+            let len = rows.checked_sub(1).map_or(0, |last| last * step + row_len);
+            anyhow::ensure!(
+                data.len() >= len,
+                "Expect at least {len} elements, but get {} elements",
+                data.len()
+            );
+            let cv_type = cv::CV_MAKETYPE(T::DEPTH, channels as i32);
+            // INFO: OpenCV never frees data it didn't allocate, and `data` stays borrowed for as
+            // long as the header lives.
+            let mat = unsafe {
+                cv::Mat::new_rows_cols_with_data_unsafe(
+                    rows as i32,
+                    cols as i32,
+                    cv_type,
+                    data.as_ptr() as *mut _,
+                    step * std::mem::size_of::<T>(),
+                )?
+            };
+            Ok(Self {
+                mat,
+                _data: PhantomData,
+            })
+        }
+
+        /// View of the `rect` part of `mat`, sharing its elements.
+        ///
+        /// Unlike [cv::Mat::roi], the view derefs to `&Mat`. It isn't continuous unless `rect`
+        /// spans whole rows, since its rows stay as far apart as those of `mat`.
+        pub fn roi(mat: &'a cv::Mat, rect: cv::Rect) -> anyhow::Result<Self> {
+            anyhow::ensure!(
+                mat.dims() <= 2,
+                "Mat with more than 2 dimensions is not supported."
+            );
+            anyhow::ensure!(
+                rect.x >= 0
+                    && rect.y >= 0
+                    && rect.width > 0
+                    && rect.height > 0
+                    && rect.x + rect.width <= mat.cols()
+                    && rect.y + rect.height <= mat.rows(),
+                "ROI {rect:?} is empty or out of the {}x{} Mat",
+                mat.cols(),
+                mat.rows()
+            );
+            let step = mat.step1(0)? * mat.elem_size1()?;
+            // INFO: Same as above, with `mat` borrowed instead of a slice.
+            let view = unsafe {
+                let data = mat.ptr(rect.y)?.add(rect.x as usize * mat.elem_size()?);
+                cv::Mat::new_rows_cols_with_data_unsafe(
+                    rect.height,
+                    rect.width,
+                    mat.typ(),
+                    data as *mut _,
+                    step,
+                )?
+            };
+            Ok(Self {
+                mat: view,
+                _data: PhantomData,
+            })
+        }
+    }
+
+    impl Deref for MatView<'_> {
+        type Target = cv::Mat;
+
+        fn deref(&self) -> &cv::Mat {
+            &self.mat
+        }
+    }
+}
+
 pub(crate) use mat_ext::*;
 mod mat_ext {
     use anyhow::ensure;
@@ -52,14 +176,25 @@ mod mat_ext {
     pub trait MatExt {
         fn size_with_depth(&self) -> Vec<usize>;
 
+        /// Strides of each dimension of [Self::size_with_depth] in elements, which are larger than
+        /// the dimensions after them when the Mat isn't continuous, e.g. for ROIs.
+        fn strides_with_depth(&self) -> anyhow::Result<Vec<usize>>;
+
         fn numel(&self) -> usize {
             self.size_with_depth().iter().product()
         }
 
+        /// Elements of a continuous Mat.
         fn as_slice<T>(&self) -> anyhow::Result<&[T]>
         where
             T: OpenCvElement;
 
+        /// Elements from the first to the last one of the Mat, including the padding between its
+        /// rows when it isn't continuous. Meant to be read with [Self::strides_with_depth].
+        fn as_strided_slice<T>(&self) -> anyhow::Result<&[T]>
+        where
+            T: OpenCvElement;
+
         fn type_name(&self) -> String;
     }
 
@@ -71,6 +206,14 @@ mod mat_ext {
             size.chain([channels]).collect()
         }
 
+        fn strides_with_depth(&self) -> anyhow::Result<Vec<usize>> {
+            let mut strides = (0..self.dims())
+                .map(|i| Ok(self.step1(i)?))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            strides.push(1);
+            Ok(strides)
+        }
+
         fn as_slice<T>(&self) -> anyhow::Result<&[T]>
         where
             T: OpenCvElement,
@@ -85,6 +228,28 @@ mod mat_ext {
             Ok(slice)
         }
 
+        fn as_strided_slice<T>(&self) -> anyhow::Result<&[T]>
+        where
+            T: OpenCvElement,
+        {
+            ensure!(self.depth() == T::DEPTH, "element type mismatch");
+
+            let shape = self.size_with_depth();
+            if shape.contains(&0) {
+                return Ok(&[]);
+            }
+            let len = shape
+                .iter()
+                .zip(self.strides_with_depth()?)
+                .map(|(&dim, stride)| (dim - 1) * stride)
+                .sum::<usize>()
+                + 1;
+            let ptr = self.ptr(0)? as *const T;
+
+            let slice = unsafe { std::slice::from_raw_parts(ptr, len) };
+            Ok(slice)
+        }
+
         fn type_name(&self) -> String {
             cv::type_to_string(self.typ()).unwrap()
         }
@@ -1,12 +1,23 @@
-use super::with_opencv::{MatExt, OpenCvElement};
+use super::with_opencv::{MatExt, MatView, OpenCvElement};
 use super::{TryFromCv, TryIntoCv};
+use ndarray as nd;
 use opencv::{core as cv_core, prelude::*};
 use std::ops::Deref;
 
-// ImageBuffer -> Mat
+/// Pixels without color, which read the same in any channel order.
+///
+/// The `TryFromCv` conversions between images and Mats only take these, since nothing in a Mat
+/// records the order of its color channels. Color images go through [image_to_mat] and
+/// [mat_to_image], which take the order as a parameter.
+pub trait GrayPixel: image::Pixel {}
+
+impl<T: image::Primitive> GrayPixel for image::Luma<T> {}
+impl<T: image::Primitive> GrayPixel for image::LumaA<T> {}
+
+/// Same as the conversion of `&ImageBuffer`.
 impl<P, Container> TryFromCv<image::ImageBuffer<P, Container>> for cv_core::Mat
 where
-    P: image::Pixel,
+    P: GrayPixel,
     P::Subpixel: OpenCvElement,
     Container: Deref<Target = [P::Subpixel]> + Clone,
 {
@@ -16,32 +27,33 @@ where
     }
 }
 
-// &ImageBuffer -> Mat
+/// Copies the pixels of a gray image, see [image_to_mat] for color ones.
 impl<P, Container> TryFromCv<&image::ImageBuffer<P, Container>> for cv_core::Mat
 where
-    P: image::Pixel,
+    P: GrayPixel,
     P::Subpixel: OpenCvElement,
     Container: Deref<Target = [P::Subpixel]> + Clone,
 {
     type Error = anyhow::Error;
     fn try_from_cv(from: &image::ImageBuffer<P, Container>) -> Result<Self, Self::Error> {
-        let (width, height) = from.dimensions();
-        let cv_type = cv_core::CV_MAKETYPE(P::Subpixel::DEPTH, P::CHANNEL_COUNT as i32);
-        let mat = unsafe {
-            cv_core::Mat::new_rows_cols_with_data_unsafe(
-                height as i32,
-                width as i32,
-                cv_type,
-                from.as_ptr() as *mut _,
-                cv_core::Mat_AUTO_STEP,
-            )?
-            .try_clone()?
-        };
-        Ok(mat)
+        Ok(MatView::try_from_cv(from)?.try_clone()?)
+    }
+}
+
+/// Views the pixels of a gray image without copying them.
+impl<'a, P, Container> TryFromCv<&'a image::ImageBuffer<P, Container>> for MatView<'a>
+where
+    P: GrayPixel,
+    P::Subpixel: OpenCvElement,
+    Container: Deref<Target = [P::Subpixel]>,
+{
+    type Error = anyhow::Error;
+    fn try_from_cv(from: &'a image::ImageBuffer<P, Container>) -> Result<Self, Self::Error> {
+        view_image_channels(from)
     }
 }
 
-// &DynamicImage -> Mat
+/// Converts the variant held when it is gray, see [image_to_mat] for color ones.
 impl TryFromCv<&image::DynamicImage> for cv_core::Mat {
     type Error = anyhow::Error;
 
@@ -51,21 +63,18 @@ impl TryFromCv<&image::DynamicImage> for cv_core::Mat {
         let mat = match from {
             D::ImageLuma8(image) => image.try_into_cv()?,
             D::ImageLumaA8(image) => image.try_into_cv()?,
-            D::ImageRgb8(image) => image.try_into_cv()?,
-            D::ImageRgba8(image) => image.try_into_cv()?,
             D::ImageLuma16(image) => image.try_into_cv()?,
             D::ImageLumaA16(image) => image.try_into_cv()?,
-            D::ImageRgb16(image) => image.try_into_cv()?,
-            D::ImageRgba16(image) => image.try_into_cv()?,
-            D::ImageRgb32F(image) => image.try_into_cv()?,
-            D::ImageRgba32F(image) => image.try_into_cv()?,
-            image => anyhow::bail!("the color type {:?} is not supported", image.color()),
+            image => anyhow::bail!(
+                "the color type {:?} needs a channel order, convert it with `image_to_mat`",
+                image.color()
+            ),
         };
         Ok(mat)
     }
 }
 
-// DynamicImage -> Mat
+/// Same as the conversion of `&DynamicImage`.
 impl TryFromCv<image::DynamicImage> for cv_core::Mat {
     type Error = anyhow::Error;
     fn try_from_cv(from: image::DynamicImage) -> Result<Self, Self::Error> {
@@ -73,29 +82,24 @@ impl TryFromCv<image::DynamicImage> for cv_core::Mat {
     }
 }
 
-// &Mat -> DynamicImage
+/// Picks the gray variant from the depth and the channel count. Mats with 3 or 4 channels don't
+/// tell the order of their colors, see [mat_to_image] for those.
 impl TryFromCv<&cv_core::Mat> for image::DynamicImage {
     type Error = anyhow::Error;
 
     fn try_from_cv(from: &cv_core::Mat) -> Result<Self, Self::Error> {
-        let rows = from.rows();
-        let cols = from.cols();
-        anyhow::ensure!(
-            rows != -1 && cols != -1,
-            "Mat with more than 2 dimensions is not supported."
-        );
-
-        let depth = from.depth();
-        let n_channels = from.channels();
-        let width = cols as u32;
-        let height = rows as u32;
-
-        let image: image::DynamicImage = match (depth, n_channels) {
-            (cv_core::CV_8U, 1) => mat_to_image_buffer_gray::<u8>(from, width, height).into(),
-            (cv_core::CV_16U, 1) => mat_to_image_buffer_gray::<u16>(from, width, height).into(),
-            (cv_core::CV_8U, 3) => mat_to_image_buffer_rgb::<u8>(from, width, height).into(),
-            (cv_core::CV_16U, 3) => mat_to_image_buffer_rgb::<u16>(from, width, height).into(),
-            (cv_core::CV_32F, 3) => mat_to_image_buffer_rgb::<f32>(from, width, height).into(),
+        use image::{Luma, LumaA};
+        type I<P> = image::ImageBuffer<P, Vec<<P as image::Pixel>::Subpixel>>;
+
+        let image: image::DynamicImage = match (from.depth(), from.channels()) {
+            (cv_core::CV_8U, 1) => I::<Luma<u8>>::try_from_cv(from)?.into(),
+            (cv_core::CV_8U, 2) => I::<LumaA<u8>>::try_from_cv(from)?.into(),
+            (cv_core::CV_16U, 1) => I::<Luma<u16>>::try_from_cv(from)?.into(),
+            (cv_core::CV_16U, 2) => I::<LumaA<u16>>::try_from_cv(from)?.into(),
+            (_, 3 | 4) => anyhow::bail!(
+                "Mat of type {} needs a channel order, convert it with `mat_to_image`",
+                from.type_name()
+            ),
             _ => anyhow::bail!("Mat of type {} is not supported", from.type_name()),
         };
 
@@ -103,7 +107,7 @@ impl TryFromCv<&cv_core::Mat> for image::DynamicImage {
     }
 }
 
-// Mat -> DynamicImage
+/// Same as the conversion of `&Mat`.
 impl TryFromCv<cv_core::Mat> for image::DynamicImage {
     type Error = anyhow::Error;
 
@@ -112,43 +116,24 @@ impl TryFromCv<cv_core::Mat> for image::DynamicImage {
     }
 }
 
-// &Mat -> gray ImageBuffer
-impl<T> TryFromCv<&cv_core::Mat> for image::ImageBuffer<image::Luma<T>, Vec<T>>
+/// Copies the pixels into a gray image, see [mat_to_image] for color ones.
+impl<P> TryFromCv<&cv_core::Mat> for image::ImageBuffer<P, Vec<P::Subpixel>>
 where
-    image::Luma<T>: image::Pixel,
-    T: OpenCvElement + image::Primitive + DataType,
+    P: GrayPixel,
+    P::Subpixel: OpenCvElement,
 {
     type Error = anyhow::Error;
 
     fn try_from_cv(from: &cv_core::Mat) -> Result<Self, Self::Error> {
-        let rows = from.rows();
-        let cols = from.cols();
-        anyhow::ensure!(
-            rows != -1 && cols != -1,
-            "Mat with more than 2 dimensions is not supported."
-        );
-
-        let depth = from.depth();
-        let n_channels = from.channels();
-        let width = cols as u32;
-        let height = rows as u32;
-
-        anyhow::ensure!(
-            n_channels == 1,
-            "Unable to convert a multi-channel Mat to a gray image"
-        );
-        anyhow::ensure!(depth == T::DEPTH, "Subpixel type is not supported");
-
-        let image = mat_to_image_buffer_gray::<T>(from, width, height);
-        Ok(image)
+        copy_image_channels(from)
     }
 }
 
-// Mat -> gray ImageBuffer
-impl<T> TryFromCv<cv_core::Mat> for image::ImageBuffer<image::Luma<T>, Vec<T>>
+/// Same as the conversion of `&Mat`.
+impl<P> TryFromCv<cv_core::Mat> for image::ImageBuffer<P, Vec<P::Subpixel>>
 where
-    image::Luma<T>: image::Pixel,
-    T: OpenCvElement + image::Primitive + DataType,
+    P: GrayPixel,
+    P::Subpixel: OpenCvElement,
 {
     type Error = anyhow::Error;
 
@@ -157,15 +142,16 @@ where
     }
 }
 
-// &Mat -> rgb ImageBuffer
-impl<T> TryFromCv<&cv_core::Mat> for image::ImageBuffer<image::Rgb<T>, Vec<T>>
+/// Borrows the pixels of the Mat as a gray image. Fails on Mats that aren't continuous, such as
+/// ROIs, since an image has no room for the padding between their rows.
+impl<'a, P> TryFromCv<&'a cv_core::Mat> for image::ImageBuffer<P, &'a [P::Subpixel]>
 where
-    image::Rgb<T>: image::Pixel<Subpixel = T>,
-    T: OpenCvElement + image::Primitive + DataType,
+    P: GrayPixel,
+    P::Subpixel: OpenCvElement,
 {
     type Error = anyhow::Error;
 
-    fn try_from_cv(from: &cv_core::Mat) -> Result<Self, Self::Error> {
+    fn try_from_cv(from: &'a cv_core::Mat) -> Result<Self, Self::Error> {
         let rows = from.rows();
         let cols = from.cols();
         anyhow::ensure!(
@@ -173,121 +159,265 @@ where
             "Mat with more than 2 dimensions is not supported."
         );
 
-        let depth = from.depth();
         let n_channels = from.channels();
-        let width = cols as u32;
-        let height = rows as u32;
-
         anyhow::ensure!(
-            n_channels == 3,
-            "Expect 3 channels, but get {n_channels} channels"
+            n_channels == P::CHANNEL_COUNT as i32,
+            "Expect {} channels, but get {n_channels} channels",
+            P::CHANNEL_COUNT
         );
-        anyhow::ensure!(depth == T::DEPTH, "Subpixel type is not supported");
 
-        let image = mat_to_image_buffer_rgb::<T>(from, width, height);
-        Ok(image)
+        let slice = from.as_slice::<P::Subpixel>()?;
+        image::ImageBuffer::from_raw(cols as u32, rows as u32, slice)
+            .ok_or_else(|| anyhow::anyhow!("Mat data is shorter than its size"))
     }
 }
 
-// Mat -> rgb ImageBuffer
-impl<T> TryFromCv<cv_core::Mat> for image::ImageBuffer<image::Rgb<T>, Vec<T>>
-where
-    image::Rgb<T>: image::Pixel<Subpixel = T>,
-    T: OpenCvElement + image::Primitive + DataType,
-{
-    type Error = anyhow::Error;
-
-    fn try_from_cv(from: cv_core::Mat) -> Result<Self, Self::Error> {
-        (&from).try_into_cv()
-    }
+/// Order of the color channels of a `Mat`, which the `Mat` itself doesn't record.
+///
+/// Images and the captured frames are RGB(A), while most OpenCV functions reading or writing color
+/// expect BGR(A).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelOrder {
+    #[default]
+    Rgb,
+    Bgr,
 }
 
-// &Mat -> rgba u8 ImageBuffer
-impl TryFromCv<&cv_core::Mat> for image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
-    type Error = anyhow::Error;
-
-    fn try_from_cv(from: &cv_core::Mat) -> Result<Self, Self::Error> {
-        let rows = from.rows();
-        let cols = from.cols();
-        anyhow::ensure!(
-            rows != -1 && cols != -1,
-            "Mat with more than 2 dimensions is not supported."
-        );
-
-        let depth = from.depth();
-        let n_channels = from.channels();
-        let width = cols as u32;
-        let height = rows as u32;
-
-        anyhow::ensure!(
-            n_channels == 4,
-            "Expect 4 channels, but get {n_channels} channels"
-        );
-        anyhow::ensure!(depth == u8::DEPTH, "Subpixel type is not supported");
-
-        let image = mat_to_image_buffer_rgba_u8(from, width, height);
-        Ok(image)
+/// Converts an image into a `Mat` with its color channels in `order`.
+///
+/// Gray images, with or without alpha, are the same in both orders.
+pub fn image_to_mat<P, Container>(
+    image: &image::ImageBuffer<P, Container>,
+    order: ChannelOrder,
+) -> anyhow::Result<cv_core::Mat>
+where
+    P: image::Pixel,
+    P::Subpixel: OpenCvElement,
+    Container: Deref<Target = [P::Subpixel]>,
+{
+    let view = view_image_channels(image)?;
+    match swap_red_blue_code(order, view.channels()) {
+        Some(code) => {
+            let mut mat = cv_core::Mat::default();
+            opencv::imgproc::cvt_color(&*view, &mut mat, code, 0)?;
+            Ok(mat)
+        }
+        None => Ok(view.try_clone()?),
     }
 }
 
-// Mat -> rgba u8 ImageBuffer
-impl TryFromCv<cv_core::Mat> for image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
-    type Error = anyhow::Error;
-
-    fn try_from_cv(from: cv_core::Mat) -> Result<Self, Self::Error> {
-        (&from).try_into_cv()
+/// Converts a `Mat` with its color channels in `order` into an image.
+pub fn mat_to_image<P>(
+    mat: &cv_core::Mat,
+    order: ChannelOrder,
+) -> anyhow::Result<image::ImageBuffer<P, Vec<P::Subpixel>>>
+where
+    P: image::Pixel,
+    P::Subpixel: OpenCvElement,
+{
+    match swap_red_blue_code(order, mat.channels()) {
+        Some(code) => {
+            let mut swapped = cv_core::Mat::default();
+            opencv::imgproc::cvt_color(mat, &mut swapped, code, 0)?;
+            copy_image_channels(&swapped)
+        }
+        None => copy_image_channels(mat),
     }
 }
 
-// Utility functions
-fn mat_to_image_buffer_gray<T>(
-    mat: &cv_core::Mat,
-    width: u32,
-    height: u32,
-) -> image::ImageBuffer<image::Luma<T>, Vec<T>>
+// Views the channels of `image` in the order they are stored, which only the caller knows.
+pub(crate) fn view_image_channels<P, Container>(
+    image: &image::ImageBuffer<P, Container>,
+) -> anyhow::Result<MatView<'_>>
 where
-    T: image::Primitive + OpenCvElement + DataType,
+    P: image::Pixel,
+    P::Subpixel: OpenCvElement,
+    Container: Deref<Target = [P::Subpixel]>,
 {
-    type Image<T> = image::ImageBuffer<image::Luma<T>, Vec<T>>;
-
-    match mat.as_slice::<T>() {
-        Ok(slice) => Image::<T>::from_vec(width, height, slice.to_vec()).unwrap(),
-        Err(_) => Image::<T>::from_fn(width, height, |col, row| {
-            let pixel: T = *mat.at_2d(row as i32, col as i32).unwrap();
-            image::Luma([pixel])
-        }),
-    }
+    let (width, height) = image.dimensions();
+    let channels = P::CHANNEL_COUNT as usize;
+    // The container may be longer than the pixels.
+    let len = width as usize * height as usize * channels;
+    MatView::from_slice(
+        &image.as_raw().deref()[..len],
+        height as usize,
+        width as usize,
+        channels,
+    )
 }
 
-fn mat_to_image_buffer_rgb<T>(
+// Copies the channels of `mat` in the order they are stored into an image of `P`.
+fn copy_image_channels<P>(
     mat: &cv_core::Mat,
-    width: u32,
-    height: u32,
-) -> image::ImageBuffer<image::Rgb<T>, Vec<T>>
+) -> anyhow::Result<image::ImageBuffer<P, Vec<P::Subpixel>>>
 where
-    T: image::Primitive + OpenCvElement + DataType,
-    image::Rgb<T>: image::Pixel<Subpixel = T>,
+    P: image::Pixel,
+    P::Subpixel: OpenCvElement,
 {
-    type Image<T> = image::ImageBuffer<image::Rgb<T>, Vec<T>>;
-
-    match mat.as_slice::<T>() {
-        Ok(slice) => Image::<T>::from_vec(width, height, slice.to_vec()).unwrap(),
-        Err(_) => Image::<T>::from_fn(width, height, |col, row| {
-            let cv_core::Point3_::<T> { x, y, z } = *mat.at_2d(row as i32, col as i32).unwrap();
-            image::Rgb([x, y, z])
-        }),
+    // Read through the strides of the Mat, so that ROIs are only copied once.
+    let pixels = nd::ArrayView3::<P::Subpixel>::try_from_cv(mat)?;
+    let (height, width, n_channels) = pixels.dim();
+    anyhow::ensure!(
+        n_channels == P::CHANNEL_COUNT as usize,
+        "Expect {} channels, but get {n_channels} channels",
+        P::CHANNEL_COUNT
+    );
+
+    let data = match pixels.as_slice() {
+        Some(slice) => slice.to_vec(),
+        None => pixels.iter().copied().collect(),
+    };
+    image::ImageBuffer::from_raw(width as u32, height as u32, data)
+        .ok_or_else(|| anyhow::anyhow!("Mat data is shorter than its size"))
+}
+
+// Code of `cvt_color` turning RGB(A) into `order` and back, `None` when there is nothing to swap.
+fn swap_red_blue_code(order: ChannelOrder, channels: i32) -> Option<i32> {
+    match (order, channels) {
+        (ChannelOrder::Rgb, _) => None,
+        (ChannelOrder::Bgr, 3) => Some(opencv::imgproc::COLOR_RGB2BGR),
+        (ChannelOrder::Bgr, 4) => Some(opencv::imgproc::COLOR_RGBA2BGRA),
+        (ChannelOrder::Bgr, _) => None,
     }
 }
 
-fn mat_to_image_buffer_rgba_u8(
-    mat: &cv_core::Mat,
-    width: u32,
-    height: u32,
-) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
-    image::ImageBuffer::<image::Rgba<u8>, Vec<u8>>::from_raw(
-        width,
-        height,
-        mat.data_bytes().unwrap().to_owned(),
-    )
-    .unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_buffer_views() -> anyhow::Result<()> {
+        type GrayAlphaImage<C> = image::ImageBuffer<image::LumaA<u8>, C>;
+        let image = GrayAlphaImage::from_fn(8, 6, |x, y| image::LumaA([x as u8, y as u8]));
+        let mat = MatView::try_from_cv(&image)?;
+        assert_eq!((mat.rows(), mat.cols()), (6, 8));
+        assert_eq!(mat.typ(), cv_core::CV_8UC2);
+        assert_eq!(mat.data(), image.as_ptr());
+
+        // Viewed back without copying either.
+        let view = GrayAlphaImage::<&[u8]>::try_from_cv(&*mat)?;
+        assert_eq!(view.as_ptr(), image.as_ptr());
+        assert_eq!(view.get_pixel(3, 2), image.get_pixel(3, 2));
+        let pixels = nd::ArrayView3::<u8>::try_from_cv(&*mat)?;
+        assert_eq!(pixels.as_ptr(), image.as_ptr());
+        assert_eq!(pixels[[2, 3, 1]], 2);
+
+        assert!(image::ImageBuffer::<image::Luma<u8>, &[u8]>::try_from_cv(&*mat).is_err());
+        assert!(image::ImageBuffer::<image::LumaA<u16>, &[u16]>::try_from_cv(&*mat).is_err());
+
+        // The rows of a ROI are apart in memory, so it can't be viewed as a slice.
+        let roi = MatView::roi(&mat, cv_core::Rect::new(1, 1, 4, 4))?;
+        assert!(GrayAlphaImage::<&[u8]>::try_from_cv(&*roi).is_err());
+        Ok(())
+    }
+
+    fn gradient<P>() -> image::ImageBuffer<P, Vec<P::Subpixel>>
+    where
+        P: image::Pixel,
+        P::Subpixel: From<u8>,
+    {
+        let len = 5 * 3 * P::CHANNEL_COUNT as usize;
+        let data = (0..len).map(|i| P::Subpixel::from(i as u8)).collect();
+        image::ImageBuffer::from_raw(5, 3, data).unwrap()
+    }
+
+    fn assert_round_trip<P>(cv_type: i32) -> anyhow::Result<()>
+    where
+        P: image::Pixel + PartialEq + std::fmt::Debug,
+        P::Subpixel: OpenCvElement + From<u8>,
+    {
+        let image = gradient::<P>();
+        let mat = image_to_mat(&image, ChannelOrder::Rgb)?;
+        assert_eq!(mat.typ(), cv_type, "{}", mat.type_name());
+        assert_eq!(mat_to_image::<P>(&mat, ChannelOrder::Rgb)?, image);
+
+        // Same values through ndarray, with the channels as the last axis.
+        let array = nd::Array3::<P::Subpixel>::try_from_cv(&mat)?;
+        assert_eq!(array.dim(), (3, 5, P::CHANNEL_COUNT as usize));
+        let mat = cv_core::Mat::try_from_cv(&array)?;
+        assert_eq!(mat.typ(), cv_type);
+        assert_eq!(mat_to_image::<P>(&mat, ChannelOrder::Rgb)?, image);
+        Ok(())
+    }
+
+    fn assert_gray_round_trip<P>(cv_type: i32) -> anyhow::Result<()>
+    where
+        P: GrayPixel + PartialEq + std::fmt::Debug,
+        P::Subpixel: OpenCvElement + From<u8>,
+        image::DynamicImage: From<image::ImageBuffer<P, Vec<P::Subpixel>>>,
+    {
+        assert_round_trip::<P>(cv_type)?;
+
+        // Gray images also convert without a channel order.
+        let image = gradient::<P>();
+        let mat = cv_core::Mat::try_from_cv(&image)?;
+        assert_eq!(mat.typ(), cv_type, "{}", mat.type_name());
+        assert_eq!(image::ImageBuffer::<P, Vec<_>>::try_from_cv(&mat)?, image);
+
+        let dynamic = image::DynamicImage::from(image);
+        let mat = cv_core::Mat::try_from_cv(&dynamic)?;
+        assert_eq!(image::DynamicImage::try_from_cv(&mat)?, dynamic);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pixel_format_round_trip() -> anyhow::Result<()> {
+        use image::{Luma, LumaA, Rgb, Rgba};
+        assert_gray_round_trip::<Luma<u8>>(cv_core::CV_8UC1)?;
+        assert_gray_round_trip::<LumaA<u8>>(cv_core::CV_8UC2)?;
+        assert_round_trip::<Rgb<u8>>(cv_core::CV_8UC3)?;
+        assert_round_trip::<Rgba<u8>>(cv_core::CV_8UC4)?;
+        assert_gray_round_trip::<Luma<u16>>(cv_core::CV_16UC1)?;
+        assert_gray_round_trip::<LumaA<u16>>(cv_core::CV_16UC2)?;
+        assert_round_trip::<Rgb<u16>>(cv_core::CV_16UC3)?;
+        assert_round_trip::<Rgba<u16>>(cv_core::CV_16UC4)?;
+        assert_round_trip::<Rgb<f32>>(cv_core::CV_32FC3)?;
+        assert_round_trip::<Rgba<f32>>(cv_core::CV_32FC4)?;
+
+        // Depths that no `DynamicImage` variant holds.
+        let mat = cv_core::Mat::try_from_cv(&gradient::<Luma<f32>>())?;
+        assert!(image::DynamicImage::try_from_cv(&mat).is_err());
+        // Colors need an order.
+        let mat = image_to_mat(&gradient::<Rgba<u8>>(), ChannelOrder::Rgb)?;
+        assert!(image::DynamicImage::try_from_cv(&mat).is_err());
+        let dynamic = image::DynamicImage::from(gradient::<Rgb<u8>>());
+        assert!(cv_core::Mat::try_from_cv(&dynamic).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_order() -> anyhow::Result<()> {
+        let image = gradient::<image::Rgba<u8>>();
+        let rgba = image_to_mat(&image, ChannelOrder::Rgb)?;
+        let bgra = image_to_mat(&image, ChannelOrder::Bgr)?;
+        assert_eq!(rgba.as_slice::<u8>()?[..4], [0, 1, 2, 3]);
+        assert_eq!(bgra.as_slice::<u8>()?[..4], [2, 1, 0, 3]);
+        assert_eq!(
+            mat_to_image::<image::Rgba<u8>>(&rgba, ChannelOrder::Rgb)?,
+            image
+        );
+        assert_eq!(
+            mat_to_image::<image::Rgba<u8>>(&bgra, ChannelOrder::Bgr)?,
+            image
+        );
+        // Reading BGRA as RGBA swaps red and blue.
+        assert_ne!(
+            mat_to_image::<image::Rgba<u8>>(&bgra, ChannelOrder::Rgb)?,
+            image
+        );
+
+        let image = gradient::<image::Rgb<f32>>();
+        let bgr = image_to_mat(&image, ChannelOrder::Bgr)?;
+        assert_eq!(bgr.as_slice::<f32>()?[..3], [2.0, 1.0, 0.0]);
+        assert_eq!(
+            mat_to_image::<image::Rgb<f32>>(&bgr, ChannelOrder::Bgr)?,
+            image
+        );
+
+        // Nothing to swap without colors.
+        let image = gradient::<image::LumaA<u16>>();
+        let mat = image_to_mat(&image, ChannelOrder::Bgr)?;
+        assert_eq!(mat.as_slice::<u16>()?, &image.as_raw()[..]);
+        Ok(())
+    }
 }
@@ -1,8 +1,11 @@
 use super::with_opencv::{MatExt as _, OpenCvElement};
 use super::{TryFromCv, TryIntoCv};
-use ndarray as nd;
+use ndarray::{self as nd, ShapeBuilder};
 use opencv::{core as cv_core, prelude::*};
 
+// &Mat -> ArrayView borrowing the elements of the Mat, with the channels as the last axis
+//
+// Mats that aren't continuous, such as ROIs, get views with the strides of their rows.
 impl<'a, A, D> TryFromCv<&'a cv_core::Mat> for nd::ArrayView<'a, A, D>
 where
     A: OpenCvElement,
@@ -11,8 +14,10 @@ where
     type Error = anyhow::Error;
 
     fn try_from_cv(from: &'a cv_core::Mat) -> Result<Self, Self::Error> {
-        let src_shape = from.size_with_depth();
-        let array = nd::ArrayViewD::from_shape(src_shape, from.as_slice()?)?;
+        let src_shape = nd::IxDyn(&from.size_with_depth());
+        let src_strides = nd::IxDyn(&from.strides_with_depth()?);
+        let array =
+            nd::ArrayViewD::from_shape(src_shape.strides(src_strides), from.as_strided_slice()?)?;
         let array = array.into_dimensionality()?;
         Ok(array)
     }
@@ -26,11 +31,8 @@ where
     type Error = anyhow::Error;
 
     fn try_from_cv(from: &cv_core::Mat) -> Result<Self, Self::Error> {
-        let src_shape = from.size_with_depth();
-        let array = nd::ArrayViewD::from_shape(src_shape, from.as_slice()?)?;
-        let array = array.into_dimensionality()?;
-        let array = array.into_owned();
-        Ok(array)
+        let array = nd::ArrayView::<A, D>::try_from_cv(from)?;
+        Ok(array.to_owned())
     }
 }
 
@@ -83,3 +85,66 @@ where
         (&from).try_into_cv()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cv_convert::with_opencv::MatExt, MatView};
+
+    #[test]
+    fn test_strided_views() -> anyhow::Result<()> {
+        let image = image::RgbaImage::from_fn(8, 6, |x, y| {
+            image::Rgba([x as u8, y as u8, (x * y) as u8, 255])
+        });
+        let mat = crate::cv_convert::with_opencv_image::view_image_channels(&image)?;
+
+        // A ROI keeps the rows of the frame, 8 pixels apart.
+        let roi = MatView::roi(&mat, cv_core::Rect::new(2, 1, 4, 3))?;
+        assert!(!roi.is_continuous());
+        assert_eq!(roi.strides_with_depth()?, [32, 4, 1]);
+        assert!(roi.as_slice::<u8>().is_err());
+        assert_eq!(roi.as_strided_slice::<u8>()?.len(), 2 * 32 + 4 * 4);
+        let pixels = nd::ArrayView3::<u8>::try_from_cv(&*roi)?;
+        assert_eq!(pixels.dim(), (3, 4, 4));
+        assert_eq!(pixels.as_ptr(), image.get_pixel(2, 1).0.as_ptr());
+        for ((y, x, c), &v) in pixels.indexed_iter() {
+            assert_eq!(v, image.get_pixel(x as u32 + 2, y as u32 + 1)[c]);
+        }
+
+        // Each channel of the ROI on its own.
+        let green = nd::ArrayView3::<u8>::try_from_cv(&*roi)?.index_axis_move(nd::Axis(2), 1);
+        assert_eq!(green.row(2).to_vec(), [3, 3, 3, 3]);
+
+        // Owned conversions copy the ROI alone.
+        assert_eq!(nd::Array3::<u8>::try_from_cv(&*roi)?, pixels);
+        assert_eq!(
+            crate::mat_to_image::<image::Rgba<u8>>(&roi, crate::ChannelOrder::Rgb)?,
+            image::imageops::crop_imm(&image, 2, 1, 4, 3).to_image()
+        );
+        assert!(MatView::roi(&mat, cv_core::Rect::new(6, 0, 4, 3)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_padded_steps() -> anyhow::Result<()> {
+        // Rows of 3 RGB pixels, starting 5 pixels apart.
+        let data: Vec<f32> = (0..4 * 5 * 3).map(|i| i as f32).collect();
+        let mat = MatView::from_slice_with_step(&data, 4, 3, 3, 15)?;
+        assert_eq!(mat.typ(), cv_core::CV_32FC3);
+        assert!(!mat.is_continuous());
+        assert_eq!(mat.as_strided_slice::<f32>()?.len(), 3 * 15 + 9);
+
+        let pixels = nd::ArrayView3::<f32>::try_from_cv(&*mat)?;
+        assert_eq!(pixels.dim(), (4, 3, 3));
+        assert_eq!(pixels[[2, 1, 2]], (2 * 15 + 3 + 2) as f32);
+        let mat = cv_core::Mat::try_from_cv(&pixels)?;
+        assert!(mat.is_continuous());
+        assert_eq!(nd::ArrayView3::<f32>::try_from_cv(&mat)?, pixels);
+
+        // The last row doesn't need its padding.
+        assert!(MatView::from_slice_with_step(&data[..3 * 15 + 9], 4, 3, 3, 15).is_ok());
+        assert!(MatView::from_slice_with_step(&data[..3 * 15 + 8], 4, 3, 3, 15).is_err());
+        assert!(MatView::from_slice_with_step(&data, 4, 3, 3, 8).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{direction_color, Direction, IntermediaryDirection, TemplateMatcherResult};
+use anyhow::Result;
+use image::{Rgba, RgbaImage};
+use ndarray as nd;
+use std::{fs, io::Write, path::Path};
+
+/// Intermediate data of a single search, kept by [crate::Hd2mCvManager] in debug mode.
+#[derive(Debug, Clone)]
+pub struct Hd2mCvDebugSnapshot {
+    /// Row of the searched image the maps start at, non-zero for the bands of the pyramid search.
+    pub row_offset: usize,
+    /// Threshold the responses were cut at.
+    pub threshold: f32,
+    /// Response map of each template, in `(row, col)` layout.
+    pub results: Vec<TemplateMatcherResult>,
+    /// Best direction of each position above the threshold, in the `(x, y)` search layout.
+    pub direction_buffer: nd::Array2<IntermediaryDirection>,
+}
+
+impl Hd2mCvDebugSnapshot {
+    /// Writes the maps to `dir`, creating it if needed.
+    ///
+    /// Each response map is saved as `{prefix}_{label}.png` and `{prefix}_{label}.npy`. The
+    /// direction buffer is saved as `{prefix}_directions.png`, plus `{prefix}_directions.npy` with
+    /// the codes of [direction_code] and `{prefix}_confidence.npy`. All arrays are `(row, col)`.
+    pub fn export(&self, dir: impl AsRef<Path>, prefix: &str) -> Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        for res in self.results.iter() {
+            let response = res.response().view();
+            render_heatmap(&response).save(dir.join(format!("{prefix}_{}.png", res.label())))?;
+            write_npy(dir.join(format!("{prefix}_{}.npy", res.label())), &response)?;
+        }
+
+        let buffer = self.direction_buffer.t();
+        render_direction_buffer(&buffer).save(dir.join(format!("{prefix}_directions.png")))?;
+        write_npy(
+            dir.join(format!("{prefix}_directions.npy")),
+            &buffer
+                .map(|el| direction_code(el.map(|(direction, _)| direction)))
+                .view(),
+        )?;
+        write_npy(
+            dir.join(format!("{prefix}_confidence.npy")),
+            &buffer
+                .map(|el| el.map_or(0.0, |(_, confidence)| confidence))
+                .view(),
+        )?;
+        Ok(())
+    }
+}
+
+/// Returns the code of a direction in the exported direction buffers, `0` being no direction.
+pub fn direction_code(direction: Option<Direction>) -> u8 {
+    match direction {
+        None => 0,
+        Some(Direction::Up) => 1,
+        Some(Direction::Right) => 2,
+        Some(Direction::Down) => 3,
+        Some(Direction::Left) => 4,
+    }
+}
+
+/// Renders a response map in false colors, from dark blue for `0.0` to dark red for `1.0`.
+pub fn render_heatmap(response: &nd::ArrayView2<f32>) -> RgbaImage {
+    let (height, width) = response.dim();
+    RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+        heatmap_color(response[[y as usize, x as usize]])
+    })
+}
+
+/// Renders a `(row, col)` direction buffer with the direction colors, dimmed by their confidence.
+pub fn render_direction_buffer(buffer: &nd::ArrayView2<IntermediaryDirection>) -> RgbaImage {
+    let (height, width) = buffer.dim();
+    RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+        match buffer[[y as usize, x as usize]] {
+            None => Rgba([0, 0, 0, 255]),
+            Some((direction, confidence)) => {
+                let Rgba([r, g, b, _]) = direction_color(direction);
+                let level = confidence.clamp(0.25, 1.0);
+                Rgba([
+                    (r as f32 * level) as u8,
+                    (g as f32 * level) as u8,
+                    (b as f32 * level) as u8,
+                    255,
+                ])
+            }
+        }
+    })
+}
+
+// Piecewise linear approximation of the "jet" colormap.
+fn heatmap_color(value: f32) -> Rgba<u8> {
+    let v = if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 1.0)
+    };
+    let channel = |center: f32| ((1.5 - (4.0 * v - center).abs()).clamp(0.0, 1.0) * 255.0) as u8;
+    Rgba([channel(3.0), channel(2.0), channel(1.0), 255])
+}
+
+/// Element types that can be written to `.npy` files.
+pub trait NpyElement: Copy {
+    const DESCR: &'static str;
+
+    fn write_le(self, buf: &mut Vec<u8>);
+}
+
+impl NpyElement for f32 {
+    const DESCR: &'static str = "<f4";
+
+    fn write_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for u8 {
+    const DESCR: &'static str = "|u1";
+
+    fn write_le(self, buf: &mut Vec<u8>) {
+        buf.push(self);
+    }
+}
+
+/// Writes the array as a version 1.0 `.npy` file, readable with `numpy.load`.
+pub fn write_npy<T: NpyElement>(path: impl AsRef<Path>, array: &nd::ArrayView2<T>) -> Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(&encode_npy(array))?;
+    Ok(())
+}
+
+fn encode_npy<T: NpyElement>(array: &nd::ArrayView2<T>) -> Vec<u8> {
+    let (rows, cols) = array.dim();
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        T::DESCR,
+        rows,
+        cols
+    );
+    // The magic, version and header length take 10 bytes, and the whole preamble is padded to a
+    // multiple of 64 bytes ending with a newline.
+    let preamble_len = (10 + header.len() + 1).div_ceil(64) * 64;
+    header.push_str(&" ".repeat(preamble_len - 10 - header.len() - 1));
+    header.push('\n');
+
+    let mut buf = Vec::with_capacity(preamble_len + array.len() * std::mem::size_of::<T>());
+    buf.extend_from_slice(b"\x93NUMPY\x01\x00");
+    buf.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    buf.extend_from_slice(header.as_bytes());
+    // Iterates in logical order, so transposed views are written as they look.
+    for &el in array.iter() {
+        el.write_le(&mut buf);
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_npy() {
+        let array = nd::arr2(&[[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        let buf = encode_npy(&array.t());
+
+        assert_eq!(&buf[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([buf[8], buf[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&buf[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (3, 2), }"));
+        assert!(header.ends_with('\n'));
+
+        let values: Vec<f32> = buf[10 + header_len..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+    }
+
+    #[test]
+    fn test_render_heatmap() {
+        let response = nd::arr2(&[[0.0f32, 0.5, 1.0, f32::NAN]]);
+        let heatmap = render_heatmap(&response.view());
+        assert_eq!(heatmap.dimensions(), (4, 1));
+        assert_eq!(*heatmap.get_pixel(0, 0), Rgba([0, 0, 127, 255]));
+        assert_eq!(*heatmap.get_pixel(1, 0), Rgba([127, 255, 127, 255]));
+        assert_eq!(*heatmap.get_pixel(2, 0), Rgba([127, 0, 0, 255]));
+        assert_eq!(heatmap.get_pixel(3, 0), heatmap.get_pixel(0, 0));
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::{ensure, Result};
+use ndarray as nd;
+#[cfg(feature = "opencv")]
+use opencv as cv;
+
+// Pixels per side of the cells the frame is averaged over, a fraction of the smallest arrows so
+// that an arrow changing shape moves whole cells rather than a bit of a few large ones.
+const FINGERPRINT_CELL_SIZE: usize = 4;
+
+/// Cheap summary of a frame, telling whether it changed since another one without matching it.
+///
+/// The frame is downscaled to a grid of 4x4 pixel cells holding the mean luma of their pixels.
+/// An arrow changing color or direction moves the cells it covers by far more than capture noise
+/// does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameFingerprint {
+    frame_size: (usize, usize),
+    grid_size: (usize, usize),
+    cells: Vec<u8>,
+}
+
+impl FrameFingerprint {
+    pub fn from_rgba(image: &image::RgbaImage) -> Result<Self> {
+        let (width, height) = image.dimensions();
+        let pixels =
+            nd::ArrayView3::from_shape((height as usize, width as usize, 4), image.as_raw())?;
+        Self::from_pixels(&pixels)
+    }
+
+    #[cfg(feature = "opencv")]
+    pub fn from_mat(mat: &cv::core::Mat) -> Result<Self> {
+        use crate::TryFromCv;
+        Self::from_pixels(&nd::ArrayView3::<u8>::try_from_cv(mat)?)
+    }
+
+    /// Fingerprint of `(row, col, channel)` pixels in RGB(A) order.
+    pub fn from_pixels(pixels: &nd::ArrayView3<u8>) -> Result<Self> {
+        let (height, width, channels) = pixels.dim();
+        ensure!(
+            channels >= 3,
+            "Expect at least 3 channels, but get {channels} channels"
+        );
+        let grid_size = (
+            height.div_ceil(FINGERPRINT_CELL_SIZE),
+            width.div_ceil(FINGERPRINT_CELL_SIZE),
+        );
+        let mut sums = vec![(0u32, 0u32); grid_size.0 * grid_size.1];
+        for ((y, x), px) in pixels
+            .lanes(nd::Axis(2))
+            .into_iter()
+            .enumerate()
+            .map(|(i, px)| ((i / width, i % width), px))
+        {
+            // Integer luma with the same weights as the grayscale conversion.
+            let luma = (77 * px[0] as u32 + 150 * px[1] as u32 + 29 * px[2] as u32) >> 8;
+            let cell =
+                &mut sums[y / FINGERPRINT_CELL_SIZE * grid_size.1 + x / FINGERPRINT_CELL_SIZE];
+            cell.0 += luma;
+            cell.1 += 1;
+        }
+        Ok(Self {
+            frame_size: (width, height),
+            grid_size,
+            cells: sums
+                .into_iter()
+                .map(|(sum, count)| (sum / count.max(1)) as u8)
+                .collect(),
+        })
+    }
+
+    /// Returns the `(width, height)` of the frame.
+    pub fn frame_size(&self) -> (usize, usize) {
+        self.frame_size
+    }
+
+    /// Returns whether both frames have the same size and no cell differs by more than
+    /// `tolerance` luma levels.
+    pub fn matches(&self, other: &Self, tolerance: u8) -> bool {
+        self.frame_size == other.frame_size
+            && self.grid_size == other.grid_size
+            && self
+                .cells
+                .iter()
+                .zip(&other.cells)
+                .all(|(&a, &b)| a.abs_diff(b) <= tolerance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        render_panel_fixture, Direction::*, HudLayout, PanelFixtureConfig, PanelFixtureTemplates,
+    };
+
+    fn render(width: u32, height: u32, f: impl Fn(u32, u32) -> u8) -> image::RgbaImage {
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let v = f(x, y);
+            image::Rgba([v, v, v, 255])
+        })
+    }
+
+    #[test]
+    fn test_frame_fingerprint() -> Result<()> {
+        let frame = render(200, 100, |x, y| ((x * 7 + y * 13) % 256) as u8);
+        let fingerprint = FrameFingerprint::from_rgba(&frame)?;
+        assert_eq!(fingerprint.grid_size, (25, 50));
+        assert!(fingerprint.matches(&FrameFingerprint::from_rgba(&frame.clone())?, 0));
+
+        // Noise on a few pixels stays within the tolerance.
+        let mut noisy = frame.clone();
+        for x in (0..200).step_by(50) {
+            noisy.get_pixel_mut(x, 50).0[0] ^= 1;
+        }
+        assert!(fingerprint.matches(&FrameFingerprint::from_rgba(&noisy)?, 1));
+
+        // An arrow-sized patch changing brightness does not.
+        let mut changed = frame.clone();
+        for y in 40..61 {
+            for x in 100..120 {
+                let px = changed.get_pixel_mut(x, y);
+                px.0 = [px.0[0].saturating_add(60), px.0[1], px.0[2], 255];
+            }
+        }
+        assert!(!fingerprint.matches(&FrameFingerprint::from_rgba(&changed)?, 1));
+
+        // Frames of another size never match.
+        let cropped = image::imageops::crop_imm(&frame, 0, 0, 199, 100).to_image();
+        assert!(!fingerprint.matches(&FrameFingerprint::from_rgba(&cropped)?, u8::MAX));
+
+        // The cells at the edges average the pixels they have.
+        let tiny = FrameFingerprint::from_rgba(&render(5, 3, |x, _| x as u8 * 50))?;
+        assert_eq!(tiny.grid_size, (1, 2));
+        assert_eq!(tiny.cells, [75, 200]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_frame_fingerprint_mirrored_arrow() -> Result<()> {
+        let templates = PanelFixtureTemplates::bundled()?;
+        let (x, y, width, height) = HudLayout::new((2560, 1440), (2560, 1440)).panel_roi();
+        let fingerprint = |last| -> Result<FrameFingerprint> {
+            let fixture = render_panel_fixture(
+                &templates,
+                &PanelFixtureConfig {
+                    sequences: vec![vec![Down, Right, Down], vec![Down, Left, last]],
+                    screen_size: (2560, 1440),
+                    noise_amplitude: Some(4),
+                    ..Default::default()
+                },
+            )?;
+            let (x, y, width, height) = (x as u32, y as u32, width as u32, height as u32);
+            FrameFingerprint::from_rgba(
+                &image::imageops::crop_imm(&fixture.image, x, y, width, height).to_image(),
+            )
+        };
+        // Turned upside down, an arrow covers about as many pixels of the panel as before, which
+        // coarse cells average away. The cells of its tip and tail change completely.
+        assert!(!fingerprint(Up)?.matches(&fingerprint(Down)?, 32));
+        assert!(fingerprint(Up)?.matches(&fingerprint(Up)?, 0));
+        Ok(())
+    }
+}
@@ -0,0 +1,484 @@
+use crate::{
+    load_bundled_template,
+    scale::{
+        PANEL_ARROW_PITCH, PANEL_ICON_MARGIN, PANEL_ICON_SIZE, PANEL_NAME_HEIGHT, PANEL_ORIGIN,
+        PANEL_ROW_PITCH,
+    },
+    Direction, DirectionDescriptor, Hd2mCvManagerConfig, HudLayout, Point,
+    BUNDLED_TEMPLATE_SCREEN_SIZE,
+};
+use anyhow::{ensure, Result};
+use image::{imageops, Rgba, RgbaImage};
+
+const PANEL_COLOR: Rgba<u8> = Rgba([16, 18, 16, 255]);
+const ICON_COLOR: Rgba<u8> = Rgba([196, 182, 120, 255]);
+const NAME_COLOR: Rgba<u8> = Rgba([210, 210, 210, 255]);
+
+/// Arrow templates to compose the panels from, captured at `base_screen_size`.
+#[derive(Debug, Clone)]
+pub struct PanelFixtureTemplates {
+    pub template_up_image: RgbaImage,
+    pub template_down_image: RgbaImage,
+    pub template_right_image: RgbaImage,
+    pub template_left_image: RgbaImage,
+    pub base_screen_size: (usize, usize),
+}
+
+impl PanelFixtureTemplates {
+    /// The templates bundled with the crate, see [load_bundled_template].
+    pub fn bundled() -> Result<Self> {
+        Ok(Self {
+            template_up_image: load_bundled_template(Direction::Up)?,
+            template_down_image: load_bundled_template(Direction::Down)?,
+            template_right_image: load_bundled_template(Direction::Right)?,
+            template_left_image: load_bundled_template(Direction::Left)?,
+            base_screen_size: BUNDLED_TEMPLATE_SCREEN_SIZE,
+        })
+    }
+
+    /// Configuration of a manager matching these templates, with the defaults for the rest, to be
+    /// completed with the struct update syntax.
+    pub fn manager_config(&self) -> Hd2mCvManagerConfig {
+        Hd2mCvManagerConfig {
+            template_up_image: self.template_up_image.clone(),
+            template_down_image: self.template_down_image.clone(),
+            template_right_image: self.template_right_image.clone(),
+            template_left_image: self.template_left_image.clone(),
+            template_source_hashes: None,
+            base_screen_size: self.base_screen_size,
+            hud_scale: None,
+            search_options: None,
+            backend: None,
+            template_cache: None,
+            tone_map_options: None,
+        }
+    }
+
+    fn template_for(&self, direction: Direction) -> &RgbaImage {
+        match direction {
+            Direction::Up => &self.template_up_image,
+            Direction::Down => &self.template_down_image,
+            Direction::Right => &self.template_right_image,
+            Direction::Left => &self.template_left_image,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PanelBackground {
+    Solid(Rgba<u8>),
+    /// Blocky value noise around `base`, varying each channel by up to `amplitude`.
+    Noise {
+        base: Rgba<u8>,
+        amplitude: u8,
+        cell_size: u32,
+    },
+    /// Tiled over the whole frame.
+    Texture(RgbaImage),
+}
+
+impl Default for PanelBackground {
+    fn default() -> Self {
+        PanelBackground::Solid(Rgba([48, 56, 52, 255]))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PanelFixtureConfig {
+    /// One stratagem per entry, from the top of the panel.
+    pub sequences: Vec<Vec<Direction>>,
+    pub screen_size: (u32, u32),
+    /// In-game HUD scale, `1.0` by default, see [HudLayout].
+    pub hud_scale: Option<f64>,
+    pub background: Option<PanelBackground>,
+    /// Sigma of the gaussian blur applied to the whole frame.
+    pub blur_sigma: Option<f32>,
+    /// Maximum per-channel deviation of the uniform pixel noise.
+    pub noise_amplitude: Option<u8>,
+    /// Round-trips the frame through JPEG at this quality.
+    pub jpeg_quality: Option<u8>,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PanelFixture {
+    pub image: RgbaImage,
+    /// Top-left position of each arrow, grouped per non-empty sequence, in the same layout as
+    /// [crate::Hd2mCvManager::run_match_rgba] reports them.
+    pub descriptors: Vec<Vec<DirectionDescriptor>>,
+    /// Scale of the arrows relative to the templates.
+    pub scale: f64,
+}
+
+/// Composes a frame with a fake stratagem panel at its top-left corner, along with the ground
+/// truth of every arrow drawn.
+///
+/// The output only depends on the inputs, so the same `seed` always renders the same frame.
+pub fn render_panel_fixture(
+    templates: &PanelFixtureTemplates,
+    config: &PanelFixtureConfig,
+) -> Result<PanelFixture> {
+    let (width, height) = config.screen_size;
+    ensure!(width > 0 && height > 0, "Screen size must not be empty");
+    ensure!(
+        templates.base_screen_size.0 > 0 && templates.base_screen_size.1 > 0,
+        "Base screen size must not be empty"
+    );
+    let scale = HudLayout::new(
+        (width as usize, height as usize),
+        templates.base_screen_size,
+    )
+    .with_hud_scale(config.hud_scale.unwrap_or(1.0))
+    .scale();
+    ensure!(scale > 0.0, "Scale must be positive, but get {scale}");
+
+    let mut rng = SplitMix64::new(config.seed);
+    let mut frame = render_background(
+        &config.background.clone().unwrap_or_default(),
+        config.screen_size,
+        &mut rng,
+    );
+
+    let longest_sequence = config.sequences.iter().map(Vec::len).max().unwrap_or(0);
+    let panel_left = ((PANEL_ORIGIN.0 - PANEL_ICON_SIZE - PANEL_ICON_MARGIN * 2.0) * scale) as i64;
+    let panel_top = ((PANEL_ORIGIN.1 - PANEL_NAME_HEIGHT * 2.0) * scale) as i64;
+    let panel_right = ((PANEL_ORIGIN.0
+        + PANEL_ARROW_PITCH * longest_sequence as f64
+        + PANEL_ICON_MARGIN)
+        * scale) as i64;
+    let panel_bottom =
+        ((PANEL_ORIGIN.1 + PANEL_ROW_PITCH * config.sequences.len() as f64) * scale) as i64;
+    ensure!(
+        panel_right <= width as i64 && panel_bottom <= height as i64,
+        "Panel of {} rows does not fit in {}x{}",
+        config.sequences.len(),
+        width,
+        height
+    );
+    fill_rect(
+        &mut frame,
+        (panel_left, panel_top),
+        (panel_right - panel_left, panel_bottom - panel_top),
+        PANEL_COLOR,
+    );
+
+    let scaled_templates: Vec<(Direction, RgbaImage)> = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Right,
+        Direction::Left,
+    ]
+    .into_iter()
+    .map(|direction| {
+        let template = templates.template_for(direction);
+        let scaled = imageops::resize(
+            template,
+            ((template.width() as f64 * scale).round() as u32).max(1),
+            ((template.height() as f64 * scale).round() as u32).max(1),
+            imageops::FilterType::Triangle,
+        );
+        (direction, scaled)
+    })
+    .collect();
+
+    let mut descriptors = Vec::new();
+    for (row, sequence) in config.sequences.iter().enumerate() {
+        let row_top = PANEL_ORIGIN.1 + PANEL_ROW_PITCH * row as f64;
+        fill_rect(
+            &mut frame,
+            (
+                ((PANEL_ORIGIN.0 - PANEL_ICON_SIZE - PANEL_ICON_MARGIN) * scale) as i64,
+                ((row_top - PANEL_NAME_HEIGHT * 1.5) * scale) as i64,
+            ),
+            (
+                (PANEL_ICON_SIZE * scale) as i64,
+                (PANEL_ICON_SIZE * scale) as i64,
+            ),
+            ICON_COLOR,
+        );
+        // Stands in for the name of the stratagem above the arrows.
+        let name_width = PANEL_ARROW_PITCH * (2 + rng.next_u64() % 4) as f64;
+        fill_rect(
+            &mut frame,
+            (
+                (PANEL_ORIGIN.0 * scale) as i64,
+                ((row_top - PANEL_NAME_HEIGHT * 1.5) * scale) as i64,
+            ),
+            (
+                (name_width * scale) as i64,
+                (PANEL_NAME_HEIGHT * 0.75 * scale) as i64,
+            ),
+            NAME_COLOR,
+        );
+
+        let mut row_descriptors = Vec::new();
+        for (i, &direction) in sequence.iter().enumerate() {
+            let position = Point::new(
+                ((PANEL_ORIGIN.0 + PANEL_ARROW_PITCH * i as f64) * scale).round() as usize,
+                (row_top * scale).round() as usize,
+            );
+            let (_, template) = scaled_templates
+                .iter()
+                .find(|(d, _)| *d == direction)
+                .unwrap();
+            imageops::overlay(&mut frame, template, position.x as i64, position.y as i64);
+            row_descriptors.push(DirectionDescriptor {
+                direction,
+                position,
+                confidence: 1.0,
+            });
+        }
+        if !row_descriptors.is_empty() {
+            descriptors.push(row_descriptors);
+        }
+    }
+
+    if let Some(sigma) = config.blur_sigma.filter(|&sigma| sigma > 0.0) {
+        frame = imageops::blur(&frame, sigma);
+    }
+    if let Some(amplitude) = config.noise_amplitude.filter(|&amplitude| amplitude > 0) {
+        for px in frame.pixels_mut() {
+            for channel in px.0.iter_mut().take(3) {
+                let delta = (rng.next_u64() % (amplitude as u64 * 2 + 1)) as i16 - amplitude as i16;
+                *channel = (*channel as i16 + delta).clamp(0, 255) as u8;
+            }
+        }
+    }
+    if let Some(quality) = config.jpeg_quality {
+        frame = round_trip_jpeg(&frame, quality)?;
+    }
+
+    Ok(PanelFixture {
+        image: frame,
+        descriptors,
+        scale,
+    })
+}
+
+fn render_background(
+    background: &PanelBackground,
+    (width, height): (u32, u32),
+    rng: &mut SplitMix64,
+) -> RgbaImage {
+    match background {
+        PanelBackground::Solid(color) => RgbaImage::from_pixel(width, height, *color),
+        PanelBackground::Noise {
+            base,
+            amplitude,
+            cell_size,
+        } => {
+            let cell_size = (*cell_size).max(1);
+            let cells_x = width.div_ceil(cell_size);
+            let cells_y = height.div_ceil(cell_size);
+            let cells: Vec<Rgba<u8>> = (0..cells_x * cells_y)
+                .map(|_| {
+                    let mut px = *base;
+                    for channel in px.0.iter_mut().take(3) {
+                        let delta = (rng.next_u64() % (*amplitude as u64 * 2 + 1)) as i16
+                            - *amplitude as i16;
+                        *channel = (*channel as i16 + delta).clamp(0, 255) as u8;
+                    }
+                    px
+                })
+                .collect();
+            RgbaImage::from_fn(width, height, |x, y| {
+                cells[((y / cell_size) * cells_x + x / cell_size) as usize]
+            })
+        }
+        PanelBackground::Texture(texture) if texture.width() > 0 && texture.height() > 0 => {
+            RgbaImage::from_fn(width, height, |x, y| {
+                *texture.get_pixel(x % texture.width(), y % texture.height())
+            })
+        }
+        PanelBackground::Texture(_) => RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255])),
+    }
+}
+
+fn fill_rect(
+    frame: &mut RgbaImage,
+    (x, y): (i64, i64),
+    (width, height): (i64, i64),
+    color: Rgba<u8>,
+) {
+    let x_range = x.max(0)..(x + width).min(frame.width() as i64);
+    let y_range = y.max(0)..(y + height).min(frame.height() as i64);
+    for py in y_range {
+        for px in x_range.clone() {
+            frame.put_pixel(px as u32, py as u32, color);
+        }
+    }
+}
+
+fn round_trip_jpeg(frame: &RgbaImage, quality: u8) -> Result<RgbaImage> {
+    let rgb = image::DynamicImage::ImageRgba8(frame.clone()).to_rgb8();
+    let mut buf = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf, quality.clamp(1, 100))
+        .encode_image(&rgb)?;
+    Ok(image::load_from_memory_with_format(&buf, image::ImageFormat::Jpeg)?.to_rgba8())
+}
+
+// Small, dependency-free PRNG so the fixtures stay stable across crate updates.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchDescriptor, TemplateMatcher, TemplateMatcherResult};
+
+    fn sequences() -> Vec<Vec<Direction>> {
+        use Direction::*;
+        vec![
+            vec![Down, Down, Up, Right],
+            vec![Up, Up],
+            vec![],
+            vec![Right, Left, Up, Down, Right],
+        ]
+    }
+
+    #[test]
+    fn test_render_panel_fixture_ground_truth() -> anyhow::Result<()> {
+        let templates = PanelFixtureTemplates::bundled()?;
+        let fixture = render_panel_fixture(
+            &templates,
+            &PanelFixtureConfig {
+                sequences: sequences(),
+                screen_size: (2560, 1440),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(fixture.scale, 1.0);
+        assert_eq!(fixture.descriptors.len(), 3);
+        assert_eq!(fixture.descriptors[2].len(), 5);
+
+        // Without scaling and post-processing, the templates are pasted as-is onto the panel.
+        let desc = fixture.descriptors[0][2];
+        assert_eq!(desc.direction, Direction::Up);
+        let template = &templates.template_up_image;
+        let pasted = imageops::crop_imm(
+            &fixture.image,
+            desc.position.x as u32,
+            desc.position.y as u32,
+            template.width(),
+            template.height(),
+        )
+        .to_image();
+        let mut expected = RgbaImage::from_pixel(template.width(), template.height(), PANEL_COLOR);
+        imageops::overlay(&mut expected, template, 0, 0);
+        assert_eq!(pasted, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_panel_fixture_is_deterministic() -> anyhow::Result<()> {
+        let templates = PanelFixtureTemplates::bundled()?;
+        let config = PanelFixtureConfig {
+            sequences: sequences(),
+            screen_size: (1280, 720),
+            background: Some(PanelBackground::Noise {
+                base: Rgba([60, 60, 60, 255]),
+                amplitude: 30,
+                cell_size: 8,
+            }),
+            noise_amplitude: Some(6),
+            jpeg_quality: Some(80),
+            seed: 42,
+            ..Default::default()
+        };
+        let a = render_panel_fixture(&templates, &config)?;
+        let b = render_panel_fixture(&templates, &config)?;
+        assert_eq!(a.image, b.image);
+
+        let c = render_panel_fixture(&templates, &PanelFixtureConfig { seed: 43, ..config })?;
+        assert_ne!(a.image, c.image);
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_panel_fixture_rejects_overflow() {
+        let result = render_panel_fixture(
+            &PanelFixtureTemplates::bundled().unwrap(),
+            &PanelFixtureConfig {
+                sequences: vec![vec![Direction::Up; 4]; 20],
+                screen_size: (1920, 1080),
+                ..Default::default()
+            },
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_fixture_arrows_match_their_own_template() -> anyhow::Result<()> {
+        let templates = PanelFixtureTemplates::bundled()?;
+        let fixture = render_panel_fixture(
+            &templates,
+            &PanelFixtureConfig {
+                sequences: sequences(),
+                screen_size: (1920, 1080),
+                blur_sigma: Some(0.5),
+                noise_amplitude: Some(4),
+                jpeg_quality: Some(90),
+                seed: 7,
+                ..Default::default()
+            },
+        )?;
+
+        let descriptors: Vec<MatchDescriptor> = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Right,
+            Direction::Left,
+        ]
+        .into_iter()
+        .map(|direction| {
+            MatchDescriptor::new(
+                direction.label().to_owned(),
+                templates.template_for(direction).clone(),
+            )
+        })
+        .collect();
+        let matcher = TemplateMatcher::new(&descriptors)?.with_resized_scale(fixture.scale)?;
+        let crop = imageops::crop_imm(&fixture.image, 0, 0, 314, 502).to_image();
+        let results = matcher.match_templates(&crop)?;
+
+        // Best response of each template around the ground truth, which tolerates the rounding of
+        // the resized templates.
+        let best_around = |res: &TemplateMatcherResult, position: Point| -> f32 {
+            let response = res.response();
+            let rows = position.y.saturating_sub(1)..(position.y + 2).min(response.nrows());
+            let cols = position.x.saturating_sub(1)..(position.x + 2).min(response.ncols());
+            response
+                .slice(ndarray::s![rows, cols])
+                .fold(f32::MIN, |acc, &v| acc.max(v))
+        };
+        for desc in fixture.descriptors.iter().flatten() {
+            let own = results
+                .iter()
+                .find(|res| res.label() == desc.direction.label())
+                .unwrap();
+            let own_score = best_around(own, desc.position);
+            for other in results.iter().filter(|res| res.label() != own.label()) {
+                assert!(
+                    own_score > best_around(other, desc.position),
+                    "{:?} matches {} better",
+                    desc,
+                    other.label()
+                );
+            }
+        }
+        Ok(())
+    }
+}
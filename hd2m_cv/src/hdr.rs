@@ -0,0 +1,593 @@
+use anyhow::{ensure, Result};
+use half::f16;
+use ndarray as nd;
+#[cfg(feature = "opencv")]
+use opencv as cv;
+
+// Luminance of `1.0` in scRGB, which is also the SDR white.
+const SCRGB_WHITE_NITS: f32 = 80.0;
+
+// SMPTE ST 2084 (PQ) constants.
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+const PQ_MAX_NITS: f32 = 10000.0;
+
+// Converts linear BT.2020 colors into linear BT.709 ones, the primaries of scRGB and sRGB.
+const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [1.660_491, -0.587_641, -0.072_850],
+    [-0.124_550, 1.1329, -0.008_349],
+    [-0.018_151, -0.100_579, 1.118_73],
+];
+
+// Luminance weights of linear BT.709 colors.
+const LUMINANCE_WEIGHTS: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+// The automatic exposure bins the log2 luminances of `HISTOGRAM_STOPS` in steps of
+// `1 / HISTOGRAM_BINS_PER_STOP` stops.
+const HISTOGRAM_STOPS: (f32, f32) = (-16.0, 8.0);
+const HISTOGRAM_BINS_PER_STOP: f32 = 32.0;
+
+const DEFAULT_WHITE_PERCENTILE: f32 = 0.99;
+
+// Bounds of the automatic exposure, so that a black panel isn't amplified into noise.
+const EXPOSURE_RANGE: (f32, f32) = (1.0 / 64.0, 4.0);
+
+/// Pixel format of an HDR frame, as captured by Windows Graphics Capture from an HDR display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrFormat {
+    /// `R16G16B16A16_FLOAT`, linear scRGB where `1.0` is the SDR white of 80 nits. Values above
+    /// `1.0` are highlights, negative ones are outside of the BT.709 gamut.
+    Rgba16F,
+    /// `R10G10B10A2_UNORM` in little-endian `u32`s with red in the lowest bits, PQ-encoded with
+    /// BT.2020 primaries as in HDR10.
+    ///
+    /// Only read through [HdrFrame::new]: windows-capture has no 10-bit color format, so the
+    /// captured `Mat`s are always [HdrFormat::Rgba16F].
+    Rgb10A2,
+}
+
+impl HdrFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            HdrFormat::Rgba16F => 8,
+            HdrFormat::Rgb10A2 => 4,
+        }
+    }
+}
+
+/// HDR frame borrowed from a capture buffer, see [tone_map_hdr_frame].
+#[derive(Debug, Clone, Copy)]
+pub struct HdrFrame<'a> {
+    format: HdrFormat,
+    width: usize,
+    height: usize,
+    // Bytes from a row to the next, larger than a row once cropped.
+    stride: usize,
+    data: &'a [u8],
+}
+
+impl<'a> HdrFrame<'a> {
+    /// Wraps `data`, holding the rows of the frame without padding.
+    pub fn new(format: HdrFormat, width: usize, height: usize, data: &'a [u8]) -> Result<Self> {
+        let len = width * height * format.bytes_per_pixel();
+        ensure!(
+            data.len() == len,
+            "Expect {len} bytes for a {width}x{height} frame, but get {} bytes",
+            data.len()
+        );
+        Ok(Self {
+            format,
+            width,
+            height,
+            stride: width * format.bytes_per_pixel(),
+            data,
+        })
+    }
+
+    /// Returns the `(x, y, width, height)` part of the frame, without copying it.
+    pub fn crop(&self, roi: (usize, usize, usize, usize)) -> Result<Self> {
+        self.check_roi(roi)?;
+        let (x, y, width, height) = roi;
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let start = y * self.stride + x * bytes_per_pixel;
+        let end = (y + height - 1) * self.stride + (x + width) * bytes_per_pixel;
+        Ok(Self {
+            width,
+            height,
+            data: &self.data[start..end],
+            ..*self
+        })
+    }
+
+    pub fn format(&self) -> HdrFormat {
+        self.format
+    }
+
+    /// Returns the `(width, height)` of the frame.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Returns the linear scRGB color of the pixel at `(x, y)` clamped to the BT.709 gamut, and its
+    /// alpha.
+    pub fn pixel(&self, x: usize, y: usize) -> ([f32; 3], f32) {
+        let i = y * self.stride + x * self.format.bytes_per_pixel();
+        let (rgb, alpha) = match self.format {
+            HdrFormat::Rgba16F => {
+                let channel =
+                    |c: usize| f16::from_le_bytes([self.data[i + 2 * c], self.data[i + 2 * c + 1]]);
+                (
+                    [channel(0), channel(1), channel(2)].map(f16::to_f32),
+                    channel(3).to_f32(),
+                )
+            }
+            HdrFormat::Rgb10A2 => {
+                let px = u32::from_le_bytes([
+                    self.data[i],
+                    self.data[i + 1],
+                    self.data[i + 2],
+                    self.data[i + 3],
+                ]);
+                let channel = |c: usize| {
+                    pq_to_nits(((px >> (10 * c)) & 0x3ff) as f32 / 1023.0) / SCRGB_WHITE_NITS
+                };
+                let bt2020 = [channel(0), channel(1), channel(2)];
+                (
+                    BT2020_TO_BT709.map(|row| (0..3).map(|c| row[c] * bt2020[c]).sum()),
+                    (px >> 30) as f32 / 3.0,
+                )
+            }
+        };
+        // INFO: `max` also turns NaN into zero.
+        (rgb.map(|v| v.max(0.0)), alpha)
+    }
+
+    fn check_roi(&self, roi: (usize, usize, usize, usize)) -> Result<()> {
+        let (x, y, width, height) = roi;
+        ensure!(
+            width > 0 && height > 0 && x + width <= self.width && y + height <= self.height,
+            "Expect a non-empty ROI within the {}x{} frame, but get {roi:?}",
+            self.width,
+            self.height
+        );
+        Ok(())
+    }
+}
+
+#[cfg(feature = "opencv")]
+impl<'a> HdrFrame<'a> {
+    /// Wraps a continuous `CV_16FC4` `Mat` of [HdrFormat::Rgba16F] pixels, the format of the HDR
+    /// captures.
+    pub fn from_mat(mat: &'a cv::core::Mat) -> Result<Self> {
+        use cv::prelude::*;
+
+        ensure!(
+            mat.typ() == cv::core::CV_16FC4,
+            "Expect a CV_16FC4 frame, but get type {}",
+            mat.typ()
+        );
+        ensure!(mat.is_continuous(), "Expect a continuous frame");
+        Self::new(
+            HdrFormat::Rgba16F,
+            mat.cols() as usize,
+            mat.rows() as usize,
+            mat.data_bytes()?,
+        )
+    }
+}
+
+/// Curve bringing the exposed linear values into the SDR range.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ToneCurve {
+    /// Clips the values above the SDR white and leaves the others as they are, which keeps the HUD
+    /// as it looks in SDR when the exposure puts its white at `1.0`.
+    #[default]
+    Clip,
+    /// Extended Reinhard, compressing the highlights so that `white` reaches the SDR white.
+    Reinhard { white: f32 },
+    /// Narkowicz's fit of the ACES filmic curve, with more contrast in the midtones.
+    Aces,
+}
+
+impl ToneCurve {
+    /// Maps a linear value to `0.0..=1.0`.
+    pub fn apply(self, value: f32) -> f32 {
+        let v = value.max(0.0);
+        let mapped = match self {
+            ToneCurve::Clip => v,
+            ToneCurve::Reinhard { white } => v * (1.0 + v / (white * white)) / (1.0 + v),
+            ToneCurve::Aces => v * (2.51 * v + 0.03) / (v * (2.43 * v + 0.59) + 0.14),
+        };
+        mapped.clamp(0.0, 1.0)
+    }
+}
+
+/// Parses `clip`, `aces` or `reinhard:<white>`, e.g. from an environment variable.
+impl std::str::FromStr for ToneCurve {
+    type Err = anyhow::Error;
+
+    fn from_str(curve: &str) -> Result<Self> {
+        match curve.trim().to_lowercase().as_str() {
+            "clip" => Ok(ToneCurve::Clip),
+            "aces" => Ok(ToneCurve::Aces),
+            curve => match curve.strip_prefix("reinhard:") {
+                Some(white) => Ok(ToneCurve::Reinhard {
+                    white: white.parse()?,
+                }),
+                None => anyhow::bail!(
+                    "Unknown tone curve `{curve}`, expect `clip`, `aces` or `reinhard:<white>`"
+                ),
+            },
+        }
+    }
+}
+
+/// Options of [tone_map_hdr_frame], also used by the manager for HDR frames, see
+/// [crate::Hd2mCvManagerConfig::tone_map_options].
+#[derive(Debug, Clone, Default)]
+pub struct ToneMapOptions {
+    pub curve: Option<ToneCurve>,
+    /// Factor the linear values are multiplied by before the curve. Picked by [auto_exposure]
+    /// when not set.
+    pub exposure: Option<f32>,
+    /// Part of the frame the automatic exposure is measured over as `(x, y, width, height)`, the
+    /// whole frame by default. The panel ROI works best, since games draw their HUD at a fixed
+    /// paper white regardless of the exposure of the scene behind it.
+    pub roi: Option<(usize, usize, usize, usize)>,
+    /// Fraction of the pixels of `roi` the automatic exposure keeps below the SDR white, `0.99` by
+    /// default.
+    pub white_percentile: Option<f32>,
+}
+
+/// Returns the exposure bringing the `white_percentile` luminance of the `(x, y, width, height)`
+/// part of `frame` to the SDR white.
+///
+/// Luminances are binned in 1/32 stops, so the percentile ends up within 2.2% below `1.0`.
+pub fn auto_exposure(
+    frame: &HdrFrame,
+    roi: (usize, usize, usize, usize),
+    white_percentile: f32,
+) -> Result<f32> {
+    frame.check_roi(roi)?;
+    let (x, y, width, height) = roi;
+    ensure!(
+        (0.0..=1.0).contains(&white_percentile),
+        "Expect a percentile within 0.0..=1.0, but get {white_percentile}"
+    );
+
+    let (min_stop, max_stop) = HISTOGRAM_STOPS;
+    let bins = ((max_stop - min_stop) * HISTOGRAM_BINS_PER_STOP) as usize;
+    let mut histogram = vec![0usize; bins];
+    for py in y..y + height {
+        for px in x..x + width {
+            let (rgb, _) = frame.pixel(px, py);
+            let luminance: f32 = (0..3).map(|c| LUMINANCE_WEIGHTS[c] * rgb[c]).sum();
+            // INFO: Black pixels land in the first bin, since `log2(0.0)` is negative infinity.
+            let bin = ((luminance.log2() - min_stop) * HISTOGRAM_BINS_PER_STOP)
+                .clamp(0.0, (bins - 1) as f32);
+            histogram[bin as usize] += 1;
+        }
+    }
+
+    let target = ((white_percentile * (width * height) as f32).ceil() as usize).max(1);
+    let mut count = 0;
+    let bin = histogram
+        .iter()
+        .position(|&n| {
+            count += n;
+            count >= target
+        })
+        .unwrap_or(bins - 1);
+    // The upper edge of the bin, so that the percentile is never clipped.
+    let white = (min_stop + (bin + 1) as f32 / HISTOGRAM_BINS_PER_STOP).exp2();
+    Ok((1.0 / white).clamp(EXPOSURE_RANGE.0, EXPOSURE_RANGE.1))
+}
+
+/// Converts `frame` into an sRGB RGBA8 image, the format of the SDR captures the templates were
+/// made from.
+pub fn tone_map_hdr_frame(frame: &HdrFrame, options: &ToneMapOptions) -> Result<image::RgbaImage> {
+    let curve = options.curve.unwrap_or_default();
+    if let ToneCurve::Reinhard { white } = curve {
+        ensure!(white > 0.0, "Expect a positive white, but get {white}");
+    }
+    let exposure = match options.exposure {
+        Some(exposure) => exposure,
+        None => auto_exposure(
+            frame,
+            options.roi.unwrap_or((0, 0, frame.width, frame.height)),
+            options.white_percentile.unwrap_or(DEFAULT_WHITE_PERCENTILE),
+        )?,
+    };
+    ensure!(
+        exposure.is_finite() && exposure > 0.0,
+        "Expect a positive exposure, but get {exposure}"
+    );
+
+    let mut pixels = nd::Array3::<u8>::zeros((frame.height, frame.width, 4));
+    nd::Zip::indexed(pixels.lanes_mut(nd::Axis(2))).par_for_each(|(y, x), mut px| {
+        let (rgb, alpha) = frame.pixel(x, y);
+        for c in 0..3 {
+            px[c] = encode_srgb(curve.apply(rgb[c] * exposure));
+        }
+        px[3] = (alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+    });
+    image::RgbaImage::from_raw(
+        frame.width as u32,
+        frame.height as u32,
+        pixels.into_raw_vec(),
+    )
+    .ok_or(anyhow::anyhow!("Failed to create the tone-mapped image"))
+}
+
+/// Same as [tone_map_hdr_frame] for a `Mat` taken by [HdrFrame::from_mat], returning an RGBA
+/// `CV_8UC4` `Mat` in [crate::ChannelOrder::Rgb].
+///
+/// This maps the whole frame, whereas [crate::Hd2mCvManager::match_mat_roi_async] only maps the
+/// panel of HDR frames.
+#[cfg(feature = "opencv")]
+pub fn tone_map_hdr_mat(mat: &cv::core::Mat, options: &ToneMapOptions) -> Result<cv::core::Mat> {
+    let frame = HdrFrame::from_mat(mat)?;
+    crate::image_to_mat(
+        &tone_map_hdr_frame(&frame, options)?,
+        crate::ChannelOrder::Rgb,
+    )
+}
+
+// SMPTE ST 2084 EOTF, from a PQ signal in `0.0..=1.0` to nits.
+fn pq_to_nits(signal: f32) -> f32 {
+    let p = signal.powf(1.0 / PQ_M2);
+    ((p - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * p)).powf(1.0 / PQ_M1) * PQ_MAX_NITS
+}
+
+// sRGB OETF, from a linear value in `0.0..=1.0` to 8 bits.
+fn encode_srgb(linear: f32) -> u8 {
+    let v = if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (v * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Brightness games commonly draw their HUD at in HDR, 200 nits.
+    const PAPER_WHITE: f32 = 2.5;
+
+    fn decode_srgb(v: u8) -> f32 {
+        let v = v as f32 / 255.0;
+        if v <= 0.040_45 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    fn nits_to_pq(nits: f32) -> f32 {
+        let y = (nits / PQ_MAX_NITS).powf(PQ_M1);
+        ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+    }
+
+    // Renders `sdr` the way an HDR game would, the SDR white at `PAPER_WHITE`.
+    fn render_rgba16f(sdr: &image::RgbaImage) -> Vec<u8> {
+        sdr.pixels()
+            .flat_map(|px| {
+                let [r, g, b, a] = px.0;
+                [r, g, b]
+                    .map(|v| decode_srgb(v) * PAPER_WHITE)
+                    .into_iter()
+                    .chain([a as f32 / 255.0])
+                    .flat_map(|v| f16::from_f32(v).to_le_bytes())
+            })
+            .collect()
+    }
+
+    fn panel() -> image::RgbaImage {
+        image::RgbaImage::from_fn(64, 48, |x, y| {
+            if (20..44).contains(&x) && (12..36).contains(&y) {
+                // The arrow, white as in the SDR templates.
+                image::Rgba([255, 255, 255, 255])
+            } else {
+                let v = ((x * 3 + y * 2) % 96) as u8;
+                image::Rgba([v, v / 2 + 20, v, 255])
+            }
+        })
+    }
+
+    fn assert_close(a: &image::RgbaImage, b: &image::RgbaImage, tolerance: u8) {
+        assert_eq!(a.dimensions(), b.dimensions());
+        for (pa, pb) in a.pixels().zip(b.pixels()) {
+            for c in 0..4 {
+                assert!(pa[c].abs_diff(pb[c]) <= tolerance, "{pa:?} != {pb:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_tone_map_rgba16f() -> Result<()> {
+        let sdr = panel();
+        let data = render_rgba16f(&sdr);
+        let frame = HdrFrame::new(HdrFormat::Rgba16F, 64, 48, &data)?;
+
+        // Undoing the paper white gets the SDR frame back.
+        let exposed = tone_map_hdr_frame(
+            &frame,
+            &ToneMapOptions {
+                exposure: Some(1.0 / PAPER_WHITE),
+                ..Default::default()
+            },
+        )?;
+        assert_close(&exposed, &sdr, 1);
+
+        // So does the automatic exposure, the arrow being the brightest part of the panel.
+        let exposure = auto_exposure(&frame, (0, 0, 64, 48), DEFAULT_WHITE_PERCENTILE)?;
+        assert!((exposure * PAPER_WHITE - 1.0).abs() <= 0.022, "{exposure}");
+        assert_close(
+            &tone_map_hdr_frame(&frame, &ToneMapOptions::default())?,
+            &sdr,
+            3,
+        );
+
+        // Without it, the HUD is blown out as in the washed-out captures.
+        let unexposed = tone_map_hdr_frame(
+            &frame,
+            &ToneMapOptions {
+                exposure: Some(1.0),
+                ..Default::default()
+            },
+        )?;
+        assert!(unexposed.get_pixel(0, 40)[1] > sdr.get_pixel(0, 40)[1] + 25);
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_exposure_roi() -> Result<()> {
+        // A sun far brighter than the HUD, outside of the panel.
+        let mut data = render_rgba16f(&panel());
+        for px in data.chunks_exact_mut(8).skip(48 * 64 - 64 * 4) {
+            for c in 0..3 {
+                px[2 * c..2 * c + 2].copy_from_slice(&f16::from_f32(40.0).to_le_bytes());
+            }
+        }
+        let frame = HdrFrame::new(HdrFormat::Rgba16F, 64, 48, &data)?;
+        let whole = auto_exposure(&frame, (0, 0, 64, 48), DEFAULT_WHITE_PERCENTILE)?;
+        assert!(whole * 40.0 <= 1.0 && whole * 40.0 > 0.97, "{whole}");
+        let panel = auto_exposure(&frame, (0, 0, 64, 44), DEFAULT_WHITE_PERCENTILE)?;
+        assert!((panel * PAPER_WHITE - 1.0).abs() <= 0.022, "{panel}");
+
+        // A black panel keeps a bounded exposure.
+        let black = vec![0u8; 16 * 16 * 8];
+        let frame = HdrFrame::new(HdrFormat::Rgba16F, 16, 16, &black)?;
+        assert_eq!(
+            auto_exposure(&frame, (0, 0, 16, 16), 0.99)?,
+            EXPOSURE_RANGE.1
+        );
+
+        assert!(auto_exposure(&frame, (8, 8, 9, 8), 0.99).is_err());
+        assert!(auto_exposure(&frame, (0, 0, 0, 8), 0.99).is_err());
+        assert!(auto_exposure(&frame, (0, 0, 16, 16), 1.5).is_err());
+        assert!(HdrFrame::new(HdrFormat::Rgba16F, 16, 15, &black).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_crop() -> Result<()> {
+        let data = render_rgba16f(&panel());
+        let frame = HdrFrame::new(HdrFormat::Rgba16F, 64, 48, &data)?;
+        let roi = (16, 8, 40, 32);
+        let cropped = frame.crop(roi)?;
+        assert_eq!(cropped.size(), (40, 32));
+        assert_eq!(cropped.pixel(0, 0), frame.pixel(16, 8));
+        assert_eq!(cropped.pixel(39, 31), frame.pixel(55, 39));
+
+        // The crop is exposed as the ROI of the whole frame.
+        let options = ToneMapOptions {
+            roi: Some(roi),
+            ..Default::default()
+        };
+        let whole = tone_map_hdr_frame(&frame, &options)?;
+        let (x, y, width, height) = roi;
+        let expected =
+            image::imageops::crop_imm(&whole, x as u32, y as u32, width as u32, height as u32)
+                .to_image();
+        assert_eq!(
+            tone_map_hdr_frame(&cropped, &ToneMapOptions::default())?,
+            expected
+        );
+
+        assert!(frame.crop((0, 0, 65, 48)).is_err());
+        assert!(frame.crop((8, 8, 0, 8)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hdr10_decoding() -> Result<()> {
+        let pack = |rgb: [f32; 3], alpha: u32| {
+            let code = |nits: f32| (nits_to_pq(nits) * 1023.0).round() as u32;
+            (code(rgb[0]) | code(rgb[1]) << 10 | code(rgb[2]) << 20 | alpha << 30).to_le_bytes()
+        };
+        let data = [
+            pack([80.0; 3], 3),
+            pack([200.0; 3], 3),
+            pack([0.0; 3], 0),
+            pack([1000.0, 0.0, 0.0], 3),
+        ]
+        .concat();
+        let frame = HdrFrame::new(HdrFormat::Rgb10A2, 2, 2, &data)?;
+
+        // Grays keep their luminance through the change of primaries.
+        let (gray, alpha) = frame.pixel(0, 0);
+        for v in gray {
+            assert!((v - 1.0).abs() < 0.01, "{gray:?}");
+        }
+        assert_eq!(alpha, 1.0);
+        for v in frame.pixel(1, 0).0 {
+            assert!((v - 2.5).abs() < 0.025, "{v}");
+        }
+        assert_eq!(frame.pixel(0, 1), ([0.0; 3], 0.0));
+        // The BT.2020 red is outside of BT.709, so the other channels clamp to zero.
+        let (red, _) = frame.pixel(1, 1);
+        assert!(
+            red[0] > 12.5 * 1.6 && red[1] == 0.0 && red[2] == 0.0,
+            "{red:?}"
+        );
+
+        let sdr = tone_map_hdr_frame(
+            &frame,
+            &ToneMapOptions {
+                exposure: Some(1.0),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(sdr.get_pixel(0, 0).0, [255, 255, 255, 255]);
+        assert_eq!(sdr.get_pixel(0, 1).0, [0, 0, 0, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tone_curves() {
+        let curves = [
+            ToneCurve::Clip,
+            ToneCurve::Reinhard { white: 4.0 },
+            ToneCurve::Aces,
+        ];
+        for curve in curves {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert_eq!(curve.apply(-1.0), 0.0);
+            assert_eq!(curve.apply(f32::NAN), 0.0);
+            assert_eq!(curve.apply(100.0), 1.0);
+            let mut last = 0.0;
+            for i in 1..=64 {
+                let v = curve.apply(i as f32 / 16.0);
+                assert!(v >= last, "{curve:?} is not monotonic");
+                last = v;
+            }
+        }
+        assert_eq!("Clip".parse::<ToneCurve>().unwrap(), ToneCurve::Clip);
+        assert_eq!("aces".parse::<ToneCurve>().unwrap(), ToneCurve::Aces);
+        assert_eq!(
+            "reinhard:4".parse::<ToneCurve>().unwrap(),
+            ToneCurve::Reinhard { white: 4.0 }
+        );
+        assert!("reinhard".parse::<ToneCurve>().is_err());
+        assert!("filmic".parse::<ToneCurve>().is_err());
+
+        assert_eq!(ToneCurve::Clip.apply(0.5), 0.5);
+        assert!((ToneCurve::Reinhard { white: 4.0 }.apply(4.0) - 1.0).abs() < 1e-6);
+        assert!(ToneCurve::Reinhard { white: 4.0 }.apply(2.0) < 1.0);
+
+        let data = vec![0u8; 8];
+        let frame = HdrFrame::new(HdrFormat::Rgba16F, 1, 1, &data).unwrap();
+        let options = ToneMapOptions {
+            curve: Some(ToneCurve::Reinhard { white: 0.0 }),
+            exposure: Some(1.0),
+            ..Default::default()
+        };
+        assert!(tone_map_hdr_frame(&frame, &options).is_err());
+    }
+}
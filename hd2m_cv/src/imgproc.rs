@@ -0,0 +1,514 @@
+use crate::ResponseElement;
+use anyhow::{ensure, Result};
+use ndarray::{self as nd, parallel::prelude::*};
+
+// Same weights OpenCV uses for `COLOR_RGBA2GRAY`.
+const GRAY_WEIGHTS: [f32; 3] = [0.299, 0.587, 0.114];
+
+// tan(22.5deg), used to bucket gradient directions for non-maximum suppression.
+const TAN_22_5: f32 = 0.414_213_57;
+
+const EDGE_NONE: u8 = 0;
+const EDGE_WEAK: u8 = 1;
+const EDGE_STRONG: u8 = 2;
+
+/// Converts an RGBA image into a `(row, col)` grayscale array with values in `0.0..=255.0`.
+pub fn convert_rgba_to_grayscale(image: &image::RgbaImage) -> Result<nd::Array2<f32>> {
+    let mut gray = nd::Array2::zeros((0, 0));
+    convert_rgba_to_grayscale_into(image, &mut gray)?;
+    Ok(gray)
+}
+
+pub(crate) fn convert_rgba_to_grayscale_into(
+    image: &image::RgbaImage,
+    gray: &mut nd::Array2<f32>,
+) -> Result<()> {
+    let (width, height) = image.dimensions();
+    let pixels = nd::ArrayView3::from_shape((height as usize, width as usize, 4), image.as_raw())?;
+    convert_pixels_to_grayscale_into(&pixels, gray)
+}
+
+/// Converts `(row, col, channel)` pixels in RGB(A) order into a grayscale array.
+pub fn convert_pixels_to_grayscale(pixels: &nd::ArrayView3<u8>) -> Result<nd::Array2<f32>> {
+    let mut gray = nd::Array2::zeros((0, 0));
+    convert_pixels_to_grayscale_into(pixels, &mut gray)?;
+    Ok(gray)
+}
+
+pub(crate) fn convert_pixels_to_grayscale_into(
+    pixels: &nd::ArrayView3<u8>,
+    gray: &mut nd::Array2<f32>,
+) -> Result<()> {
+    ensure!(
+        pixels.dim().2 >= 3,
+        "Expect at least 3 channels, but get {} channels",
+        pixels.dim().2
+    );
+    let (height, width, _) = pixels.dim();
+    ensure_shape(gray, (height, width));
+    nd::Zip::from(gray)
+        .and(pixels.lanes(nd::Axis(2)))
+        .par_for_each(|gray, px| {
+            *gray = GRAY_WEIGHTS[0] * px[0] as f32
+                + GRAY_WEIGHTS[1] * px[1] as f32
+                + GRAY_WEIGHTS[2] * px[2] as f32;
+        });
+    Ok(())
+}
+
+/// Reshapes `buf` to `shape`, only allocating when the shape changes.
+///
+/// The contents are left as they are, so callers must overwrite all of them.
+pub(crate) fn ensure_shape<T: Clone + Default>(buf: &mut nd::Array2<T>, shape: (usize, usize)) {
+    if buf.dim() != shape {
+        *buf = nd::Array2::from_elem(shape, T::default());
+    }
+}
+
+/// Blurs the array with a `ksize` x `ksize` gaussian kernel.
+///
+/// When `sigma` is not positive it is derived from `ksize` the same way OpenCV does.
+pub fn gaussian_blur(
+    src: &nd::ArrayView2<f32>,
+    ksize: usize,
+    sigma: f64,
+) -> Result<nd::Array2<f32>> {
+    let mut res = nd::Array2::zeros((0, 0));
+    gaussian_blur_into(src, ksize, sigma, &mut nd::Array2::zeros((0, 0)), &mut res)?;
+    Ok(res)
+}
+
+// Same as `gaussian_blur`, with `horizontal` holding the intermediate pass.
+pub(crate) fn gaussian_blur_into(
+    src: &nd::ArrayView2<f32>,
+    ksize: usize,
+    sigma: f64,
+    horizontal: &mut nd::Array2<f32>,
+    res: &mut nd::Array2<f32>,
+) -> Result<()> {
+    ensure!(ksize % 2 == 1, "Kernel size must be odd, but get {ksize}");
+    let kernel = gaussian_kernel(ksize, sigma);
+    filter_separable_into(src, &kernel, &kernel, horizontal, res);
+    Ok(())
+}
+
+fn gaussian_kernel(ksize: usize, sigma: f64) -> Vec<f32> {
+    // OpenCV uses a fixed kernel for the small sizes when no sigma is given.
+    if sigma <= 0.0 {
+        match ksize {
+            1 => return vec![1.0],
+            3 => return vec![0.25, 0.5, 0.25],
+            5 => return vec![0.0625, 0.25, 0.375, 0.25, 0.0625],
+            _ => {}
+        }
+    }
+    let sigma = if sigma > 0.0 {
+        sigma
+    } else {
+        0.3 * ((ksize as f64 - 1.0) * 0.5 - 1.0) + 0.8
+    };
+    let center = (ksize / 2) as f64;
+    let weights: Vec<f64> = (0..ksize)
+        .map(|i| (-(i as f64 - center).powi(2) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    weights.iter().map(|w| (w / sum) as f32).collect()
+}
+
+/// Applies `kernel_x` along the columns and then `kernel_y` along the rows, mirroring the border
+/// (`BORDER_REFLECT_101`).
+pub fn filter_separable(
+    src: &nd::ArrayView2<f32>,
+    kernel_x: &[f32],
+    kernel_y: &[f32],
+) -> nd::Array2<f32> {
+    let mut res = nd::Array2::zeros((0, 0));
+    filter_separable_into(
+        src,
+        kernel_x,
+        kernel_y,
+        &mut nd::Array2::zeros((0, 0)),
+        &mut res,
+    );
+    res
+}
+
+// Same as `filter_separable`, with `horizontal` holding the intermediate pass.
+pub(crate) fn filter_separable_into(
+    src: &nd::ArrayView2<f32>,
+    kernel_x: &[f32],
+    kernel_y: &[f32],
+    horizontal: &mut nd::Array2<f32>,
+    res: &mut nd::Array2<f32>,
+) {
+    let (height, width) = src.dim();
+    let radius_x = (kernel_x.len() / 2) as isize;
+    let radius_y = (kernel_y.len() / 2) as isize;
+
+    ensure_shape(horizontal, (height, width));
+    nd::Zip::indexed(&mut *horizontal).par_for_each(|(y, x), out| {
+        *out = kernel_x
+            .iter()
+            .enumerate()
+            .map(|(k, &w)| w * src[[y, reflect_101(x as isize + k as isize - radius_x, width)]])
+            .sum();
+    });
+
+    ensure_shape(res, (height, width));
+    nd::Zip::indexed(res).par_for_each(|(y, x), out| {
+        *out = kernel_y
+            .iter()
+            .enumerate()
+            .map(|(k, &w)| {
+                w * horizontal[[reflect_101(y as isize + k as isize - radius_y, height), x]]
+            })
+            .sum();
+    });
+}
+
+fn reflect_101(i: isize, len: usize) -> usize {
+    if len == 1 {
+        return 0;
+    }
+    let len = len as isize;
+    let mut i = i;
+    while i < 0 || i >= len {
+        i = if i < 0 { -i } else { 2 * (len - 1) - i };
+    }
+    i as usize
+}
+
+/// Canny edge detector with a 3x3 Sobel aperture, modeled after `cv::imgproc::canny`.
+///
+/// Edge pixels are set to `255.0`, everything else to `0.0`.
+pub fn canny(
+    src: &nd::ArrayView2<f32>,
+    low_threshold: f32,
+    high_threshold: f32,
+    l2_gradient: bool,
+) -> nd::Array2<f32> {
+    let mut res = nd::Array2::zeros((0, 0));
+    canny_into(
+        src,
+        low_threshold,
+        high_threshold,
+        l2_gradient,
+        &mut CannyBuffers::default(),
+        &mut res,
+    );
+    res
+}
+
+/// Intermediate arrays of [canny], kept to be reused across frames of the same size.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CannyBuffers {
+    filtered: nd::Array2<f32>,
+    dx: nd::Array2<f32>,
+    dy: nd::Array2<f32>,
+    magnitude: nd::Array2<f32>,
+    edges: nd::Array2<u8>,
+    stack: Vec<(usize, usize)>,
+}
+
+pub(crate) fn canny_into(
+    src: &nd::ArrayView2<f32>,
+    low_threshold: f32,
+    high_threshold: f32,
+    l2_gradient: bool,
+    buffers: &mut CannyBuffers,
+    res: &mut nd::Array2<f32>,
+) {
+    let (height, width) = src.dim();
+    let CannyBuffers {
+        filtered,
+        dx,
+        dy,
+        magnitude,
+        edges,
+        stack,
+    } = buffers;
+    filter_separable_into(src, &[-1.0, 0.0, 1.0], &[1.0, 2.0, 1.0], filtered, dx);
+    filter_separable_into(src, &[1.0, 2.0, 1.0], &[-1.0, 0.0, 1.0], filtered, dy);
+
+    ensure_shape(magnitude, (height, width));
+    nd::Zip::from(&mut *magnitude)
+        .and(&*dx)
+        .and(&*dy)
+        .par_for_each(|m, &dx, &dy| {
+            *m = if l2_gradient {
+                (dx * dx + dy * dy).sqrt()
+            } else {
+                dx.abs() + dy.abs()
+            };
+        });
+
+    // Pixels outside of the image have no gradient.
+    let mag_at = |y: isize, x: isize| -> f32 {
+        if y < 0 || x < 0 || y >= height as isize || x >= width as isize {
+            0.0
+        } else {
+            magnitude[[y as usize, x as usize]]
+        }
+    };
+
+    // Non-maximum suppression, classifying the surviving pixels into weak and strong edges.
+    ensure_shape(edges, (height, width));
+    nd::Zip::indexed(&mut *edges).par_for_each(|(y, x), edge| {
+        *edge = EDGE_NONE;
+        let m = magnitude[[y, x]];
+        if m <= low_threshold {
+            return;
+        }
+        let (gx, gy) = (dx[[y, x]], dy[[y, x]]);
+        let (ax, ay) = (gx.abs(), gy.abs());
+        let (y, x) = (y as isize, x as isize);
+        let is_local_max = if ay < ax * TAN_22_5 {
+            m > mag_at(y, x - 1) && m >= mag_at(y, x + 1)
+        } else if ay > ax * (TAN_22_5 + 2.0) {
+            m > mag_at(y - 1, x) && m >= mag_at(y + 1, x)
+        } else {
+            let s = if (gx < 0.0) != (gy < 0.0) { -1 } else { 1 };
+            m > mag_at(y - 1, x - s) && m > mag_at(y + 1, x + s)
+        };
+        if is_local_max {
+            *edge = if m > high_threshold {
+                EDGE_STRONG
+            } else {
+                EDGE_WEAK
+            };
+        }
+    });
+
+    // Hysteresis: keep the weak edges only if they are connected to a strong one.
+    stack.clear();
+    stack.extend(
+        edges
+            .indexed_iter()
+            .filter(|(_, &e)| e == EDGE_STRONG)
+            .map(|(pos, _)| pos),
+    );
+    while let Some((y, x)) = stack.pop() {
+        for ny in y.saturating_sub(1)..(y + 2).min(height) {
+            for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                if edges[[ny, nx]] == EDGE_WEAK {
+                    edges[[ny, nx]] = EDGE_STRONG;
+                    stack.push((ny, nx));
+                }
+            }
+        }
+    }
+
+    ensure_shape(res, (height, width));
+    nd::Zip::from(res)
+        .and(&*edges)
+        .par_for_each(|res, &e| *res = if e == EDGE_STRONG { 255.0 } else { 0.0 });
+}
+
+/// Nearest-neighbor resize by `scale`, equivalent to OpenCV's `INTER_NEAREST_EXACT`.
+pub fn resize_nearest(src: &nd::ArrayView2<f32>, scale: f64) -> Result<nd::Array2<f32>> {
+    ensure!(scale > 0.0, "Scale must be positive, but get {scale}");
+    let (height, width) = src.dim();
+    ensure!(
+        height > 0 && width > 0,
+        "Expect a non-empty source, but get {height}x{width}"
+    );
+    let new_height = ((height as f64 * scale).round() as usize).max(1);
+    let new_width = ((width as f64 * scale).round() as usize).max(1);
+    let res = nd::Array2::from_shape_fn((new_height, new_width), |(y, x)| {
+        let src_y = (((y as f64 + 0.5) / scale) as usize).min(height - 1);
+        let src_x = (((x as f64 + 0.5) / scale) as usize).min(width - 1);
+        src[[src_y, src_x]]
+    });
+    Ok(res)
+}
+
+/// Summed-area table of `src` with an extra leading row and column of zeros.
+pub fn integral_image(src: &nd::ArrayView2<f64>) -> nd::Array2<f64> {
+    let mut sum = nd::Array2::zeros((0, 0));
+    integral_image_with(src, |v| v, &mut sum);
+    sum
+}
+
+/// Summed-area table of the squares of `src`, as used to normalize the correlation.
+pub(crate) fn squared_integral_image_into(src: &nd::ArrayView2<f32>, sum: &mut nd::Array2<f64>) {
+    integral_image_with(src, |v| (v as f64).powi(2), sum);
+}
+
+fn integral_image_with<T: Copy>(
+    src: &nd::ArrayView2<T>,
+    map: impl Fn(T) -> f64,
+    sum: &mut nd::Array2<f64>,
+) {
+    let (height, width) = src.dim();
+    ensure_shape(sum, (height + 1, width + 1));
+    sum.row_mut(0).fill(0.0);
+    for y in 0..height {
+        sum[[y + 1, 0]] = 0.0;
+        let mut row_sum = 0.0;
+        for x in 0..width {
+            row_sum += map(src[[y, x]]);
+            sum[[y + 1, x + 1]] = sum[[y, x + 1]] + row_sum;
+        }
+    }
+}
+
+/// Sum of the `height` x `width` window at `(y, x)` from a table made by [integral_image].
+pub fn integral_window_sum(
+    integral: &nd::ArrayView2<f64>,
+    y: usize,
+    x: usize,
+    height: usize,
+    width: usize,
+) -> f64 {
+    integral[[y + height, x + width]] - integral[[y, x + width]] - integral[[y + height, x]]
+        + integral[[y, x]]
+}
+
+/// Normalized cross-correlation (`TM_CCORR_NORMED`) of `template` over `image`.
+///
+/// The result has the shape `(image_rows - template_rows + 1, image_cols - template_cols + 1)`.
+pub fn match_template_ccorr_normed(
+    image: &nd::ArrayView2<f32>,
+    template: &nd::ArrayView2<f32>,
+) -> Result<nd::Array2<f32>> {
+    let mut sq_integral = nd::Array2::zeros((0, 0));
+    squared_integral_image_into(image, &mut sq_integral);
+    let mut res = nd::Array2::zeros((0, 0));
+    match_template_ccorr_normed_into(image, template, &sq_integral.view(), &mut res)?;
+    Ok(res)
+}
+
+// Same as `match_template_ccorr_normed`, with the squared integral image of `image` made once
+// for all the templates, and the result stored as any `ResponseElement`.
+pub(crate) fn match_template_ccorr_normed_into<T: ResponseElement>(
+    image: &nd::ArrayView2<f32>,
+    template: &nd::ArrayView2<f32>,
+    sq_integral: &nd::ArrayView2<f64>,
+    res: &mut nd::Array2<T>,
+) -> Result<()> {
+    let (image_height, image_width) = image.dim();
+    let (template_height, template_width) = template.dim();
+    ensure!(
+        template_height > 0 && template_width > 0,
+        "Template must not be empty"
+    );
+    ensure!(
+        template_height <= image_height && template_width <= image_width,
+        "Template ({template_width}x{template_height}) is larger than the image ({image_width}x{image_height})"
+    );
+    let res_height = image_height - template_height + 1;
+    let res_width = image_width - template_width + 1;
+
+    // Edge maps are mostly zeros, so only walk the pixels that actually contribute.
+    let taps: Vec<(usize, usize, f32)> = template
+        .indexed_iter()
+        .filter(|(_, &v)| v != 0.0)
+        .map(|((y, x), &v)| (y, x, v))
+        .collect();
+    let template_norm = taps
+        .iter()
+        .map(|&(_, _, v)| (v as f64).powi(2))
+        .sum::<f64>()
+        .sqrt();
+
+    ensure_shape(res, (res_height, res_width));
+    res.axis_iter_mut(nd::Axis(0))
+        .into_par_iter()
+        .enumerate()
+        .for_each_init(
+            // Correlations are summed in `f32` whatever the element type of the result.
+            || nd::Array1::<f32>::zeros(res_width),
+            |sums, (y, mut row)| {
+                sums.fill(0.0);
+                for &(ty, tx, tv) in &taps {
+                    sums.scaled_add(tv, &image.slice(nd::s![y + ty, tx..tx + res_width]));
+                }
+                for (x, (el, &sum)) in row.iter_mut().zip(sums.iter()).enumerate() {
+                    let window =
+                        integral_window_sum(sq_integral, y, x, template_height, template_width);
+                    *el = T::from_confidence(normalize_correlation(
+                        sum as f64,
+                        window.max(0.0).sqrt() * template_norm,
+                    ));
+                }
+            },
+        );
+
+    Ok(())
+}
+
+// Follows OpenCV's handling of the denominator so flat regions yield zero instead of NaN.
+pub(crate) fn normalize_correlation(num: f64, denom: f64) -> f32 {
+    if num.abs() < denom {
+        (num / denom) as f32
+    } else if num.abs() < denom * 1.125 {
+        num.signum() as f32
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gaussian_blur_keeps_flat_image() -> anyhow::Result<()> {
+        let src = nd::Array2::<f32>::from_elem((5, 7), 42.0);
+        let res = gaussian_blur(&src.view(), 3, 0.0)?;
+        assert!(res.iter().all(|&v| (v - 42.0).abs() < 1e-4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_canny_detects_step_edge() {
+        let src =
+            nd::Array2::<f32>::from_shape_fn((8, 8), |(_, x)| if x < 4 { 0.0 } else { 255.0 });
+        let edges = canny(&src.view(), 150.0, 300.0, true);
+        for y in 0..8 {
+            let row: Vec<f32> = edges.row(y).to_vec();
+            assert_eq!(
+                row.iter().filter(|&&v| v == 255.0).count(),
+                1,
+                "row {y}: {row:?}"
+            );
+        }
+        assert!(edges.column(0).iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_match_template_ccorr_normed_finds_template() -> anyhow::Result<()> {
+        let mut image = nd::Array2::<f32>::zeros((20, 30));
+        let template = nd::array![[255.0, 0.0, 255.0], [0.0, 255.0, 0.0], [255.0, 255.0, 0.0]];
+        image.slice_mut(nd::s![7..10, 12..15]).assign(&template);
+        image[[2, 3]] = 255.0;
+
+        let res = match_template_ccorr_normed(&image.view(), &template.view())?;
+        assert_eq!(res.dim(), (18, 28));
+        let (best, _) = res
+            .indexed_iter()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        assert_eq!(best, (7, 12));
+        assert!((res[[7, 12]] - 1.0).abs() < 1e-5);
+        assert_eq!(res[[15, 0]], 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resize_nearest() -> anyhow::Result<()> {
+        let src = nd::array![[1.0, 2.0], [3.0, 4.0]];
+        let res = resize_nearest(&src.view(), 2.0)?;
+        assert_eq!(
+            res,
+            nd::array![
+                [1.0, 1.0, 2.0, 2.0],
+                [1.0, 1.0, 2.0, 2.0],
+                [3.0, 3.0, 4.0, 4.0],
+                [3.0, 3.0, 4.0, 4.0]
+            ]
+        );
+        assert!(resize_nearest(&nd::Array2::zeros((0, 2)).view(), 2.0).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+use anyhow::{ensure, Result};
+use ndarray as nd;
+
+/// Axis order of a 3-dimensional array of pixels.
+///
+/// Arrays converted from `Mat`s and images are laid out as [PixelLayout::Hwc].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PixelLayout {
+    /// `(row, col, channel)`, with the channels of a pixel next to each other.
+    #[default]
+    Hwc,
+    /// `(channel, row, col)`, with a plane per channel.
+    Chw,
+}
+
+impl PixelLayout {
+    /// Returns the axis holding the channels.
+    pub fn channel_axis(self) -> nd::Axis {
+        match self {
+            PixelLayout::Hwc => nd::Axis(2),
+            PixelLayout::Chw => nd::Axis(0),
+        }
+    }
+
+    /// Returns the `(height, width, channels)` of `pixels` laid out as `self`.
+    pub fn dim<T>(self, pixels: &nd::ArrayView3<T>) -> (usize, usize, usize) {
+        let (a, b, c) = pixels.dim();
+        match self {
+            PixelLayout::Hwc => (a, b, c),
+            PixelLayout::Chw => (b, c, a),
+        }
+    }
+}
+
+/// Returns a view of `pixels` laid out as `to`, without copying them.
+pub fn convert_layout<'a, T>(
+    pixels: nd::ArrayView3<'a, T>,
+    from: PixelLayout,
+    to: PixelLayout,
+) -> nd::ArrayView3<'a, T> {
+    match (from, to) {
+        (PixelLayout::Hwc, PixelLayout::Chw) => pixels.permuted_axes([2, 0, 1]),
+        (PixelLayout::Chw, PixelLayout::Hwc) => pixels.permuted_axes([1, 2, 0]),
+        _ => pixels,
+    }
+}
+
+/// Returns the `(row, col)` plane of `channel`, without copying it.
+pub fn select_channel<'a, T>(
+    pixels: nd::ArrayView3<'a, T>,
+    layout: PixelLayout,
+    channel: usize,
+) -> Result<nd::ArrayView2<'a, T>> {
+    let channels = pixels.len_of(layout.channel_axis());
+    ensure!(
+        channel < channels,
+        "Expect a channel below {channels}, but get channel {channel}"
+    );
+    Ok(pixels.index_axis_move(layout.channel_axis(), channel))
+}
+
+/// Stacks `(row, col)` planes as the channels of an array laid out as `layout`, in order.
+pub fn merge_channels<T: Clone>(
+    planes: &[nd::ArrayView2<T>],
+    layout: PixelLayout,
+) -> Result<nd::Array3<T>> {
+    let Some(first) = planes.first() else {
+        anyhow::bail!("Expect at least 1 plane, but get none");
+    };
+    for plane in planes.iter() {
+        ensure!(
+            plane.dim() == first.dim(),
+            "Expect planes of size {:?}, but get {:?}",
+            first.dim(),
+            plane.dim()
+        );
+    }
+    Ok(nd::stack(layout.channel_axis(), planes)?)
+}
+
+/// Flattens the channels of `pixels` into the columns of their row, as `(row, col * channels +
+/// channel)`.
+///
+/// This is the layout of a `Mat` reshaped to a single channel.
+pub fn flatten_channels<T: Clone>(
+    pixels: nd::ArrayView3<T>,
+    layout: PixelLayout,
+) -> Result<nd::Array2<T>> {
+    let (height, width, channels) = layout.dim(&pixels);
+    let pixels = convert_layout(pixels, layout, PixelLayout::Hwc);
+    Ok(pixels
+        .as_standard_layout()
+        .into_owned()
+        .into_shape((height, width * channels))?)
+}
+
+/// Returns the `(row, col)` response map of a match result laid out as `(row, col, channel)`,
+/// without copying it.
+///
+/// The result must hold a single channel, as the ones of `match_template` do. The search takes
+/// the `(x, y)` layout, which is the transposed view of the map.
+pub fn extract_response_map<T>(result: nd::ArrayView3<T>) -> Result<nd::ArrayView2<T>> {
+    let channels = result.len_of(nd::Axis(2));
+    ensure!(
+        channels == 1,
+        "Expect a response with a single channel, but get {channels} channels"
+    );
+    select_channel(result, PixelLayout::Hwc, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const LAYOUTS: [PixelLayout; 2] = [PixelLayout::Hwc, PixelLayout::Chw];
+
+    // `(row, col, channel)` pixels of up to 4 channels.
+    fn pixels_strategy() -> impl Strategy<Value = nd::Array3<u8>> {
+        (1usize..12, 1usize..12, 1usize..5).prop_flat_map(|(height, width, channels)| {
+            proptest::collection::vec(any::<u8>(), height * width * channels).prop_map(
+                move |data| nd::Array3::from_shape_vec((height, width, channels), data).unwrap(),
+            )
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_convert_layout(pixels in pixels_strategy()) {
+            let chw = convert_layout(pixels.view(), PixelLayout::Hwc, PixelLayout::Chw);
+            prop_assert_eq!(PixelLayout::Chw.dim(&chw), pixels.dim());
+            for ((y, x, c), &v) in pixels.indexed_iter() {
+                prop_assert_eq!(chw[[c, y, x]], v);
+            }
+            prop_assert_eq!(convert_layout(chw, PixelLayout::Chw, PixelLayout::Hwc), pixels.view());
+            for layout in LAYOUTS {
+                prop_assert_eq!(convert_layout(pixels.view(), layout, layout), pixels.view());
+            }
+        }
+
+        #[test]
+        fn test_select_and_merge_channels(pixels in pixels_strategy()) {
+            let channels = pixels.dim().2;
+            for layout in LAYOUTS {
+                let arranged = convert_layout(pixels.view(), PixelLayout::Hwc, layout);
+                let planes = (0..channels)
+                    .map(|c| select_channel(arranged, layout, c))
+                    .collect::<Result<Vec<_>>>()
+                    .unwrap();
+                for (c, plane) in planes.iter().enumerate() {
+                    for ((y, x), &v) in plane.indexed_iter() {
+                        prop_assert_eq!(pixels[[y, x, c]], v);
+                    }
+                }
+                prop_assert_eq!(merge_channels(&planes, layout).unwrap(), arranged);
+                prop_assert!(select_channel(arranged, layout, channels).is_err());
+            }
+        }
+
+        #[test]
+        fn test_flatten_channels(pixels in pixels_strategy()) {
+            let (height, width, channels) = pixels.dim();
+            for layout in LAYOUTS {
+                let arranged = convert_layout(pixels.view(), PixelLayout::Hwc, layout);
+                let flat = flatten_channels(arranged, layout).unwrap();
+                prop_assert_eq!(flat.dim(), (height, width * channels));
+                for ((y, x, c), &v) in pixels.indexed_iter() {
+                    prop_assert_eq!(flat[[y, x * channels + c]], v);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let pixels = nd::Array3::<u8>::zeros((4, 5, 3));
+        assert!(extract_response_map(pixels.view()).is_err());
+        assert_eq!(
+            extract_response_map(pixels.slice(nd::s![.., .., ..1]))
+                .unwrap()
+                .dim(),
+            (4, 5)
+        );
+
+        assert!(merge_channels::<u8>(&[], PixelLayout::Hwc).is_err());
+        let (a, b) = (
+            nd::Array2::<u8>::zeros((4, 5)),
+            nd::Array2::<u8>::zeros((5, 4)),
+        );
+        assert!(merge_channels(&[a.view(), b.view()], PixelLayout::Hwc).is_err());
+        assert_eq!(
+            merge_channels(&[a.view(), a.view()], PixelLayout::Chw)
+                .unwrap()
+                .dim(),
+            (2, 4, 5)
+        );
+    }
+
+    #[cfg(feature = "opencv")]
+    mod opencv_layout {
+        use super::*;
+        use crate::TryFromCv;
+        use opencv::{self as cv, prelude::*};
+
+        proptest! {
+            // The utilities agree with how OpenCV itself lays out the channels of a `Mat`.
+            #[test]
+            fn test_matches_opencv_layout(pixels in pixels_strategy()) {
+                let (height, width, channels) = pixels.dim();
+                let mat = cv::core::Mat::try_from_cv(&pixels).unwrap();
+                prop_assert_eq!(mat.channels() as usize, channels);
+
+                let mut split = cv::core::Vector::<cv::core::Mat>::new();
+                cv::core::split(&mat, &mut split).unwrap();
+                let mut planes = Vec::new();
+                for (c, plane) in split.iter().enumerate() {
+                    let plane = nd::Array3::<u8>::try_from_cv(&plane).unwrap();
+                    let plane = extract_response_map(plane.view()).unwrap().to_owned();
+                    prop_assert_eq!(
+                        &plane,
+                        &select_channel(pixels.view(), PixelLayout::Hwc, c).unwrap()
+                    );
+                    planes.push(plane);
+                }
+
+                let mut merged = cv::core::Mat::default();
+                cv::core::merge(&split, &mut merged).unwrap();
+                let views = planes.iter().map(|p| p.view()).collect::<Vec<_>>();
+                prop_assert_eq!(
+                    nd::Array3::<u8>::try_from_cv(&merged).unwrap(),
+                    merge_channels(&views, PixelLayout::Hwc).unwrap()
+                );
+
+                // Reshaping to a single channel keeps the data, so each row holds its channels
+                // interleaved.
+                let reshaped = mat.reshape(1, height as i32).unwrap().clone_pointee();
+                let flat = flatten_channels(pixels.view(), PixelLayout::Hwc).unwrap();
+                prop_assert_eq!(
+                    extract_response_map(nd::ArrayView3::<u8>::try_from_cv(&reshaped).unwrap())
+                        .unwrap(),
+                    flat.view()
+                );
+                for ((y, x), &v) in flat.indexed_iter() {
+                    prop_assert_eq!(*reshaped.at_2d::<u8>(y as i32, x as i32).unwrap(), v);
+                }
+                prop_assert_eq!(flat.dim(), (height, width * channels));
+            }
+        }
+    }
+}
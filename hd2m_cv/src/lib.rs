@@ -1,17 +1,61 @@
 mod cv_convert;
 pub use cv_convert::*;
 
+mod imgproc;
+pub use imgproc::*;
+
+mod layout;
+pub use layout::*;
+
+mod hdr;
+pub use hdr::*;
+
 mod matcher;
 pub use matcher::*;
 
+mod response;
+pub use response::*;
+
 mod search;
 pub use search::*;
 
+mod threshold;
+pub use threshold::*;
+
+#[cfg(feature = "opencv")]
 mod convert;
+#[cfg(feature = "opencv")]
 pub use convert::*;
 
+mod pyramid;
+pub use pyramid::*;
+
+mod fingerprint;
+pub use fingerprint::*;
+
 mod manager;
 pub use manager::*;
 
+mod task;
+pub use task::*;
+
+mod batch;
+pub use batch::*;
+
 mod scale;
 pub use scale::*;
+
+mod fixture;
+pub use fixture::*;
+
+mod visualize;
+pub use visualize::*;
+
+mod debug;
+pub use debug::*;
+
+mod calibrate;
+pub use calibrate::*;
+
+mod templates;
+pub use templates::*;
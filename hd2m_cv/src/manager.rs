@@ -1,10 +1,59 @@
 use crate::{
-    convert_mat_to_array2, find_direction_commands, DirectionDescriptor, TemplateMatcher, TryIntoCv,
+    best_responses, find_candidate_bands,
+    search::{collect_direction_commands, raw_mats_to_direction_buffer_into},
+    select_threshold_from_scores, Direction, DirectionDescriptor, FrameFingerprint,
+    Hd2mCvDebugSnapshot, HudLayout, IntermediaryDirection, MatchCancelled, MatchDescriptor,
+    MatchFuture, MatchWorkspace, MatchingBackend, ResponseElement, ResponsePrecision, RowTiling,
+    TemplateCache, TemplateMatcher, TemplateMatcherResult, ThresholdMode, ToneMapOptions,
 };
 use anyhow::Result;
-use cv::core::MatTraitConst;
-use opencv as cv;
-use std::collections::BTreeMap;
+use half::f16;
+use ndarray as nd;
+#[cfg(feature = "opencv")]
+use opencv::{self as cv, prelude::*};
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard,
+    },
+};
+
+// Screen sizes the resized templates are kept for by default, enough for switching between
+// windowed and fullscreen without resizing again.
+const DEFAULT_REGISTRY_CAPACITY: usize = 4;
+
+// Luma levels the cells of a frame fingerprint may move by while the frame counts as unchanged.
+const FINGERPRINT_TOLERANCE: u8 = 1;
+
+// Rows the windows of the incremental search keep clear of at their edges, where the blur and edge
+// kernels see the border instead of the rest of the frame.
+const INCREMENTAL_WINDOW_MARGIN: usize = 4;
+
+// Scale of the downscaled frame the incremental search looks for rows outside of its windows in.
+const INCREMENTAL_COARSE_SCALE: f64 = 0.5;
+
+// Response of the coarse stage of the pyramid search above which its rows are matched in full
+// resolution. Lower than the search threshold, since the downscaled templates lose most of their
+// edges.
+const DEFAULT_PYRAMID_THRESHOLD: f32 = 0.4;
+
+// Incremental runs between two full searches, which find the rows that showed up outside of the
+// windows but were missed by the downscaled search.
+const INCREMENTAL_REFRESH_RUNS: usize = 30;
+
+// Confidence the weakest arrow of a row may lose since the last run before the windows are matched
+// again in full.
+const INCREMENTAL_CONFIDENCE_DROP: f32 = 0.05;
+
+// Order of the templates registered to the matcher, which is also the order of the match results.
+const TEMPLATE_DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Right,
+    Direction::Left,
+];
 
 #[derive(Debug)]
 pub struct Hd2mCvManagerConfig {
@@ -12,8 +61,42 @@ pub struct Hd2mCvManagerConfig {
     pub template_down_image: image::RgbaImage,
     pub template_right_image: image::RgbaImage,
     pub template_left_image: image::RgbaImage,
+    /// Hashes of the encoded templates, in the order of up, down, right and left, keying the
+    /// `template_cache` on them rather than on the decoded images.
+    /// See [crate::hash_encoded_template].
+    pub template_source_hashes: Option<[u64; 4]>,
     pub base_screen_size: (usize, usize),
+    /// In-game HUD scale, `1.0` by default, see [HudLayout].
+    pub hud_scale: Option<f64>,
     pub search_options: Option<Hd2mCvSearchOptions>,
+    pub backend: Option<MatchingBackend>,
+    /// Keeps the pre-processed and resized templates on disk, so that they aren't made again on
+    /// every start.
+    pub template_cache: Option<TemplateCache>,
+    /// How [Hd2mCvManager::match_mat_roi_async] tone-maps HDR frames, the defaults of
+    /// [ToneMapOptions] when not set. The automatic exposure is measured over the matched part of
+    /// the frame, and `roi` is taken within that part.
+    pub tone_map_options: Option<ToneMapOptions>,
+}
+
+impl Hd2mCvManagerConfig {
+    // Descriptors of the templates, in the order of `TEMPLATE_DIRECTIONS`.
+    pub(crate) fn template_descriptors(&self) -> [MatchDescriptor; 4] {
+        let mut source_hashes = self.template_source_hashes.map(|hashes| hashes.into_iter());
+        TEMPLATE_DIRECTIONS.map(|direction| {
+            let template = match direction {
+                Direction::Up => &self.template_up_image,
+                Direction::Down => &self.template_down_image,
+                Direction::Right => &self.template_right_image,
+                Direction::Left => &self.template_left_image,
+            };
+            let descriptor = MatchDescriptor::new(direction.label().to_owned(), template.clone());
+            match source_hashes.as_mut().and_then(Iterator::next) {
+                Some(hash) => descriptor.with_source_hash(hash),
+                None => descriptor,
+            }
+        })
+    }
 }
 
 #[derive(Debug, Default)]
@@ -21,168 +104,1854 @@ pub struct Hd2mCvSearchOptions {
     pub threshold: Option<f32>,
     pub search_chunk_size: Option<usize>,
     pub discarding_distance_threshold: Option<f64>,
+    /// Scale of the downscaled frame used to find the arrow rows before matching them in full
+    /// resolution. Values of `1.0` or above disable the coarse-to-fine search.
+    pub pyramid_scale: Option<f64>,
+    /// Threshold for the downscaled search, lower than `threshold` since downscaling blurs the
+    /// edges. `0.4` by default.
+    pub pyramid_threshold: Option<f32>,
+    /// How the threshold is picked for each frame. [ThresholdMode::Fixed] always uses `threshold`,
+    /// the other modes pick it from the responses within `threshold_floor..=threshold_ceiling`.
+    pub threshold_mode: Option<ThresholdMode>,
+    pub threshold_floor: Option<f32>,
+    pub threshold_ceiling: Option<f32>,
+    /// Element type of the response maps, `f32` by default. The bands of the pyramid search are
+    /// small enough that they always stay `f32`.
+    pub response_precision: Option<ResponsePrecision>,
+    /// Returns the result of the last matched frame again instead of matching frames that didn't
+    /// change since, as told by their [FrameFingerprint]. Off by default.
+    pub skip_unchanged_frames: Option<bool>,
+    /// Matches only the windows around the rows found by the last run, falling back to a full
+    /// search when a search on a half-size copy of the frame finds arrows outside of the windows,
+    /// as told by `pyramid_threshold`, when the number of rows or arrows changes or when the
+    /// confidence of a row drops. A full search still runs every 30 frames.
+    ///
+    /// The results are the same as those of a full search, down to the last bit of the
+    /// confidences, as long as the half-size search sees every new row. A row it misses, e.g. one
+    /// too faint at half size for `pyramid_threshold`, is only found by the next full search.
+    ///
+    /// Only applies to the native backends, since the transforms of [MatchingBackend::OpenCv] are
+    /// sized after the whole frame, and to [ThresholdMode::Fixed] without the pyramid search. Off
+    /// by default.
+    pub incremental_search: Option<bool>,
+}
+
+/// Result of a single run of [Hd2mCvManager].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hd2mCvMatchResult {
+    /// Arrows found, one row per stratagem.
+    pub commands: Vec<Vec<DirectionDescriptor>>,
+    /// Threshold the responses were cut at for this frame.
+    pub threshold: f32,
+}
+
+/// Frames [Hd2mCvManager] skipped or matched, see [Hd2mCvSearchOptions::skip_unchanged_frames].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hd2mCvFrameCacheStats {
+    /// Frames that reused the result of the last matched frame.
+    pub hits: u64,
+    /// Frames that had to be matched.
+    pub misses: u64,
 }
 
+/// Finds the stratagem codes in frames of the game.
+///
+/// The manager is `Send + Sync` and everything goes through `&self`, so it can be shared between
+/// workers, e.g. behind an [std::sync::Arc]. Changes to the screen size or the options apply to
+/// the runs started after them.
 #[derive(Debug)]
 pub struct Hd2mCvManager {
-    template_original: DirectionTemplateMatcherSet,
+    template_original: TemplateMatcher,
     base_screen_size: (usize, usize),
+    // INFO: Only locked to read or update the state, never while matching, so that the workers
+    // sharing the manager match in parallel.
+    state: Mutex<ManagerState>,
+    // `None` unless the debug mode is on.
+    debug_snapshots: Mutex<Option<Vec<Hd2mCvDebugSnapshot>>>,
+    // Buffers of the runs that finished, taken by the next ones so that steady-state runs don't
+    // allocate them again. There are as many as runs that went in parallel.
+    workspaces: WorkspacePools,
+    // `None` until a frame is matched with `skip_unchanged_frames`.
+    last_frame: Mutex<Option<MatchedFrame>>,
+    frame_cache_hits: AtomicU64,
+    frame_cache_misses: AtomicU64,
+    // `None` until a frame is matched with `incremental_search`.
+    tracked_rows: Mutex<Option<TrackedRows>>,
+    #[cfg(feature = "opencv")]
+    tone_map_options: ToneMapOptions,
+}
+
+// Frame matched with `skip_unchanged_frames`, along with the templates and settings it was matched
+// with, so that changing any of them matches the next frame again.
+#[derive(Debug)]
+struct MatchedFrame {
+    fingerprint: FrameFingerprint,
+    scaled: Arc<ScaledTemplateMatcher>,
+    settings: SearchSettings,
+    result: Hd2mCvMatchResult,
+}
+
+// Rows found by the last run of the incremental search, along with what they were found with.
+#[derive(Debug, Clone)]
+struct TrackedRows {
+    frame_size: (usize, usize),
+    scaled: Arc<ScaledTemplateMatcher>,
+    settings: SearchSettings,
+    commands: Vec<Vec<DirectionDescriptor>>,
+    // Incremental runs since the last full search.
+    incremental_runs: usize,
+}
+
+impl TrackedRows {
+    // Row ranges of the frame holding the tiles of the tracked rows grown by `padding` on both
+    // sides, along with a margin, starting at a tile boundary and merged when they overlap.
+    fn windows(&self, tiling: RowTiling, padding: usize) -> Vec<(usize, usize)> {
+        let frame_height = self.frame_size.1;
+        let mut rows: Vec<_> = self
+            .commands
+            .iter()
+            .filter_map(|row| {
+                let top = row.iter().map(|desc| desc.position.y).min()?;
+                let bottom = row.iter().map(|desc| desc.position.y).max()?;
+                let first = top.saturating_sub(padding) / tiling.rows * tiling.rows;
+                let last = (bottom + padding) / tiling.rows * tiling.rows;
+                Some((
+                    first.saturating_sub(INCREMENTAL_WINDOW_MARGIN) / tiling.rows * tiling.rows,
+                    (last + tiling.span + INCREMENTAL_WINDOW_MARGIN).min(frame_height),
+                ))
+            })
+            .collect();
+        rows.sort_unstable();
+
+        let mut windows: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in rows {
+            match windows.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => windows.push((start, end)),
+            }
+        }
+        windows
+    }
+
+    // Returns whether the rows found in the windows are still the tracked ones, or whether
+    // something changed enough that the frame has to be searched in full.
+    fn still_holds(&self, res: &Hd2mCvMatchResult) -> bool {
+        let weakest = |row: &[DirectionDescriptor]| {
+            row.iter()
+                .map(|desc| desc.confidence)
+                .fold(f32::INFINITY, f32::min)
+        };
+        res.commands.len() == self.commands.len()
+            && res.commands.iter().zip(&self.commands).all(|(row, last)| {
+                row.len() == last.len()
+                    && weakest(row) >= weakest(last) - INCREMENTAL_CONFIDENCE_DROP
+            })
+    }
+}
+
+// One pool of workspaces per response precision.
+#[derive(Debug, Default)]
+struct WorkspacePools {
+    f32: Mutex<Vec<MatchWorkspace<f32>>>,
+    f16: Mutex<Vec<MatchWorkspace<f16>>>,
+    u8: Mutex<Vec<MatchWorkspace<u8>>>,
+}
+
+// Frame matched with the buffers of a workspace.
+#[derive(Clone, Copy)]
+enum WorkspaceTarget<'a> {
+    Rgba(&'a image::RgbaImage),
+    #[cfg(feature = "opencv")]
+    Mat(&'a cv::core::Mat),
+}
+
+impl WorkspaceTarget<'_> {
+    // Returns the `(width, height)` of the frame.
+    fn size(self) -> (usize, usize) {
+        match self {
+            WorkspaceTarget::Rgba(image) => (image.width() as usize, image.height() as usize),
+            #[cfg(feature = "opencv")]
+            WorkspaceTarget::Mat(mat) => (mat.cols() as usize, mat.rows() as usize),
+        }
+    }
+
+    fn fingerprint(self) -> Result<FrameFingerprint> {
+        match self {
+            WorkspaceTarget::Rgba(image) => FrameFingerprint::from_rgba(image),
+            #[cfg(feature = "opencv")]
+            WorkspaceTarget::Mat(mat) => FrameFingerprint::from_mat(mat),
+        }
+    }
+
+    // Returns the edge map of the whole frame.
+    fn pre_process(self, backend: MatchingBackend) -> Result<nd::Array2<f32>> {
+        match self {
+            WorkspaceTarget::Rgba(image) => backend.pre_process_rgba(image),
+            #[cfg(feature = "opencv")]
+            WorkspaceTarget::Mat(mat) => backend.pre_process_mat(mat),
+        }
+    }
+
+    // Matches a copy of the frame downscaled to `(width, height)`.
+    fn match_downscaled(
+        self,
+        matcher: &TemplateMatcher,
+        (width, height): (usize, usize),
+    ) -> Result<Vec<TemplateMatcherResult>> {
+        match self {
+            WorkspaceTarget::Rgba(image) => {
+                let downscaled = image::imageops::resize(
+                    image,
+                    width as u32,
+                    height as u32,
+                    image::imageops::FilterType::Triangle,
+                );
+                matcher.match_templates(&downscaled)
+            }
+            #[cfg(feature = "opencv")]
+            WorkspaceTarget::Mat(mat) => {
+                let mut downscaled = cv::core::Mat::default();
+                cv::imgproc::resize(
+                    mat,
+                    &mut downscaled,
+                    cv::core::Size::new(width as i32, height as i32),
+                    0.0,
+                    0.0,
+                    cv::imgproc::INTER_AREA,
+                )?;
+                matcher.match_templates_mat(&downscaled)
+            }
+        }
+    }
+
+    fn match_into<T: ResponseElement>(
+        self,
+        matcher: &TemplateMatcher,
+        workspace: &mut MatchWorkspace<T>,
+    ) -> Result<()> {
+        match self {
+            WorkspaceTarget::Rgba(image) => matcher.match_templates_into(image, workspace)?,
+            #[cfg(feature = "opencv")]
+            WorkspaceTarget::Mat(mat) => matcher.match_templates_mat_into(mat, workspace)?,
+        };
+        Ok(())
+    }
+
+    // Matches the `(start, end)` row range of the frame alone.
+    fn match_rows_into<T: ResponseElement>(
+        self,
+        matcher: &TemplateMatcher,
+        (start, end): (usize, usize),
+        workspace: &mut MatchWorkspace<T>,
+    ) -> Result<()> {
+        match self {
+            WorkspaceTarget::Rgba(image) => {
+                let rows = image::imageops::crop_imm(
+                    image,
+                    0,
+                    start as u32,
+                    image.width(),
+                    (end - start) as u32,
+                )
+                .to_image();
+                matcher.match_templates_into(&rows, workspace)?;
+            }
+            #[cfg(feature = "opencv")]
+            WorkspaceTarget::Mat(mat) => {
+                let rect = cv::core::Rect::new(0, start as i32, mat.cols(), (end - start) as i32);
+                let rows = crate::MatView::roi(mat, rect)?;
+                matcher.match_templates_mat_into(&rows, workspace)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+// Everything that changes after the manager is made, updated together so that a run always gets
+// the templates and the search settings of the same screen size.
+#[derive(Debug)]
+struct ManagerState {
+    hud_scale: f64,
     current_screen_size: Option<(usize, usize)>,
-    template_registry: BTreeMap<(usize, usize), DirectionTemplateMatcherSet>,
-    template_search_threshold: f32,
-    template_search_chunk_size: usize,
-    template_discarding_distance_threshold: f64,
+    template_registry: TemplateRegistry,
+    search: SearchSettings,
 }
 
-impl Hd2mCvManager {
-    pub fn new(config: Hd2mCvManagerConfig) -> Result<Self> {
-        let up_template = TemplateMatcher::new(&config.template_up_image.try_into_cv()?)?;
-        let init_template_size = up_template.mat().size()?;
-        let matcher_set = DirectionTemplateMatcherSet::new(
-            up_template,
-            TemplateMatcher::new(&config.template_down_image.try_into_cv()?)?,
-            TemplateMatcher::new(&config.template_right_image.try_into_cv()?)?,
-            TemplateMatcher::new(&config.template_left_image.try_into_cv()?)?,
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SearchSettings {
+    threshold: f32,
+    chunk_size: usize,
+    discarding_distance_threshold: f64,
+    pyramid_scale: Option<f64>,
+    pyramid_threshold: f32,
+    threshold_mode: ThresholdMode,
+    threshold_floor: f32,
+    threshold_ceiling: f32,
+    response_precision: ResponsePrecision,
+    skip_unchanged_frames: bool,
+    incremental_search: bool,
+}
+
+#[derive(Debug)]
+struct ScaledTemplateMatcher {
+    scale: f64,
+    matcher: SharedMatcher,
+    // Made on demand for the coarse stage of the pyramid search, keyed by the pyramid scale.
+    coarse_matcher: Mutex<Option<(f64, Arc<SharedMatcher>)>>,
+}
+
+impl ScaledTemplateMatcher {
+    fn new(scale: f64, matcher: TemplateMatcher) -> Self {
+        Self {
+            scale,
+            matcher: SharedMatcher::new(matcher),
+            coarse_matcher: Mutex::new(None),
+        }
+    }
+}
+
+// Matcher used by several workers at once, prepared for the frame size of the last one that asked.
+#[derive(Debug)]
+struct SharedMatcher(RwLock<TemplateMatcher>);
+
+impl SharedMatcher {
+    fn new(matcher: TemplateMatcher) -> Self {
+        Self(RwLock::new(matcher))
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, TemplateMatcher> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn prepare_frame_size(&self, width: usize, height: usize) -> Result<()> {
+        // INFO: Another worker may prepare it for another size right after, which only costs the
+        // matching the time to prepare the frame size on the fly.
+        if !self.read().is_prepared_for(width, height) {
+            self.0
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .prepare_frame_size(width, height)?;
+        }
+        Ok(())
+    }
+}
+
+// Templates resized for each screen size, evicting the least recently used sizes past the capacity.
+#[derive(Debug)]
+struct TemplateRegistry {
+    capacity: usize,
+    // Each entry is stamped with `clock` when its screen size is used.
+    entries: BTreeMap<(usize, usize), (u64, Arc<ScaledTemplateMatcher>)>,
+    clock: u64,
+}
+
+impl TemplateRegistry {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&self, screen_size: (usize, usize)) -> Option<&Arc<ScaledTemplateMatcher>> {
+        self.entries.get(&screen_size).map(|(_, entry)| entry)
+    }
+
+    // Marks the screen size as the most recently used one, making its entry if needed.
+    fn use_or_insert_with(
+        &mut self,
+        screen_size: (usize, usize),
+        make: impl FnOnce() -> Result<ScaledTemplateMatcher>,
+    ) -> Result<&Arc<ScaledTemplateMatcher>> {
+        self.clock += 1;
+        match self.entries.get_mut(&screen_size) {
+            Some((last_used, _)) => *last_used = self.clock,
+            None => {
+                let entry = Arc::new(make()?);
+                self.entries.insert(screen_size, (self.clock, entry));
+                self.evict();
+            }
+        }
+        Ok(&self.entries[&screen_size].1)
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    // The most recently used entry is never evicted, so the capacity is at least one.
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity.max(1) {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(screen_size, _)| *screen_size)
+            else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+impl ManagerState {
+    fn use_screen_size(
+        &mut self,
+        template_original: &TemplateMatcher,
+        base_screen_size: (usize, usize),
+        screen_size: (usize, usize),
+    ) -> Result<()> {
+        let layout = HudLayout::new(screen_size, base_screen_size).with_hud_scale(self.hud_scale);
+        let template_resized = self.template_registry.use_or_insert_with(screen_size, || {
+            let inferred_scale = layout.scale();
+            Ok(ScaledTemplateMatcher::new(
+                inferred_scale,
+                template_original.with_resized_scale(inferred_scale)?,
+            ))
+        })?;
+
+        let rep_template_size = template_resized
+            .matcher
+            .read()
+            .template_size()
+            .ok_or(anyhow::anyhow!("No template registered"))?;
+        self.current_screen_size = Some(screen_size);
+        self.search.apply(Hd2mCvSearchOptions {
+            search_chunk_size: Some(rep_template_size.1 * 2),
+            discarding_distance_threshold: Some(rep_template_size.0 as f64 + 3.0),
+            ..Default::default()
+        });
+
+        Ok(())
+    }
+
+    fn invalidate(
+        &mut self,
+        template_original: &TemplateMatcher,
+        base_screen_size: (usize, usize),
+    ) -> Result<()> {
+        self.template_registry.clear();
+        if let Some(screen_size) = self.current_screen_size.take() {
+            self.use_screen_size(template_original, base_screen_size, screen_size)?;
+        }
+        Ok(())
+    }
+}
+
+impl SearchSettings {
+    fn apply(&mut self, options: Hd2mCvSearchOptions) {
+        self.threshold = options.threshold.unwrap_or(self.threshold);
+        self.chunk_size = options.search_chunk_size.unwrap_or(self.chunk_size);
+        self.discarding_distance_threshold = options
+            .discarding_distance_threshold
+            .unwrap_or(self.discarding_distance_threshold);
+        if let Some(pyramid_scale) = options.pyramid_scale {
+            self.pyramid_scale = (pyramid_scale < 1.0).then_some(pyramid_scale);
+        }
+        self.pyramid_threshold = options.pyramid_threshold.unwrap_or(self.pyramid_threshold);
+        self.threshold_mode = options.threshold_mode.unwrap_or(self.threshold_mode);
+        self.threshold_floor = options.threshold_floor.unwrap_or(self.threshold_floor);
+        self.threshold_ceiling = options.threshold_ceiling.unwrap_or(self.threshold_ceiling);
+        self.response_precision = options
+            .response_precision
+            .unwrap_or(self.response_precision);
+        self.skip_unchanged_frames = options
+            .skip_unchanged_frames
+            .unwrap_or(self.skip_unchanged_frames);
+        self.incremental_search = options
+            .incremental_search
+            .unwrap_or(self.incremental_search);
+    }
+
+    // Picks the threshold of a frame from the response maps of each of its searched parts.
+    fn select_threshold<'a, T: ResponseElement>(
+        &self,
+        parts: impl IntoIterator<Item = &'a [nd::Array2<T>]>,
+    ) -> f32 {
+        if self.threshold_mode == ThresholdMode::Fixed {
+            return self.threshold;
+        }
+        let mut scores = Vec::new();
+        for responses in parts {
+            let responses: Vec<_> = responses.iter().map(|res| res.view()).collect();
+            scores.extend(best_responses(&responses));
+        }
+        select_threshold_from_scores(
+            &scores,
+            self.threshold_mode,
+            self.threshold_floor,
+            self.threshold_ceiling,
+        )
+    }
+
+    // Finds the arrows in the response maps, in the order of `TEMPLATE_DIRECTIONS`, with
+    // `direction_buffer` holding the best direction of each position.
+    fn find_commands<T: ResponseElement>(
+        &self,
+        responses: &[nd::Array2<T>],
+        direction_buffer: &mut nd::Array2<IntermediaryDirection>,
+        row_offset: usize,
+        threshold: f32,
+        debug_snapshots: &mut Option<Vec<Hd2mCvDebugSnapshot>>,
+    ) -> Result<Vec<Vec<DirectionDescriptor>>> {
+        let [res_up, res_down, res_right, res_left] = responses else {
+            return Err(anyhow::anyhow!(
+                "Expected {} match results, but got {}",
+                TEMPLATE_DIRECTIONS.len(),
+                responses.len()
+            ));
+        };
+
+        // Transposed into the `(x, y)` search layout.
+        raw_mats_to_direction_buffer_into(
+            &res_up.t(),
+            &res_down.t(),
+            &res_right.t(),
+            &res_left.t(),
+            threshold,
+            direction_buffer,
         )?;
-        let mut template_registry: BTreeMap<(usize, usize), DirectionTemplateMatcherSet> =
-            BTreeMap::new();
-        template_registry.insert(
-            (
-                init_template_size.width as usize,
-                init_template_size.height as usize,
-            ),
-            matcher_set.clone(),
+        let descriptors = collect_direction_commands(
+            &direction_buffer.view(),
+            self.chunk_size,
+            self.discarding_distance_threshold,
+        )?;
+
+        if let Some(snapshots) = debug_snapshots.as_mut() {
+            snapshots.push(Hd2mCvDebugSnapshot {
+                row_offset,
+                threshold,
+                results: TEMPLATE_DIRECTIONS
+                    .iter()
+                    .zip(responses)
+                    .map(|(direction, res)| {
+                        let res = res.mapv(T::to_confidence);
+                        TemplateMatcherResult::new(direction.label().to_owned(), res)
+                    })
+                    .collect(),
+                direction_buffer: direction_buffer.clone(),
+            });
+        }
+
+        Ok(descriptors)
+    }
+
+    // Finds the arrows in the response maps that the last match left in `workspace`.
+    fn find_workspace_commands<T: ResponseElement>(
+        &self,
+        workspace: &mut MatchWorkspace<T>,
+        debug_snapshots: &mut Option<Vec<Hd2mCvDebugSnapshot>>,
+    ) -> Result<Hd2mCvMatchResult> {
+        let threshold = self.select_threshold([workspace.responses()]);
+        Ok(Hd2mCvMatchResult {
+            commands: self.find_commands(
+                &workspace.responses,
+                &mut workspace.direction_buffer,
+                0,
+                threshold,
+                debug_snapshots,
+            )?,
+            threshold,
+        })
+    }
+}
+
+impl Hd2mCvManager {
+    pub fn new(mut config: Hd2mCvManagerConfig) -> Result<Self> {
+        let search_options = config.search_options.take().unwrap_or_default();
+        let template_search_threshold = search_options.threshold.unwrap_or(0.987);
+        let hud_scale = config.hud_scale.unwrap_or(1.0);
+        anyhow::ensure!(
+            hud_scale > 0.0,
+            "HUD scale must be positive, but got {hud_scale}"
         );
-        let search_options = config.search_options.unwrap_or_default();
-        Ok(Self {
-            template_original: matcher_set,
-            base_screen_size: config.base_screen_size,
-            template_registry,
-            template_search_threshold: search_options.threshold.unwrap_or(0.987),
-            template_search_chunk_size: search_options
+        let descriptors = config.template_descriptors();
+        let matcher = TemplateMatcher::with_cache(
+            &descriptors,
+            config.backend.unwrap_or_default(),
+            config.template_cache.take(),
+        )?;
+        let init_template_size = matcher
+            .template_size()
+            .ok_or(anyhow::anyhow!("No template registered"))?;
+        let search = SearchSettings {
+            threshold: template_search_threshold,
+            chunk_size: search_options
                 .search_chunk_size
-                .unwrap_or(init_template_size.height as usize + 10),
-            current_screen_size: None,
-            template_discarding_distance_threshold: search_options
+                .unwrap_or(init_template_size.1 + 10),
+            discarding_distance_threshold: search_options
                 .discarding_distance_threshold
-                .unwrap_or(init_template_size.width as f64 + 3.0),
+                .unwrap_or(init_template_size.0 as f64 + 3.0),
+            pyramid_scale: search_options.pyramid_scale.filter(|&scale| scale < 1.0),
+            pyramid_threshold: search_options
+                .pyramid_threshold
+                .unwrap_or(DEFAULT_PYRAMID_THRESHOLD),
+            threshold_mode: search_options.threshold_mode.unwrap_or_default(),
+            // Otsu tends to split the arrows from the rest of the edges rather than from the
+            // background, so the floor matters as much as the mode.
+            threshold_floor: search_options.threshold_floor.unwrap_or(0.5),
+            threshold_ceiling: search_options.threshold_ceiling.unwrap_or(0.99),
+            response_precision: search_options.response_precision.unwrap_or_default(),
+            skip_unchanged_frames: search_options.skip_unchanged_frames.unwrap_or(false),
+            incremental_search: search_options.incremental_search.unwrap_or(false),
+        };
+        Ok(Self {
+            template_original: matcher,
+            base_screen_size: config.base_screen_size,
+            state: Mutex::new(ManagerState {
+                hud_scale,
+                current_screen_size: None,
+                template_registry: TemplateRegistry::new(DEFAULT_REGISTRY_CAPACITY),
+                search,
+            }),
+            debug_snapshots: Mutex::new(None),
+            workspaces: WorkspacePools::default(),
+            last_frame: Mutex::new(None),
+            frame_cache_hits: AtomicU64::new(0),
+            frame_cache_misses: AtomicU64::new(0),
+            tracked_rows: Mutex::new(None),
+            #[cfg(feature = "opencv")]
+            tone_map_options: config.tone_map_options.unwrap_or_default(),
         })
     }
 
-    pub fn run_match_rgba(
-        &mut self,
+    pub fn run_match_rgba(&self, target: &image::RgbaImage) -> Result<Hd2mCvMatchResult> {
+        self.run_match_rgba_cancellable(target, &|| false)
+    }
+
+    #[cfg(feature = "opencv")]
+    pub fn run_match_mat(&self, target: &cv::core::Mat) -> Result<Hd2mCvMatchResult> {
+        self.run_match_mat_cancellable(target, &|| false)
+    }
+
+    /// Same as [Self::run_match_rgba], for a frame unrelated to the ones matched before it: it
+    /// neither reuses nor updates what [Hd2mCvSearchOptions::skip_unchanged_frames] and
+    /// [Hd2mCvSearchOptions::incremental_search] keep across consecutive frames.
+    pub fn run_match_rgba_independent(
+        &self,
         target: &image::RgbaImage,
-    ) -> Result<Vec<Vec<DirectionDescriptor>>> {
-        self.run_match_mat(&target.try_into_cv()?)
+    ) -> Result<Hd2mCvMatchResult> {
+        self.run_match_cancellable(WorkspaceTarget::Rgba(target), true, &|| false)
     }
 
-    pub fn run_match_mat(
-        &mut self,
+    /// Same as [Self::run_match_rgba_independent], for OpenCV frames.
+    #[cfg(feature = "opencv")]
+    pub fn run_match_mat_independent(&self, target: &cv::core::Mat) -> Result<Hd2mCvMatchResult> {
+        self.run_match_cancellable(WorkspaceTarget::Mat(target), true, &|| false)
+    }
+
+    /// Matches `target` on the rayon thread pool, so that async callers aren't blocked while it
+    /// runs.
+    ///
+    /// Dropping the future cancels its match, which stops with [MatchCancelled] at its next stage
+    /// unless it already finished. Requests don't cancel each other, so that workers sharing the
+    /// manager each supersede their own request by dropping its future.
+    pub fn match_async(self: &Arc<Self>, target: image::RgbaImage) -> MatchFuture {
+        let manager = self.clone();
+        MatchFuture::spawn(move |is_dropped| {
+            manager.run_match_rgba_cancellable(&target, is_dropped)
+        })
+    }
+
+    /// Same as [Self::match_async], for OpenCV frames.
+    #[cfg(feature = "opencv")]
+    pub fn match_mat_async(self: &Arc<Self>, target: cv::core::Mat) -> MatchFuture {
+        let manager = self.clone();
+        MatchFuture::spawn(move |is_dropped| manager.run_match_mat_cancellable(&target, is_dropped))
+    }
+
+    /// Same as [Self::match_mat_async], matching only the `(x, y, width, height)` part of `target`
+    /// without copying it out of the frame.
+    ///
+    /// `target` being the whole screen, the templates are first resized for its size as with
+    /// [Self::use_screen_size], on the rayon thread pool as well.
+    ///
+    /// HDR frames, `CV_16FC4` `Mat`s as taken by [crate::HdrFrame::from_mat], have their part
+    /// tone-mapped there too, see [Hd2mCvManagerConfig::tone_map_options].
+    #[cfg(feature = "opencv")]
+    pub fn match_mat_roi_async(
+        self: &Arc<Self>,
+        target: cv::core::Mat,
+        roi: (usize, usize, usize, usize),
+    ) -> MatchFuture {
+        let manager = self.clone();
+        MatchFuture::spawn(move |is_dropped| {
+            manager.use_screen_size(target.cols() as usize, target.rows() as usize)?;
+            if target.typ() == cv::core::CV_16FC4 {
+                let panel = crate::tone_map_hdr_frame(
+                    &crate::HdrFrame::from_mat(&target)?.crop(roi)?,
+                    &manager.tone_map_options,
+                )?;
+                return manager.run_match_rgba_cancellable(&panel, is_dropped);
+            }
+            let (x, y, width, height) = roi;
+            let rect = cv::core::Rect::new(x as i32, y as i32, width as i32, height as i32);
+            manager.run_match_mat_cancellable(&crate::MatView::roi(&target, rect)?, is_dropped)
+        })
+    }
+
+    fn run_match_rgba_cancellable(
+        &self,
+        target: &image::RgbaImage,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Result<Hd2mCvMatchResult> {
+        self.run_match_cancellable(WorkspaceTarget::Rgba(target), false, is_cancelled)
+    }
+
+    #[cfg(feature = "opencv")]
+    fn run_match_mat_cancellable(
+        &self,
         target: &cv::core::Mat,
-    ) -> Result<Vec<Vec<DirectionDescriptor>>> {
-        let screen_size = self
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Result<Hd2mCvMatchResult> {
+        self.run_match_cancellable(WorkspaceTarget::Mat(target), false, is_cancelled)
+    }
+
+    // Checks `is_cancelled` between the stages of the run. `independent` runs leave out the state
+    // kept across consecutive frames.
+    fn run_match_cancellable(
+        &self,
+        target: WorkspaceTarget,
+        independent: bool,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Result<Hd2mCvMatchResult> {
+        let (scaled, mut settings) = self.current_template()?;
+        if independent {
+            settings.skip_unchanged_frames = false;
+            settings.incremental_search = false;
+        }
+        let fingerprint = settings
+            .skip_unchanged_frames
+            .then(|| target.fingerprint())
+            .transpose()?;
+        if let Some(res) = self.reuse_last_frame(&scaled, &settings, fingerprint.as_ref()) {
+            return Ok(res);
+        }
+        ensure_not_cancelled(is_cancelled)?;
+        let mut snapshots = self.new_debug_snapshots();
+        let res = match settings.pyramid_scale {
+            Some(pyramid_scale) => self.run_match_pyramid(
+                &scaled,
+                &settings,
+                target,
+                pyramid_scale,
+                &mut snapshots,
+                is_cancelled,
+            )?,
+            None => {
+                let (width, height) = target.size();
+                scaled.matcher.prepare_frame_size(width, height)?;
+                self.run_match_full_frame(&scaled, &settings, target, &mut snapshots, is_cancelled)?
+            }
+        };
+        self.store_debug_snapshots(snapshots);
+        self.store_last_frame(scaled, settings, fingerprint, &res);
+        Ok(res)
+    }
+
+    // Returns the result of the last matched frame when `fingerprint` tells that the frame didn't
+    // change since, counting the hit or the miss. `None` when skipping unchanged frames is off.
+    fn reuse_last_frame(
+        &self,
+        scaled: &Arc<ScaledTemplateMatcher>,
+        settings: &SearchSettings,
+        fingerprint: Option<&FrameFingerprint>,
+    ) -> Option<Hd2mCvMatchResult> {
+        let fingerprint = fingerprint?;
+        let last_frame = lock(&self.last_frame);
+        let last_frame = last_frame.as_ref().filter(|last| {
+            Arc::ptr_eq(&last.scaled, scaled)
+                && last.settings == *settings
+                && last.fingerprint.matches(fingerprint, FINGERPRINT_TOLERANCE)
+        });
+        match last_frame {
+            Some(last) => {
+                self.frame_cache_hits.fetch_add(1, Ordering::Relaxed);
+                Some(last.result.clone())
+            }
+            None => {
+                self.frame_cache_misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn store_last_frame(
+        &self,
+        scaled: Arc<ScaledTemplateMatcher>,
+        settings: SearchSettings,
+        fingerprint: Option<FrameFingerprint>,
+        result: &Hd2mCvMatchResult,
+    ) {
+        if let Some(fingerprint) = fingerprint {
+            *lock(&self.last_frame) = Some(MatchedFrame {
+                fingerprint,
+                scaled,
+                settings,
+                result: result.clone(),
+            });
+        }
+    }
+
+    /// Returns how many frames were skipped or matched since the manager was made or the counters
+    /// were reset, see [Hd2mCvSearchOptions::skip_unchanged_frames].
+    pub fn frame_cache_stats(&self) -> Hd2mCvFrameCacheStats {
+        Hd2mCvFrameCacheStats {
+            hits: self.frame_cache_hits.load(Ordering::Relaxed),
+            misses: self.frame_cache_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_frame_cache_stats(&self) {
+        self.frame_cache_hits.store(0, Ordering::Relaxed);
+        self.frame_cache_misses.store(0, Ordering::Relaxed);
+    }
+
+    // Matches the whole frame, or only the windows around the tracked rows with the incremental
+    // search.
+    fn run_match_full_frame(
+        &self,
+        scaled: &Arc<ScaledTemplateMatcher>,
+        settings: &SearchSettings,
+        target: WorkspaceTarget,
+        snapshots: &mut Option<Vec<Hd2mCvDebugSnapshot>>,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Result<Hd2mCvMatchResult> {
+        let run = WorkspaceRun {
+            pools: &self.workspaces,
+            scaled,
+            settings,
+            target,
+            is_cancelled,
+        };
+        let frame_size = target.size();
+        let tiling = scaled.matcher.read().row_tiling();
+        if let Some((tracked, tiling)) = self.tracked_rows(scaled, settings, frame_size).zip(tiling)
+        {
+            // Padded by a chunk so that the row search sees the same chunks around each row as in
+            // the full frame, even after the row moved by a few pixels.
+            let windows = tracked.windows(tiling, settings.chunk_size);
+            let (_, template_height) = scaled
+                .matcher
+                .read()
+                .template_size()
+                .ok_or(anyhow::anyhow!("No template registered"))?;
+            // Rows showing up outside of the windows are only found by a full search. The bands are
+            // left unpadded, since only the rows the arrows start at have to be in the windows.
+            let bands = self.find_coarse_bands(
+                scaled,
+                settings,
+                target,
+                INCREMENTAL_COARSE_SCALE,
+                0,
+                is_cancelled,
+            )?;
+            let in_windows = bands.iter().all(|&(start, end)| {
+                windows.iter().any(|&window| {
+                    let (top, bottom) = window_response_rows(tiling, window, frame_size.1);
+                    top <= start && end.saturating_sub(template_height) <= bottom
+                })
+            });
+            if in_windows {
+                let res = run.run(Some((&windows, tiling)), snapshots)?;
+                if tracked.still_holds(&res) {
+                    self.track_rows(
+                        scaled,
+                        settings,
+                        frame_size,
+                        &res,
+                        tracked.incremental_runs + 1,
+                    );
+                    return Ok(res);
+                }
+                if let Some(snapshots) = snapshots.as_mut() {
+                    snapshots.clear();
+                }
+            }
+        }
+        let res = run.run(None, snapshots)?;
+        if settings.incremental_search {
+            self.track_rows(scaled, settings, frame_size, &res, 0);
+        }
+        Ok(res)
+    }
+
+    // Returns the rows of the incremental search when they were found with the same templates and
+    // settings in a frame of the same size, unless a full search is due.
+    fn tracked_rows(
+        &self,
+        scaled: &Arc<ScaledTemplateMatcher>,
+        settings: &SearchSettings,
+        frame_size: (usize, usize),
+    ) -> Option<TrackedRows> {
+        // Picking the threshold from the responses needs all of them, not only the windows.
+        if !settings.incremental_search || settings.threshold_mode != ThresholdMode::Fixed {
+            return None;
+        }
+        lock(&self.tracked_rows)
+            .as_ref()
+            .filter(|tracked| {
+                tracked.frame_size == frame_size
+                    && Arc::ptr_eq(&tracked.scaled, scaled)
+                    && tracked.settings == *settings
+                    && tracked.incremental_runs < INCREMENTAL_REFRESH_RUNS
+                    && !tracked.commands.is_empty()
+            })
+            .cloned()
+    }
+
+    fn track_rows(
+        &self,
+        scaled: &Arc<ScaledTemplateMatcher>,
+        settings: &SearchSettings,
+        frame_size: (usize, usize),
+        res: &Hd2mCvMatchResult,
+        incremental_runs: usize,
+    ) {
+        *lock(&self.tracked_rows) = Some(TrackedRows {
+            frame_size,
+            scaled: scaled.clone(),
+            settings: *settings,
+            commands: res.commands.clone(),
+            incremental_runs,
+        });
+    }
+
+    // Coarse-to-fine search: the downscaled frame only tells which rows have arrows, and those rows
+    // are then matched in full resolution.
+    fn run_match_pyramid(
+        &self,
+        scaled: &ScaledTemplateMatcher,
+        settings: &SearchSettings,
+        target: WorkspaceTarget,
+        pyramid_scale: f64,
+        snapshots: &mut Option<Vec<Hd2mCvDebugSnapshot>>,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Result<Hd2mCvMatchResult> {
+        let (width, _) = target.size();
+        let bands = self.find_coarse_bands(
+            scaled,
+            settings,
+            target,
+            pyramid_scale,
+            // Covers the rows lost to rounding in the coarse frame, plus the blur and edge kernels.
+            (1.0 / pyramid_scale).ceil() as usize + 2,
+            is_cancelled,
+        )?;
+        let (backend, template_size) = {
+            let matcher = scaled.matcher.read();
+            (matcher.backend(), matcher.template_size())
+        };
+        let (_, template_height) =
+            template_size.ok_or(anyhow::anyhow!("No template registered"))?;
+
+        let mut band_results = Vec::new();
+        if !bands.is_empty() {
+            // The bands are sliced from the edges of the whole frame, which are the ones the full
+            // search matches, rather than pre-processing each band with its own borders.
+            let edges = target.pre_process(backend)?;
+            for (start, end) in bands {
+                if end - start < template_height {
+                    continue;
+                }
+                ensure_not_cancelled(is_cancelled)?;
+                scaled.matcher.prepare_frame_size(width, end - start)?;
+                let responses: Vec<_> = scaled
+                    .matcher
+                    .read()
+                    .match_templates_pre_processed(&edges.slice(nd::s![start..end, ..]))?
+                    .into_iter()
+                    .map(nd::Array2::from)
+                    .collect();
+                band_results.push((start, responses));
+            }
+        }
+
+        // The bands are parts of the same frame, so they share a single threshold.
+        let threshold =
+            settings.select_threshold(band_results.iter().map(|(_, responses)| &responses[..]));
+        let mut descriptors = Vec::new();
+        let mut direction_buffer = nd::Array2::from_elem((0, 0), None);
+        for (start, responses) in band_results {
+            for mut row in settings.find_commands(
+                &responses,
+                &mut direction_buffer,
+                start,
+                threshold,
+                snapshots,
+            )? {
+                for desc in row.iter_mut() {
+                    desc.position.y += start;
+                }
+                descriptors.push(row);
+            }
+        }
+        Ok(Hd2mCvMatchResult {
+            commands: descriptors,
+            threshold,
+        })
+    }
+
+    // Returns the `(start, end)` row bands of the frame that likely have arrows, as told by a
+    // search on a copy of it downscaled by `pyramid_scale`, grown by `padding` on both sides.
+    fn find_coarse_bands(
+        &self,
+        scaled: &ScaledTemplateMatcher,
+        settings: &SearchSettings,
+        target: WorkspaceTarget,
+        pyramid_scale: f64,
+        padding: usize,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Result<Vec<(usize, usize)>> {
+        let coarse_matcher = {
+            let mut coarse_matcher = lock(&scaled.coarse_matcher);
+            match &*coarse_matcher {
+                Some((scale, matcher)) if *scale == pyramid_scale => matcher.clone(),
+                _ => {
+                    // Resized from the original templates rather than the scaled ones to avoid
+                    // resampling twice.
+                    let matcher = Arc::new(SharedMatcher::new(
+                        self.template_original
+                            .with_resized_scale(scaled.scale * pyramid_scale)?,
+                    ));
+                    *coarse_matcher = Some((pyramid_scale, matcher.clone()));
+                    matcher
+                }
+            }
+        };
+
+        let (width, height) = target.size();
+        let coarse_size = (
+            ((width as f64 * pyramid_scale).round() as usize).max(1),
+            ((height as f64 * pyramid_scale).round() as usize).max(1),
+        );
+        coarse_matcher.prepare_frame_size(coarse_size.0, coarse_size.1)?;
+        let coarse_results = target.match_downscaled(&coarse_matcher.read(), coarse_size)?;
+        ensure_not_cancelled(is_cancelled)?;
+
+        let (_, template_height) = scaled
+            .matcher
+            .read()
+            .template_size()
+            .ok_or(anyhow::anyhow!("No template registered"))?;
+        Ok(find_candidate_bands(
+            &coarse_results,
+            settings.pyramid_threshold,
+            pyramid_scale,
+            template_height,
+            padding,
+            height,
+        ))
+    }
+
+    /// Returns the `(width, height)` of the templates for the current screen size.
+    pub fn template_size(&self) -> Option<(usize, usize)> {
+        let state = lock(&self.state);
+        state
+            .template_registry
+            .get(state.current_screen_size?)
+            .and_then(|scaled| scaled.matcher.read().template_size())
+    }
+
+    // Returns the templates of the current screen size along with the search settings, so that a
+    // run keeps using them even if another worker changes them in the meantime.
+    fn current_template(&self) -> Result<(Arc<ScaledTemplateMatcher>, SearchSettings)> {
+        let state = lock(&self.state);
+        let screen_size = state
             .current_screen_size
             .ok_or(anyhow::anyhow!("Target screen size not registered"))?;
-
-        let matching_template = self
+        let scaled = state
             .template_registry
-            .get(&screen_size)
+            .get(screen_size)
             .ok_or(anyhow::anyhow!(
                 "Resized template not found for target size"
             ))?;
+        Ok((scaled.clone(), state.search))
+    }
 
-        let res_up = matching_template.up.match_template(target)?;
-        let res_down = matching_template.down.match_template(target)?;
-        let res_right = matching_template.right.match_template(target)?;
-        let res_left = matching_template.left.match_template(target)?;
-
-        let arr_up = convert_mat_to_array2(&res_up.mat())?;
-        let arr_down = convert_mat_to_array2(&res_down.mat())?;
-        let arr_right = convert_mat_to_array2(&res_right.mat())?;
-        let arr_left = convert_mat_to_array2(&res_left.mat())?;
-
-        let descriptors = find_direction_commands(
-            &arr_up.view(),
-            &arr_down.view(),
-            &arr_right.view(),
-            &arr_left.view(),
-            Some(self.template_search_threshold),
-            Some(self.template_search_chunk_size),
-            Some(self.template_discarding_distance_threshold),
-        )?;
+    /// Keeps the response maps and direction buffers of the last run when enabled, see
+    /// [Self::debug_snapshots].
+    pub fn set_debug(&self, enabled: bool) {
+        *lock(&self.debug_snapshots) = enabled.then(Vec::new);
+    }
 
-        Ok(descriptors)
+    /// Returns the intermediate data of the last run, one snapshot per searched band.
+    ///
+    /// Always empty unless the debug mode is on.
+    pub fn debug_snapshots(&self) -> Vec<Hd2mCvDebugSnapshot> {
+        lock(&self.debug_snapshots).clone().unwrap_or_default()
+    }
+
+    /// Exports the snapshots of the last run to `dir`, prefixed by their index.
+    pub fn export_debug(&self, dir: impl AsRef<Path>) -> Result<()> {
+        for (i, snapshot) in self.debug_snapshots().iter().enumerate() {
+            snapshot.export(dir.as_ref(), &format!("{i}"))?;
+        }
+        Ok(())
+    }
+
+    fn new_debug_snapshots(&self) -> Option<Vec<Hd2mCvDebugSnapshot>> {
+        lock(&self.debug_snapshots).is_some().then(Vec::new)
     }
 
-    pub fn use_screen_size(&mut self, width: usize, height: usize) -> Result<()> {
-        if self.template_registry.contains_key(&(width, height)) {
+    fn store_debug_snapshots(&self, snapshots: Option<Vec<Hd2mCvDebugSnapshot>>) {
+        let mut debug_snapshots = lock(&self.debug_snapshots);
+        if let (Some(stored), Some(snapshots)) = (debug_snapshots.as_mut(), snapshots) {
+            *stored = snapshots;
+        }
+    }
+
+    /// Switches to a screen size, resizing the templates for it unless they are still registered
+    /// from a previous use.
+    pub fn use_screen_size(&self, width: usize, height: usize) -> Result<()> {
+        lock(&self.state).use_screen_size(
+            &self.template_original,
+            self.base_screen_size,
+            (width, height),
+        )
+    }
+
+    /// Changes the in-game HUD scale, dropping the templates resized for the previous one.
+    pub fn set_hud_scale(&self, hud_scale: f64) -> Result<()> {
+        anyhow::ensure!(
+            hud_scale > 0.0,
+            "HUD scale must be positive, but got {hud_scale}"
+        );
+        let mut state = lock(&self.state);
+        if hud_scale == state.hud_scale {
             return Ok(());
         }
+        state.hud_scale = hud_scale;
+        state.invalidate(&self.template_original, self.base_screen_size)
+    }
+
+    /// Drops the templates resized for all screen sizes, resizing them again for the current one.
+    pub fn invalidate(&self) -> Result<()> {
+        lock(&self.state).invalidate(&self.template_original, self.base_screen_size)
+    }
+
+    /// Sets how many screen sizes the resized templates are kept for, `4` by default.
+    ///
+    /// The least recently used sizes are dropped first, and the current one is always kept.
+    pub fn set_registry_capacity(&self, capacity: usize) {
+        lock(&self.state).template_registry.set_capacity(capacity);
+    }
+
+    /// Returns the screen size set by [Self::use_screen_size].
+    pub fn screen_size(&self) -> Option<(usize, usize)> {
+        lock(&self.state).current_screen_size
+    }
+
+    /// Returns the HUD layout of the current screen size.
+    pub fn layout(&self) -> Option<HudLayout> {
+        let state = lock(&self.state);
+        Some(
+            HudLayout::new(state.current_screen_size?, self.base_screen_size)
+                .with_hud_scale(state.hud_scale),
+        )
+    }
+
+    /// Returns the layout of the HUD on a `width` x `height` screen, without switching to it.
+    pub fn layout_for(&self, width: usize, height: usize) -> HudLayout {
+        let hud_scale = lock(&self.state).hud_scale;
+        HudLayout::new((width, height), self.base_screen_size).with_hud_scale(hud_scale)
+    }
+
+    pub fn set_search_options(&self, options: Hd2mCvSearchOptions) {
+        lock(&self.state).search.apply(options);
+    }
+}
+
+// Search of a frame in full resolution, with a workspace taken from the pool of its precision and
+// returned once done.
+//
+// INFO: A run that fails drops its workspaces rather than returning them, which only costs the
+// next run the allocation of new ones.
+struct WorkspaceRun<'a> {
+    pools: &'a WorkspacePools,
+    scaled: &'a ScaledTemplateMatcher,
+    settings: &'a SearchSettings,
+    target: WorkspaceTarget<'a>,
+    is_cancelled: &'a dyn Fn() -> bool,
+}
+
+impl WorkspaceRun<'_> {
+    // Matches the whole frame, or only the `(start, end)` row ranges of `windows` when given, which
+    // start at a boundary of the tiles.
+    fn run(
+        &self,
+        windows: Option<(&[(usize, usize)], RowTiling)>,
+        snapshots: &mut Option<Vec<Hd2mCvDebugSnapshot>>,
+    ) -> Result<Hd2mCvMatchResult> {
+        let pools = self.pools;
+        match self.settings.response_precision {
+            ResponsePrecision::F32 => self.run_in(&pools.f32, windows, snapshots),
+            ResponsePrecision::F16 => self.run_in(&pools.f16, windows, snapshots),
+            ResponsePrecision::U8 => self.run_in(&pools.u8, windows, snapshots),
+        }
+    }
+
+    fn run_in<T: ResponseElement>(
+        &self,
+        pool: &Mutex<Vec<MatchWorkspace<T>>>,
+        windows: Option<(&[(usize, usize)], RowTiling)>,
+        snapshots: &mut Option<Vec<Hd2mCvDebugSnapshot>>,
+    ) -> Result<Hd2mCvMatchResult> {
+        let mut workspace = lock(pool).pop().unwrap_or_default();
+        match windows {
+            Some((windows, tiling)) => {
+                let mut window_workspace = lock(pool).pop().unwrap_or_default();
+                self.match_windows_into(windows, tiling, &mut window_workspace, &mut workspace)?;
+                // Returned below the other one, so that each keeps being taken for the same size.
+                lock(pool).push(window_workspace);
+            }
+            None => self
+                .target
+                .match_into(&self.scaled.matcher.read(), &mut workspace)?,
+        }
+        ensure_not_cancelled(self.is_cancelled)?;
+        let res = self
+            .settings
+            .find_workspace_commands(&mut workspace, snapshots)?;
+        lock(pool).push(workspace);
+        Ok(res)
+    }
+
+    // Matches each window with `window_workspace`, copying its responses into the full-frame maps
+    // of `workspace`. Positions outside of the windows get no response, as if nothing matched
+    // there.
+    fn match_windows_into<T: ResponseElement>(
+        &self,
+        windows: &[(usize, usize)],
+        tiling: RowTiling,
+        window_workspace: &mut MatchWorkspace<T>,
+        workspace: &mut MatchWorkspace<T>,
+    ) -> Result<()> {
+        let matcher = self.scaled.matcher.read();
+        let (_, frame_height) = self.target.size();
+        for (i, &(start, end)) in windows.iter().enumerate() {
+            ensure_not_cancelled(self.is_cancelled)?;
+            self.target
+                .match_rows_into(&matcher, (start, end), window_workspace)?;
+            let window_responses = window_workspace.responses();
+            if i == 0 {
+                workspace
+                    .responses
+                    .resize_with(window_responses.len(), Default::default);
+                for (res, window_res) in workspace.responses.iter_mut().zip(window_responses) {
+                    // Each map is as much shorter than the frame as its window map is than the
+                    // window.
+                    let rows = frame_height - (end - start - window_res.nrows());
+                    crate::imgproc::ensure_shape(res, (rows, window_res.ncols()));
+                    res.fill(T::default());
+                }
+            }
+            let (top, bottom) = window_response_rows(tiling, (start, end), frame_height);
+            for (res, window_res) in workspace.responses.iter_mut().zip(window_responses) {
+                let (top, bottom) = (top - start, (bottom - start).min(window_res.nrows()));
+                if top < bottom {
+                    res.slice_mut(nd::s![start + top..start + bottom, ..])
+                        .assign(&window_res.slice(nd::s![top..bottom, ..]));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Returns the `(top, bottom)` frame rows whose responses matching the `(start, end)` window gives
+// the same as the full frame: those of the tiles made of rows clear of its margins. The edges of
+// the frame are kept, since the full frame has the same border there.
+fn window_response_rows(
+    tiling: RowTiling,
+    (start, end): (usize, usize),
+    frame_height: usize,
+) -> (usize, usize) {
+    let top = match start {
+        0 => 0,
+        _ => (start + INCREMENTAL_WINDOW_MARGIN).div_ceil(tiling.rows) * tiling.rows,
+    };
+    let bottom = if end == frame_height {
+        frame_height
+    } else {
+        (end - INCREMENTAL_WINDOW_MARGIN)
+            .checked_sub(tiling.span)
+            .map_or(0, |last| (last / tiling.rows + 1) * tiling.rows)
+    };
+    (top, bottom.max(top))
+}
+
+fn ensure_not_cancelled(is_cancelled: &dyn Fn() -> bool) -> Result<()> {
+    if is_cancelled() {
+        return Err(MatchCancelled.into());
+    }
+    Ok(())
+}
+
+// INFO: Poisoning is ignored since the locked state is only ever replaced as a whole, so a worker
+// panicking while holding it can't leave it half-updated.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_on, render_panel_fixture, PanelFixtureConfig, PanelFixtureTemplates};
+
+    fn new_manager() -> Result<Hd2mCvManager> {
+        Hd2mCvManager::new(new_config())
+    }
+
+    fn new_config() -> Hd2mCvManagerConfig {
+        Hd2mCvManagerConfig {
+            search_options: Some(Hd2mCvSearchOptions {
+                threshold: Some(0.7),
+                ..Default::default()
+            }),
+            ..fixture_templates().manager_config()
+        }
+    }
 
-        // Since Helldivers 2 will not scale the ui along the height, we will use the width as the base scale.
-        let inferred_scale = width as f64 / self.base_screen_size.0 as f64;
+    fn registered_screen_sizes(manager: &Hd2mCvManager) -> Vec<(usize, usize)> {
+        lock(&manager.state)
+            .template_registry
+            .entries
+            .keys()
+            .copied()
+            .collect()
+    }
 
-        let mut template_up = self.template_original.up.clone();
-        template_up.resize_template_scale(inferred_scale)?;
-        let mut template_down = self.template_original.down.clone();
-        template_down.resize_template_scale(inferred_scale)?;
-        let mut template_right = self.template_original.right.clone();
-        template_right.resize_template_scale(inferred_scale)?;
-        let mut template_left = self.template_original.left.clone();
-        template_left.resize_template_scale(inferred_scale)?;
+    fn render_frame() -> Result<image::RgbaImage> {
+        Ok(image::imageops::crop_imm(&render_panel()?, 100, 120, 200, 60).to_image())
+    }
 
-        let template_resized = DirectionTemplateMatcherSet::new(
-            template_up,
-            template_down,
-            template_right,
-            template_left,
+    fn fixture_templates() -> PanelFixtureTemplates {
+        PanelFixtureTemplates::bundled().unwrap()
+    }
+
+    fn render_panel() -> Result<image::RgbaImage> {
+        let fixture = render_panel_fixture(
+            &fixture_templates(),
+            &PanelFixtureConfig {
+                sequences: vec![vec![Direction::Down, Direction::Right, Direction::Down]],
+                screen_size: (2560, 1440),
+                ..Default::default()
+            },
         )?;
+        Ok(fixture.image)
+    }
+
+    #[test]
+    fn test_hud_layout() -> Result<()> {
+        let manager = new_manager()?;
+        manager.use_screen_size(3440, 1440)?;
+        assert_eq!(manager.template_size(), Some((20, 21)));
+        assert_eq!(
+            manager.layout().map(|l| l.panel_roi()),
+            Some((0, 0, 420, 670))
+        );
 
-        let rep_template_size = template_resized.up.mat().size()?;
-        self.template_registry
-            .insert((width, height), template_resized);
-        self.current_screen_size = Some((width, height));
-        self.set_search_options(Hd2mCvSearchOptions {
-            search_chunk_size: Some(rep_template_size.height as usize * 2),
-            discarding_distance_threshold: Some(rep_template_size.width as f64 + 3.0),
+        manager.set_hud_scale(1.5)?;
+        assert_eq!(manager.template_size(), Some((30, 32)));
+        assert_eq!(manager.layout().map(|l| l.hud_scale()), Some(1.5));
+        assert!(manager.set_hud_scale(0.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_registry() -> Result<()> {
+        let manager = new_manager()?;
+        manager.set_registry_capacity(2);
+        manager.use_screen_size(2560, 1440)?;
+        manager.use_screen_size(1920, 1080)?;
+        // Going back to a registered size still switches to it.
+        manager.use_screen_size(2560, 1440)?;
+        assert_eq!(manager.screen_size(), Some((2560, 1440)));
+        assert_eq!(manager.template_size(), Some((20, 21)));
+        assert_eq!(lock(&manager.state).search.chunk_size, 42);
+
+        // 1920x1080 is the least recently used one.
+        manager.use_screen_size(3840, 2160)?;
+        assert_eq!(
+            registered_screen_sizes(&manager),
+            [(2560, 1440), (3840, 2160)]
+        );
+
+        manager.invalidate()?;
+        assert_eq!(registered_screen_sizes(&manager), [(3840, 2160)]);
+        assert_eq!(manager.template_size(), Some((30, 32)));
+
+        manager.set_registry_capacity(0);
+        assert_eq!(manager.screen_size(), Some((3840, 2160)));
+        assert_eq!(manager.template_size(), Some((30, 32)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_registry_resize_storm() -> Result<()> {
+        let manager = new_manager()?;
+        // Tall enough for the search chunks of 2560x1440.
+        let frame = image::imageops::crop_imm(&render_panel()?, 100, 100, 200, 100).to_image();
+        // A window being dragged through many sizes, switching to fullscreen every other frame,
+        // then back to where it was.
+        for i in 0..200 {
+            let width = match i % 2 {
+                0 => 1920,
+                _ => 1280 + (i * 37) % 1280,
+            };
+            manager.use_screen_size(width, width * 9 / 16)?;
+            assert_eq!(manager.screen_size(), Some((width, width * 9 / 16)));
+            assert!(registered_screen_sizes(&manager).len() <= DEFAULT_REGISTRY_CAPACITY);
+        }
+        assert!(registered_screen_sizes(&manager).contains(&(1920, 1080)));
+        manager.use_screen_size(2560, 1440)?;
+        assert_eq!(manager.template_size(), Some((20, 21)));
+        assert_eq!(
+            registered_screen_sizes(&manager).len(),
+            DEFAULT_REGISTRY_CAPACITY
+        );
+
+        // Same as without the resizes.
+        let fresh = new_manager()?;
+        fresh.use_screen_size(2560, 1440)?;
+        let expected = fresh.run_match_rgba(&frame)?;
+        assert!(!expected.commands.is_empty());
+        assert_eq!(manager.run_match_rgba(&frame)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_precision() -> Result<()> {
+        let manager = Hd2mCvManager::new(Hd2mCvManagerConfig {
+            backend: Some(MatchingBackend::NativeFft),
+            ..new_config()
+        })?;
+        manager.use_screen_size(2560, 1440)?;
+        manager.set_debug(true);
+        let frame = image::imageops::crop_imm(&render_panel()?, 100, 100, 200, 100).to_image();
+        let expected = manager.run_match_rgba(&frame)?;
+        assert!(!expected.commands.is_empty());
+
+        for precision in [ResponsePrecision::F16, ResponsePrecision::U8] {
+            manager.set_search_options(Hd2mCvSearchOptions {
+                response_precision: Some(precision),
+                ..Default::default()
+            });
+            let res = manager.run_match_rgba(&frame)?;
+            // Same arrows, only the confidences are narrowed.
+            assert_eq!(res.commands.len(), expected.commands.len());
+            for (row, expected_row) in res.commands.iter().zip(&expected.commands) {
+                assert_eq!(row.len(), expected_row.len());
+                for (desc, expected_desc) in row.iter().zip(expected_row) {
+                    assert_eq!(desc.direction, expected_desc.direction);
+                    assert_eq!(desc.position, expected_desc.position);
+                    assert!((desc.confidence - expected_desc.confidence).abs() <= 1.0 / 255.0);
+                }
+            }
+            // Snapshots are widened back to `f32`.
+            assert_eq!(manager.debug_snapshots()[0].results.len(), 4);
+        }
+        assert_eq!(lock(&manager.workspaces.f16).len(), 1);
+        assert_eq!(lock(&manager.workspaces.u8).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_unchanged_frames() -> Result<()> {
+        let manager = Hd2mCvManager::new(Hd2mCvManagerConfig {
+            backend: Some(MatchingBackend::NativeFft),
+            ..new_config()
+        })?;
+        manager.use_screen_size(2560, 1440)?;
+        let panel = render_panel()?;
+        let frame = image::imageops::crop_imm(&panel, 100, 100, 200, 100).to_image();
+        // Off by default.
+        manager.run_match_rgba(&frame)?;
+        assert_eq!(
+            manager.frame_cache_stats(),
+            Hd2mCvFrameCacheStats::default()
+        );
+
+        manager.set_search_options(Hd2mCvSearchOptions {
+            skip_unchanged_frames: Some(true),
+            ..Default::default()
+        });
+        let expected = manager.run_match_rgba(&frame)?;
+        assert!(!expected.commands.is_empty());
+        assert_eq!(manager.run_match_rgba(&frame.clone())?, expected);
+        assert_eq!(
+            manager.frame_cache_stats(),
+            Hd2mCvFrameCacheStats { hits: 1, misses: 1 }
+        );
+
+        // The first arrow going dark is a change.
+        let mut changed = frame.clone();
+        image::imageops::replace(
+            &mut changed,
+            &image::RgbaImage::from_pixel(40, 100, image::Rgba([0, 0, 0, 255])),
+            40,
+            0,
+        );
+        let res = manager.run_match_rgba(&changed)?;
+        assert_ne!(res, expected);
+        assert_eq!(manager.frame_cache_stats().misses, 2);
+        assert_eq!(manager.run_match_rgba(&changed)?, res);
+        assert_eq!(manager.frame_cache_stats().hits, 2);
+
+        // So are new templates and new search options.
+        manager.invalidate()?;
+        manager.run_match_rgba(&changed)?;
+        manager.set_search_options(Hd2mCvSearchOptions {
+            threshold: Some(0.75),
             ..Default::default()
         });
+        manager.run_match_rgba(&changed)?;
+        assert_eq!(
+            manager.frame_cache_stats(),
+            Hd2mCvFrameCacheStats { hits: 2, misses: 4 }
+        );
 
+        manager.reset_frame_cache_stats();
+        assert_eq!(
+            manager.frame_cache_stats(),
+            Hd2mCvFrameCacheStats::default()
+        );
         Ok(())
     }
 
-    pub fn set_search_options(&mut self, options: Hd2mCvSearchOptions) {
-        self.template_search_threshold =
-            options.threshold.unwrap_or(self.template_search_threshold);
-        self.template_search_chunk_size = options
-            .search_chunk_size
-            .unwrap_or(self.template_search_chunk_size);
-        self.template_discarding_distance_threshold = options
-            .discarding_distance_threshold
-            .unwrap_or(self.template_discarding_distance_threshold);
+    #[test]
+    fn test_skip_unchanged_frames_mirrored_arrow() -> Result<()> {
+        let manager = Hd2mCvManager::new(Hd2mCvManagerConfig {
+            backend: Some(MatchingBackend::NativeFft),
+            ..new_config()
+        })?;
+        manager.use_screen_size(2560, 1440)?;
+        manager.set_search_options(Hd2mCvSearchOptions {
+            skip_unchanged_frames: Some(true),
+            ..Default::default()
+        });
+        let templates = fixture_templates();
+        let (x, y, width, height) = manager.layout_for(2560, 1440).panel_roi();
+        let render = |last| -> Result<image::RgbaImage> {
+            use Direction::*;
+            let fixture = render_panel_fixture(
+                &templates,
+                &PanelFixtureConfig {
+                    sequences: vec![vec![Down, Right, Down], vec![Down, Left, last]],
+                    screen_size: (2560, 1440),
+                    ..Default::default()
+                },
+            )?;
+            let (x, y, width, height) = (x as u32, y as u32, width as u32, height as u32);
+            Ok(image::imageops::crop_imm(&fixture.image, x, y, width, height).to_image())
+        };
+
+        let res = manager.run_match_rgba(&render(Direction::Right)?)?;
+        assert_eq!(res.commands[1][2].direction, Direction::Right);
+        // Turning the arrow around keeps about as many bright pixels in the panel, only their
+        // shape changes.
+        let res = manager.run_match_rgba(&render(Direction::Left)?)?;
+        assert_eq!(res.commands[1][2].direction, Direction::Left);
+        assert_eq!(
+            manager.frame_cache_stats(),
+            Hd2mCvFrameCacheStats { hits: 0, misses: 2 }
+        );
+        Ok(())
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct DirectionTemplateMatcherSet {
-    pub up: TemplateMatcher,
-    pub down: TemplateMatcher,
-    pub right: TemplateMatcher,
-    pub left: TemplateMatcher,
-}
-
-impl DirectionTemplateMatcherSet {
-    pub fn new(
-        template_up: TemplateMatcher,
-        template_down: TemplateMatcher,
-        template_right: TemplateMatcher,
-        template_left: TemplateMatcher,
-    ) -> Result<Self> {
-        Ok(Self {
-            up: template_up,
-            down: template_down,
-            right: template_right,
-            left: template_left,
+    #[test]
+    fn test_incremental_search() -> Result<()> {
+        use Direction::*;
+        let templates = fixture_templates();
+        let render = |sequences: Vec<Vec<Direction>>, seed, top| -> Result<image::RgbaImage> {
+            let fixture = render_panel_fixture(
+                &templates,
+                &PanelFixtureConfig {
+                    sequences,
+                    screen_size: (2560, 1440),
+                    noise_amplitude: Some(4),
+                    seed,
+                    ..Default::default()
+                },
+            )?;
+            Ok(image::imageops::crop_imm(&fixture.image, 100, top, 260, 320).to_image())
+        };
+        let rows = vec![
+            vec![Down, Right, Down],
+            vec![Right, Left],
+            vec![Left, Down, Right, Up],
+        ];
+        let changed = vec![rows[0].clone(), vec![Right, Down], rows[2].clone()];
+        let shortened = vec![rows[0].clone(), rows[2].clone()];
+        let appended = vec![rows[0].clone(), rows[2].clone(), rows[1].clone()];
+        // Each frame along with whether it is expected to be searched incrementally.
+        let frames = [
+            (render(rows.clone(), 1, 80)?, false),
+            // New noise.
+            (render(rows.clone(), 2, 80)?, true),
+            // The panel moving down by a few pixels.
+            (render(rows.clone(), 2, 77)?, true),
+            // An arrow changing direction.
+            (render(changed, 3, 77)?, true),
+            // A row going away.
+            (render(shortened.clone(), 4, 77)?, false),
+            (render(shortened.clone(), 5, 77)?, true),
+            // And coming back.
+            (render(rows.clone(), 6, 77)?, false),
+            (render(rows, 7, 77)?, true),
+            // A row showing up below the windows, which leaves the rows in them as they were.
+            (render(shortened, 8, 77)?, false),
+            (render(appended.clone(), 9, 77)?, false),
+            (render(appended, 10, 77)?, true),
+        ];
+
+        for backend in [MatchingBackend::Native, MatchingBackend::NativeFft] {
+            let config = || Hd2mCvManagerConfig {
+                backend: Some(backend),
+                ..new_config()
+            };
+            let full = Hd2mCvManager::new(config())?;
+            full.use_screen_size(2560, 1440)?;
+            let incremental = Hd2mCvManager::new(config())?;
+            incremental.use_screen_size(2560, 1440)?;
+            incremental.set_search_options(Hd2mCvSearchOptions {
+                incremental_search: Some(true),
+                ..Default::default()
+            });
+
+            for (i, (frame, is_incremental)) in frames.iter().enumerate() {
+                let expected = full.run_match_rgba(frame)?;
+                assert!(!expected.commands.is_empty());
+                assert_eq!(
+                    incremental.run_match_rgba(frame)?,
+                    expected,
+                    "{backend:?} frame {i}"
+                );
+                let incremental_runs = lock(&incremental.tracked_rows)
+                    .as_ref()
+                    .map_or(0, |tracked| tracked.incremental_runs);
+                assert_eq!(
+                    incremental_runs > 0,
+                    *is_incremental,
+                    "{backend:?} frame {i}"
+                );
+            }
+
+            // The adaptive threshold modes always search the whole frame.
+            incremental.set_search_options(Hd2mCvSearchOptions {
+                threshold_mode: Some(ThresholdMode::Otsu),
+                ..Default::default()
+            });
+            let (frame, _) = &frames[0];
+            incremental.run_match_rgba(frame)?;
+            incremental.run_match_rgba(frame)?;
+            assert!(lock(&incremental.tracked_rows)
+                .as_ref()
+                .is_some_and(|tracked| tracked.incremental_runs == 0));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_pyramid_search() -> Result<()> {
+        use Direction::*;
+        let fixture = render_panel_fixture(
+            &fixture_templates(),
+            &PanelFixtureConfig {
+                sequences: vec![
+                    vec![Down, Right, Down],
+                    vec![Right, Left],
+                    vec![Left, Down, Right, Up],
+                ],
+                screen_size: (2560, 1440),
+                noise_amplitude: Some(4),
+                seed: 1,
+                ..Default::default()
+            },
+        )?;
+        let frame = image::imageops::crop_imm(&fixture.image, 100, 80, 260, 320).to_image();
+        for backend in [MatchingBackend::Native, MatchingBackend::NativeFft] {
+            let config = || Hd2mCvManagerConfig {
+                backend: Some(backend),
+                ..new_config()
+            };
+            let full = Hd2mCvManager::new(config())?;
+            full.use_screen_size(2560, 1440)?;
+            let pyramid = Hd2mCvManager::new(config())?;
+            pyramid.use_screen_size(2560, 1440)?;
+            pyramid.set_search_options(Hd2mCvSearchOptions {
+                pyramid_scale: Some(0.5),
+                ..Default::default()
+            });
+
+            let expected = full.run_match_rgba(&frame)?;
+            assert_eq!(expected.commands.len(), 3);
+            let res = pyramid.run_match_rgba(&frame)?;
+            assert_eq!(res.threshold, expected.threshold);
+            // The bands see the same edges as the full frame, only the sums are taken over fewer
+            // rows.
+            assert_eq!(res.commands.len(), expected.commands.len(), "{backend:?}");
+            for (row, expected_row) in res.commands.iter().zip(&expected.commands) {
+                assert_eq!(row.len(), expected_row.len(), "{backend:?}");
+                for (desc, expected_desc) in row.iter().zip(expected_row) {
+                    assert_eq!(desc.direction, expected_desc.direction);
+                    assert_eq!(desc.position, expected_desc.position);
+                    assert!((desc.confidence - expected_desc.confidence).abs() <= 1e-4);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_manager() -> Result<()> {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Hd2mCvManager>();
+
+        // The FFT backend keeps state for the frame size, which the workers keep switching.
+        let manager = Hd2mCvManager::new(Hd2mCvManagerConfig {
+            backend: Some(MatchingBackend::NativeFft),
+            ..new_config()
+        })?;
+        manager.use_screen_size(2560, 1440)?;
+        let panel = render_panel()?;
+        let frames = [
+            image::imageops::crop_imm(&panel, 100, 100, 200, 100).to_image(),
+            image::imageops::crop_imm(&panel, 100, 100, 240, 160).to_image(),
+        ];
+        let expected = frames
+            .iter()
+            .map(|frame| manager.run_match_rgba(frame))
+            .collect::<Result<Vec<_>>>()?;
+        assert!(expected.iter().all(|res| !res.commands.is_empty()));
+
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|worker| {
+                    let manager = &manager;
+                    let (frames, expected) = (&frames, &expected);
+                    scope.spawn(move || -> Result<()> {
+                        for i in 0..6 {
+                            let index = (worker + i) % frames.len();
+                            assert_eq!(manager.run_match_rgba(&frames[index])?, expected[index]);
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            // Updates that leave the results as they are, racing with the runs.
+            for i in 0..20 {
+                manager.invalidate()?;
+                manager.use_screen_size(2560, 1440)?;
+                manager.set_debug(i % 2 == 0);
+                assert_eq!(manager.template_size(), Some((20, 21)));
+            }
+            for worker in workers {
+                worker.join().unwrap()?;
+            }
+            Ok(())
         })
     }
+
+    #[test]
+    fn test_match_async() -> Result<()> {
+        let manager = Arc::new(new_manager()?);
+        manager.use_screen_size(2560, 1440)?;
+        let frame = image::imageops::crop_imm(&render_panel()?, 100, 100, 200, 100).to_image();
+        let expected = manager.run_match_rgba(&frame)?;
+        assert_eq!(block_on(manager.match_async(frame.clone()))?, expected);
+
+        // Requests of different callers waiting for the state don't cancel each other.
+        let state = lock(&manager.state);
+        let first = manager.match_async(frame.clone());
+        let second = manager.match_async(frame.clone());
+        drop(state);
+        assert_eq!(block_on(second)?, expected);
+        assert_eq!(block_on(first)?, expected);
+        // Stopped by its caller, as when the future is dropped.
+        assert!(manager
+            .run_match_rgba_cancellable(&frame, &|| true)
+            .unwrap_err()
+            .is::<MatchCancelled>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_adaptive_threshold() -> Result<()> {
+        let manager = new_manager()?;
+        manager.use_screen_size(2560, 1440)?;
+        let frame = render_frame()?;
+
+        let res = manager.run_match_rgba(&frame)?;
+        assert_eq!(res.threshold, 0.7);
+
+        manager.set_search_options(Hd2mCvSearchOptions {
+            threshold_mode: Some(ThresholdMode::StdDev(4.0)),
+            threshold_floor: Some(0.3),
+            threshold_ceiling: Some(0.95),
+            ..Default::default()
+        });
+        let res = manager.run_match_rgba(&frame)?;
+        assert!(
+            res.threshold >= 0.3 && res.threshold <= 0.95,
+            "{}",
+            res.threshold
+        );
+        assert_ne!(res.threshold, 0.7);
+        assert!(res
+            .commands
+            .iter()
+            .flatten()
+            .all(|desc| desc.confidence >= res.threshold));
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_snapshots() -> Result<()> {
+        let manager = new_manager()?;
+        manager.use_screen_size(2560, 1440)?;
+        let frame = render_frame()?;
+
+        manager.run_match_rgba(&frame)?;
+        assert!(manager.debug_snapshots().is_empty());
+
+        manager.set_debug(true);
+        manager.run_match_rgba(&frame)?;
+        manager.run_match_rgba(&frame)?;
+        let snapshots = manager.debug_snapshots();
+        assert_eq!(snapshots.len(), 1);
+        let snapshot = &snapshots[0];
+        assert_eq!(snapshot.row_offset, 0);
+        assert_eq!(snapshot.results.len(), 4);
+        assert_eq!(
+            snapshot.direction_buffer.dim(),
+            snapshot.results[0].to_search_layout().dim()
+        );
+        assert!(snapshot
+            .direction_buffer
+            .iter()
+            .any(|el| matches!(el, Some((Direction::Right, _)))));
+
+        let dir = tempfile::tempdir()?;
+        manager.export_debug(dir.path())?;
+        for name in [
+            "0_up.png",
+            "0_up.npy",
+            "0_left.npy",
+            "0_directions.png",
+            "0_directions.npy",
+            "0_confidence.npy",
+        ] {
+            assert!(dir.path().join(name).is_file(), "{name} is not exported");
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,309 @@
+use super::{BLUR_KERNEL_SIZE, CANNY_HIGH_THRESHOLD, CANNY_LOW_THRESHOLD};
+use crate::{MatchDescriptor, MatchingBackend};
+use anyhow::Result;
+use ndarray as nd;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+// Bump whenever the pre-processing or the resizing of the templates changes in a way that the
+// constants below don't capture, so that the entries made by older builds are no longer used.
+const CACHE_VERSION: u32 = 1;
+const CACHE_MAGIC: &[u8; 8] = b"HD2MTPL\0";
+const CACHE_EXTENSION: &str = "tpl";
+// Magic, version, key, rows, cols and the checksum of the data.
+const HEADER_LEN: usize = 8 + 4 + 8 + 8 + 8 + 8;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// Suffix of the entries being written, unique within the process so that concurrent writers of the
+// same key never share a temporary file.
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// On-disk cache of pre-processed templates, shared by [crate::TemplateMatcher]s.
+///
+/// Entries are keyed by the hash of the template, the pre-processing of the backend, including the
+/// OpenCV version it runs on, and the scales the template was resized with, so a change to any of them misses instead of returning a
+/// stale template. Each entry carries a checksum, and entries that fail it are removed and made
+/// again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateCache {
+    dir: PathBuf,
+}
+
+impl TemplateCache {
+    /// Opens the cache in `dir`, creating it if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Removes all entries of the cache.
+    pub fn clear(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == CACHE_EXTENSION) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the template stored under `key`, or makes and stores it with `make`.
+    pub(crate) fn get_or_insert_with(
+        &self,
+        key: u64,
+        make: impl FnOnce() -> Result<nd::Array2<f32>>,
+    ) -> Result<nd::Array2<f32>> {
+        if let Some(template) = self.get(key) {
+            return Ok(template);
+        }
+        let template = make()?;
+        // INFO: The cache only saves time, so failing to write it must not fail the matching.
+        let _ = self.insert(key, &template.view());
+        Ok(template)
+    }
+
+    pub(crate) fn get(&self, key: u64) -> Option<nd::Array2<f32>> {
+        let path = self.entry_path(key);
+        let bytes = fs::read(&path).ok()?;
+        let template = decode_entry(key, &bytes);
+        if template.is_none() {
+            // Corrupted or written by another version, removed so that it gets made again.
+            let _ = fs::remove_file(&path);
+        }
+        template
+    }
+
+    pub(crate) fn insert(&self, key: u64, template: &nd::ArrayView2<f32>) -> Result<()> {
+        let payload: Vec<u8> = template.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(CACHE_MAGIC);
+        bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&key.to_le_bytes());
+        bytes.extend_from_slice(&(template.nrows() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(template.ncols() as u64).to_le_bytes());
+        bytes.extend_from_slice(&fnv1a(FNV_OFFSET_BASIS, &payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+
+        // Written aside and renamed, so that readers never see a partially written entry.
+        let temp_path = self.dir.join(format!(
+            "{key:016x}.{}.{}.tmp",
+            std::process::id(),
+            NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp_path, &bytes)?;
+        if let Err(err) = fs::rename(&temp_path, self.entry_path(key)) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.{CACHE_EXTENSION}"))
+    }
+}
+
+/// Returns the hash of an encoded template, such as the bytes of its PNG file.
+///
+/// Passed to [MatchDescriptor::with_source_hash], it keys the cache without hashing the decoded
+/// template.
+pub fn hash_encoded_template(bytes: &[u8]) -> u64 {
+    fnv1a(FNV_OFFSET_BASIS, bytes)
+}
+
+/// Returns the cache key of the template of `descriptor` pre-processed by `backend`.
+pub(crate) fn template_key(descriptor: &MatchDescriptor, backend: MatchingBackend) -> Result<u64> {
+    let mut hash = fnv1a(FNV_OFFSET_BASIS, &CACHE_VERSION.to_le_bytes());
+    // Both native backends share the same pre-processing.
+    match backend {
+        MatchingBackend::Native | MatchingBackend::NativeFft => hash = fnv1a(hash, &[0]),
+        #[cfg(feature = "opencv")]
+        MatchingBackend::OpenCv => {
+            // The blur and Canny of another OpenCV release may not give the same edges.
+            hash = fnv1a(hash, &[1]);
+            hash = fnv1a(hash, opencv::core::get_version_string()?.as_bytes());
+        }
+    }
+    hash = fnv1a(hash, &(BLUR_KERNEL_SIZE as u64).to_le_bytes());
+    hash = fnv1a(hash, &CANNY_LOW_THRESHOLD.to_le_bytes());
+    hash = fnv1a(hash, &CANNY_HIGH_THRESHOLD.to_le_bytes());
+    Ok(match descriptor.source_hash() {
+        Some(source_hash) => fnv1a(hash, &source_hash.to_le_bytes()),
+        None => {
+            let template = &descriptor.template;
+            hash = fnv1a(hash, &template.width().to_le_bytes());
+            hash = fnv1a(hash, &template.height().to_le_bytes());
+            fnv1a(hash, template.as_raw())
+        }
+    })
+}
+
+/// Returns the cache key of the template of `key` resized by `scale`.
+///
+/// Chained since resizing twice doesn't give the same template as resizing once.
+pub(crate) fn resized_template_key(key: u64, scale: f64) -> u64 {
+    fnv1a(
+        fnv1a(FNV_OFFSET_BASIS, &key.to_le_bytes()),
+        &scale.to_le_bytes(),
+    )
+}
+
+// INFO: FNV-1a rather than `DefaultHasher`, whose output may change between Rust releases.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn decode_entry(key: u64, bytes: &[u8]) -> Option<nd::Array2<f32>> {
+    let (header, payload) = bytes.split_at_checked(HEADER_LEN)?;
+    let read_u64 = |offset: usize| {
+        u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap_or_default())
+    };
+    if &header[..8] != CACHE_MAGIC
+        || header[8..12] != CACHE_VERSION.to_le_bytes()
+        || read_u64(12) != key
+    {
+        return None;
+    }
+    let rows = usize::try_from(read_u64(20)).ok()?;
+    let cols = usize::try_from(read_u64(28)).ok()?;
+    if rows.checked_mul(cols)?.checked_mul(4)? != payload.len()
+        || read_u64(36) != fnv1a(FNV_OFFSET_BASIS, payload)
+    {
+        return None;
+    }
+    let data = payload
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+    nd::Array2::from_shape_vec((rows, cols), data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bundled_template_png, load_bundled_template, Direction, TemplateMatcher};
+
+    fn load_descriptors() -> Vec<MatchDescriptor> {
+        [Direction::Up, Direction::Right]
+            .map(|direction| {
+                let template = load_bundled_template(direction).unwrap();
+                MatchDescriptor::new(direction.label().to_owned(), template)
+            })
+            .to_vec()
+    }
+
+    fn entry_paths(cache: &TemplateCache) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(cache.dir())? {
+            paths.push(entry?.path());
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    #[test]
+    fn test_template_cache() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = TemplateCache::new(dir.path().join("templates"))?;
+        let descriptors = load_descriptors();
+        let backend = MatchingBackend::Native;
+        let uncached =
+            TemplateMatcher::with_backend(&descriptors, backend)?.with_resized_scale(0.75)?;
+
+        let matcher = TemplateMatcher::with_cache(&descriptors, backend, Some(cache.clone()))?
+            .with_resized_scale(0.75)?;
+        // The original and the resized template of each descriptor.
+        assert_eq!(entry_paths(&cache)?.len(), 4);
+        let cached = TemplateMatcher::with_cache(&descriptors, backend, Some(cache.clone()))?
+            .with_resized_scale(0.75)?;
+        assert_eq!(entry_paths(&cache)?.len(), 4);
+        for descriptor in descriptors.iter() {
+            let label = &descriptor.label;
+            assert_eq!(matcher.template_for(label), uncached.template_for(label));
+            assert_eq!(cached.template_for(label), uncached.template_for(label));
+        }
+
+        // Loaded from the cache rather than made again.
+        let key = template_key(&descriptors[0], backend)?;
+        let stored = nd::Array2::from_elem((2, 3), 0.5);
+        cache.insert(key, &stored.view())?;
+        let matcher = TemplateMatcher::with_cache(&descriptors, backend, Some(cache.clone()))?;
+        assert_eq!(matcher.template_for("up"), Some(&stored));
+
+        // Other scales don't reuse the entries.
+        TemplateMatcher::with_cache(&descriptors, backend, Some(cache.clone()))?
+            .with_resized_scale(1.5)?;
+        assert_eq!(entry_paths(&cache)?.len(), 6);
+
+        cache.clear()?;
+        assert!(entry_paths(&cache)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_cache_source_hash() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = TemplateCache::new(dir.path())?;
+        let backend = MatchingBackend::Native;
+        let descriptors = load_descriptors();
+        let (up, right) = (descriptors[0].clone(), descriptors[1].clone());
+        let hash = hash_encoded_template(bundled_template_png(Direction::Up));
+
+        let keyed = up.clone().with_source_hash(hash);
+        assert_ne!(template_key(&keyed, backend)?, template_key(&up, backend)?);
+        // Keyed on the hash alone, the decoded template isn't looked at.
+        let stored = nd::Array2::from_elem((2, 3), 0.5);
+        cache.insert(template_key(&keyed, backend)?, &stored.view())?;
+        let matcher = TemplateMatcher::with_cache(
+            &[right.clone().with_source_hash(hash)],
+            backend,
+            Some(cache.clone()),
+        )?;
+        assert_eq!(matcher.template_for("right"), Some(&stored));
+        assert_ne!(
+            template_key(&right.with_source_hash(hash ^ 1), backend)?,
+            template_key(&keyed, backend)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_cache_integrity() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = TemplateCache::new(dir.path())?;
+        let template = nd::Array2::from_shape_fn((4, 5), |(y, x)| (y * 5 + x) as f32);
+        cache.insert(1, &template.view())?;
+        assert_eq!(cache.get(1), Some(template.clone()));
+        // Only returned for its own key.
+        fs::copy(cache.entry_path(1), cache.entry_path(2))?;
+        assert_eq!(cache.get(2), None);
+
+        let path = cache.entry_path(1);
+        let mut bytes = fs::read(&path)?;
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, &bytes)?;
+        assert_eq!(cache.get(1), None);
+        // Dropped, then made again on the next use.
+        assert!(!path.exists());
+        let made = cache.get_or_insert_with(1, || Ok(template.clone()))?;
+        assert_eq!(made, template);
+        assert_eq!(cache.get(1), Some(template.clone()));
+
+        fs::write(&path, &fs::read(&path)?[..HEADER_LEN + 3])?;
+        assert_eq!(cache.get(1), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,434 @@
+use crate::{
+    imgproc::{ensure_shape, normalize_correlation, squared_integral_image_into},
+    integral_window_sum, ResponseElement,
+};
+use anyhow::{ensure, Result};
+use ndarray::{self as nd, parallel::prelude::*};
+use rayon::slice::ParallelSliceMut;
+use rustfft::{num_complex::Complex32, Fft, FftDirection, FftPlanner};
+use std::fmt;
+
+// Template heights in the rows of an FFT tile. Taller tiles spend less of each transform on the rows
+// they share with the next tile, shorter ones keep the windows of the incremental search small.
+const FFT_TILE_TEMPLATE_HEIGHTS: usize = 4;
+
+/// Spectrum of a template zero-padded to a fixed FFT size, reusable across frames of the same size.
+#[derive(Debug, Clone)]
+pub struct TemplateSpectrum {
+    fft_size: (usize, usize),
+    template_size: (usize, usize),
+    template_norm: f64,
+    // Stored conjugated, so correlating is a plain element-wise product.
+    spectrum: nd::Array2<Complex32>,
+}
+
+impl TemplateSpectrum {
+    pub fn new(template: &nd::ArrayView2<f32>, fft_size: (usize, usize)) -> Result<Self> {
+        let (template_height, template_width) = template.dim();
+        ensure!(
+            template_height > 0 && template_width > 0,
+            "Template must not be empty"
+        );
+        ensure!(
+            template_height <= fft_size.0 && template_width <= fft_size.1,
+            "Template is larger than the FFT size {:?}",
+            fft_size
+        );
+
+        let mut spectrum = nd::Array2::<Complex32>::zeros(fft_size);
+        spectrum
+            .slice_mut(nd::s![..template_height, ..template_width])
+            .zip_mut_with(template, |s, &t| *s = Complex32::new(t, 0.0));
+        FftBuffers::default().fft_2d(&mut spectrum, FftDirection::Forward);
+        spectrum.par_mapv_inplace(|v| v.conj());
+
+        let template_norm = template
+            .iter()
+            .map(|&v| (v as f64).powi(2))
+            .sum::<f64>()
+            .sqrt();
+
+        Ok(Self {
+            fft_size,
+            template_size: template.dim(),
+            template_norm,
+            spectrum,
+        })
+    }
+
+    /// Returns the `(rows, cols)` of the padded spectrum.
+    pub fn fft_size(&self) -> (usize, usize) {
+        self.fft_size
+    }
+
+    /// Returns the `(rows, cols)` of the template the spectrum was made from.
+    pub fn template_size(&self) -> (usize, usize) {
+        self.template_size
+    }
+}
+
+/// Returns the FFT size to use for a `(rows, cols)` image.
+///
+/// The image only needs to fit, since the valid correlation region never wraps around.
+pub fn fft_size_for(image_size: (usize, usize)) -> (usize, usize) {
+    (fast_fft_len(image_size.0), fast_fft_len(image_size.1))
+}
+
+/// Returns the FFT size of the row tiles to correlate images `image_width` wide with templates of
+/// up to `template_height` rows in, whatever the height of the images.
+///
+/// Each tile holds a few template heights, so that the responses of a row only depend on the image
+/// rows near it.
+pub fn fft_tile_size_for(template_height: usize, image_width: usize) -> (usize, usize) {
+    (
+        fast_fft_len(FFT_TILE_TEMPLATE_HEIGHTS * template_height),
+        fast_fft_len(image_width),
+    )
+}
+
+/// Returns the response rows of each tile with an FFT of `fft_rows`, for templates of up to
+/// `template_height` rows.
+pub fn fft_tile_rows(fft_rows: usize, template_height: usize) -> usize {
+    fft_rows + 1 - template_height
+}
+
+// The smallest length >= `n` that only has 2, 3 and 5 as prime factors.
+fn fast_fft_len(n: usize) -> usize {
+    (n.max(1)..)
+        .find(|&len| {
+            let mut rem = len;
+            for factor in [2, 3, 5] {
+                while rem % factor == 0 {
+                    rem /= factor;
+                }
+            }
+            rem == 1
+        })
+        .unwrap()
+}
+
+/// FFT-based equivalent of [crate::match_template_ccorr_normed] for several templates at once.
+///
+/// The image is correlated in tiles of as many rows as the FFT size of the spectra holds, each
+/// tile giving the responses of its first [fft_tile_rows] rows. The response of a row then only
+/// depends on the rows of its tile, whichever image they were cut out of. Spectra made with
+/// [fft_size_for] the image size hold it in a single tile. The spectrum of each tile and the
+/// integral image are shared by all templates.
+pub fn match_templates_ccorr_normed_fft(
+    image: &nd::ArrayView2<f32>,
+    spectra: &[TemplateSpectrum],
+) -> Result<Vec<nd::Array2<f32>>> {
+    let mut responses = Vec::new();
+    match_templates_ccorr_normed_fft_into(
+        image,
+        spectra,
+        &mut FftBuffers::default(),
+        &mut nd::Array2::zeros((0, 0)),
+        &mut responses,
+    )?;
+    Ok(responses)
+}
+
+/// Intermediate arrays of [match_templates_ccorr_normed_fft] along with the FFT plans, kept to be
+/// reused across frames of the same size.
+pub(crate) struct FftBuffers {
+    planner: FftPlanner<f32>,
+    image_spectrum: nd::Array2<Complex32>,
+    product: nd::Array2<Complex32>,
+    transposed: nd::Array2<Complex32>,
+}
+
+impl Default for FftBuffers {
+    fn default() -> Self {
+        Self {
+            planner: FftPlanner::new(),
+            image_spectrum: Default::default(),
+            product: Default::default(),
+            transposed: Default::default(),
+        }
+    }
+}
+
+impl fmt::Debug for FftBuffers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FftBuffers")
+            .field("fft_size", &self.image_spectrum.dim())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FftBuffers {
+    // In-place 2D FFT, done as a 1D FFT over the rows followed by one over the columns.
+    // The inverse is left unnormalized, same as `rustfft`.
+    fn fft_2d(&mut self, data: &mut nd::Array2<Complex32>, direction: FftDirection) {
+        let (height, width) = data.dim();
+        fft_rows(data, self.planner.plan_fft(width, direction).as_ref());
+        // Columns are transformed as the rows of the transposed array to keep the memory access
+        // linear.
+        ensure_shape(&mut self.transposed, (width, height));
+        self.transposed.assign(&data.t());
+        fft_rows(
+            &mut self.transposed,
+            self.planner.plan_fft(height, direction).as_ref(),
+        );
+        data.assign(&self.transposed.t());
+    }
+}
+
+// Same as `match_templates_ccorr_normed_fft`, writing the response of each template into
+// `responses`.
+pub(crate) fn match_templates_ccorr_normed_fft_into<T: ResponseElement>(
+    image: &nd::ArrayView2<f32>,
+    spectra: &[TemplateSpectrum],
+    buffers: &mut FftBuffers,
+    sq_integral: &mut nd::Array2<f64>,
+    responses: &mut Vec<nd::Array2<T>>,
+) -> Result<()> {
+    let (image_height, image_width) = image.dim();
+    // The columns are transformed whole, while the rows only need to fit a tile.
+    let fft_size = spectra
+        .first()
+        .map_or((1, 1), |s| (s.fft_size.0, fast_fft_len(image_width)));
+    for spectrum in spectra {
+        ensure!(
+            spectrum.fft_size == fft_size,
+            "Template spectrum was made for {:?}, but the image requires {:?}",
+            spectrum.fft_size,
+            fft_size
+        );
+        ensure!(
+            spectrum.template_size.0 <= image_height && spectrum.template_size.1 <= image_width,
+            "Template {:?} is larger than the image {:?}",
+            spectrum.template_size,
+            image.dim()
+        );
+    }
+    let tile_rows = fft_tile_rows(
+        fft_size.0,
+        spectra.iter().map(|s| s.template_size.0).max().unwrap_or(1),
+    );
+
+    responses.resize_with(spectra.len(), Default::default);
+    for (spectrum, res) in spectra.iter().zip(responses.iter_mut()) {
+        let (template_height, template_width) = spectrum.template_size;
+        ensure_shape(
+            res,
+            (
+                image_height - template_height + 1,
+                image_width - template_width + 1,
+            ),
+        );
+    }
+
+    let mut image_spectrum = std::mem::take(&mut buffers.image_spectrum);
+    let mut product = std::mem::take(&mut buffers.product);
+    ensure_shape(&mut image_spectrum, fft_size);
+    ensure_shape(&mut product, fft_size);
+    squared_integral_image_into(image, sq_integral);
+    let scale = 1.0 / (fft_size.0 * fft_size.1) as f32;
+    let res_height = responses.iter().map(|res| res.nrows()).max().unwrap_or(0);
+    for top in (0..res_height).step_by(tile_rows) {
+        let tile = image.slice(nd::s![top..(top + fft_size.0).min(image_height), ..]);
+        image_spectrum.fill(Complex32::new(0.0, 0.0));
+        image_spectrum
+            .slice_mut(nd::s![..tile.nrows(), ..image_width])
+            .zip_mut_with(&tile, |s, &v| *s = Complex32::new(v, 0.0));
+        buffers.fft_2d(&mut image_spectrum, FftDirection::Forward);
+
+        for (spectrum, res) in spectra.iter().zip(responses.iter_mut()) {
+            let bottom = (top + tile_rows).min(res.nrows());
+            if top >= bottom {
+                continue;
+            }
+            nd::Zip::from(&mut product)
+                .and(&image_spectrum)
+                .and(&spectrum.spectrum)
+                .par_for_each(|p, &i, &s| *p = i * s);
+            buffers.fft_2d(&mut product, FftDirection::Inverse);
+
+            let (template_height, template_width) = spectrum.template_size;
+            let mut res = res.slice_mut(nd::s![top..bottom, ..]);
+            nd::Zip::indexed(&mut res).par_for_each(|(y, x), el| {
+                let window = integral_window_sum(
+                    &sq_integral.view(),
+                    top + y,
+                    x,
+                    template_height,
+                    template_width,
+                );
+                *el = T::from_confidence(normalize_correlation(
+                    (product[[y, x]].re * scale) as f64,
+                    window.max(0.0).sqrt() * spectrum.template_norm,
+                ));
+            });
+        }
+    }
+    buffers.image_spectrum = image_spectrum;
+    buffers.product = product;
+    Ok(())
+}
+
+fn fft_rows(data: &mut nd::Array2<Complex32>, fft: &dyn Fft<f32>) {
+    let width = data.ncols();
+    match data.as_slice_mut() {
+        // INFO: `Fft::process` allocates its scratch on every call, so each worker keeps its own.
+        Some(buf) => buf.par_chunks_mut(width).for_each_init(
+            || vec![Complex32::default(); fft.get_inplace_scratch_len()],
+            |scratch, row| fft.process_with_scratch(row, scratch),
+        ),
+        None => data
+            .axis_iter_mut(nd::Axis(0))
+            .into_par_iter()
+            .for_each(|mut row| {
+                let mut buf = row.to_vec();
+                fft.process(&mut buf);
+                row.assign(&nd::ArrayView1::from(&buf));
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::match_template_ccorr_normed;
+
+    #[test]
+    fn test_fast_fft_len() {
+        assert_eq!(fast_fft_len(0), 1);
+        assert_eq!(fast_fft_len(7), 8);
+        assert_eq!(fast_fft_len(502), 512);
+        assert_eq!(fast_fft_len(314), 320);
+    }
+
+    #[test]
+    fn test_fft_matches_spatial_ncc() -> anyhow::Result<()> {
+        let image = nd::Array2::<f32>::from_shape_fn((37, 53), |(y, x)| {
+            if (y * 7 + x * 13) % 11 < 3 {
+                255.0
+            } else {
+                0.0
+            }
+        });
+        let template = image.slice(nd::s![10..19, 20..31]).to_owned();
+        let spectrum = TemplateSpectrum::new(&template.view(), fft_size_for(image.dim()))?;
+
+        let fft = match_templates_ccorr_normed_fft(&image.view(), &[spectrum])?;
+        let spatial = match_template_ccorr_normed(&image.view(), &template.view())?;
+        assert_eq!(fft[0].dim(), spatial.dim());
+        nd::Zip::from(&fft[0]).and(&spatial).for_each(|&a, &b| {
+            assert!((a - b).abs() < 1e-3, "{a} != {b}");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_fft_tiles() -> anyhow::Result<()> {
+        let image = nd::Array2::<f32>::from_shape_fn((200, 53), |(y, x)| {
+            if (y * 7 + x * 13) % 11 < 3 {
+                255.0
+            } else {
+                0.0
+            }
+        });
+        let template = image.slice(nd::s![10..19, 20..31]).to_owned();
+        let fft_size = fft_tile_size_for(template.nrows(), image.ncols());
+        let tile_rows = fft_tile_rows(fft_size.0, template.nrows());
+        assert!(tile_rows < image.nrows());
+        let spectrum = TemplateSpectrum::new(&template.view(), fft_size)?;
+
+        let fft = match_templates_ccorr_normed_fft(&image.view(), std::slice::from_ref(&spectrum))?;
+        let spatial = match_template_ccorr_normed(&image.view(), &template.view())?;
+        assert_eq!(fft[0].dim(), spatial.dim());
+        nd::Zip::from(&fft[0]).and(&spatial).for_each(|&a, &b| {
+            assert!((a - b).abs() < 1e-3, "{a} != {b}");
+        });
+
+        // Rows cut out from a tile boundary get the same tiles, down to the last bit.
+        let top = 2 * tile_rows;
+        let rows = match_templates_ccorr_normed_fft(&image.slice(nd::s![top.., ..]), &[spectrum])?;
+        assert_eq!(rows[0], fft[0].slice(nd::s![top.., ..]));
+        Ok(())
+    }
+
+    mod sizes {
+        use super::*;
+        use proptest::prelude::*;
+
+        // Lengths around powers of two, where the padded FFT size jumps, besides arbitrary ones.
+        fn len_strategy() -> impl Strategy<Value = usize> {
+            prop_oneof![
+                1usize..80,
+                prop::sample::select(vec![15, 16, 17, 31, 32, 33, 63, 64, 65]),
+            ]
+        }
+
+        // Binary edge maps as canny leaves them, with a template cut out of the image or of
+        // template size as at 1440p.
+        fn scene_strategy() -> impl Strategy<Value = (nd::Array2<f32>, nd::Array2<f32>)> {
+            (len_strategy(), len_strategy(), any::<bool>()).prop_flat_map(
+                |(height, width, template_sized)| {
+                    let template = if template_sized && height >= 21 && width >= 20 {
+                        (Just(21usize).boxed(), Just(20usize).boxed())
+                    } else {
+                        ((1..=height).boxed(), (1..=width).boxed())
+                    };
+                    (
+                        prop::collection::vec(prop::bool::weighted(0.2), height * width),
+                        template,
+                    )
+                        .prop_flat_map(move |(edges, (th, tw))| {
+                            (
+                                Just(edges),
+                                Just((th, tw)),
+                                0..=height - th,
+                                0..=width - tw,
+                                any::<bool>(),
+                            )
+                        })
+                        .prop_map(move |(edges, (th, tw), y, x, cut)| {
+                            let image = nd::Array2::from_shape_vec(
+                                (height, width),
+                                edges.iter().map(|&e| if e { 255.0 } else { 0.0 }).collect(),
+                            )
+                            .unwrap();
+                            let template = if cut {
+                                image.slice(nd::s![y..y + th, x..x + tw]).to_owned()
+                            } else {
+                                nd::Array2::from_shape_fn((th, tw), |(ty, tx)| {
+                                    if (ty + tx) % 3 == 0 {
+                                        255.0
+                                    } else {
+                                        0.0
+                                    }
+                                })
+                            };
+                            (image, template)
+                        })
+                },
+            )
+        }
+
+        proptest! {
+            #[test]
+            fn test_fft_matches_spatial_ncc_sizes((image, template) in scene_strategy()) {
+                let spectrum =
+                    TemplateSpectrum::new(&template.view(), fft_size_for(image.dim())).unwrap();
+                let fft = match_templates_ccorr_normed_fft(&image.view(), &[spectrum]).unwrap();
+                let spatial = match_template_ccorr_normed(&image.view(), &template.view()).unwrap();
+                prop_assert_eq!(fft[0].dim(), spatial.dim());
+                for (&a, &b) in fft[0].iter().zip(spatial.iter()) {
+                    prop_assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fft_rejects_mismatched_spectrum() -> anyhow::Result<()> {
+        let image = nd::Array2::<f32>::zeros((20, 20));
+        let template = nd::Array2::<f32>::ones((3, 3));
+        let spectrum = TemplateSpectrum::new(&template.view(), (32, 32))?;
+        assert!(match_templates_ccorr_normed_fft(&image.view(), &[spectrum]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,692 @@
+use crate::{
+    imgproc::{
+        canny_into, convert_pixels_to_grayscale_into, gaussian_blur_into,
+        match_template_ccorr_normed_into, squared_integral_image_into,
+    },
+    match_template_ccorr_normed, resize_nearest, Point, ResponseElement,
+};
+use anyhow::Result;
+use ndarray as nd;
+
+mod cache;
+pub use cache::*;
+mod fft;
+pub use fft::*;
+mod workspace;
+#[cfg(feature = "opencv")]
+use opencv::{self as cv, prelude::*};
+pub use workspace::*;
+
+const BLUR_KERNEL_SIZE: usize = 3;
+const CANNY_LOW_THRESHOLD: f32 = 150.0;
+const CANNY_HIGH_THRESHOLD: f32 = 300.0;
+
+/// Implementation used for pre-processing and template matching.
+///
+/// Defaults to OpenCV when the `opencv` feature is enabled.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum MatchingBackend {
+    /// Pure-Rust implementation, always available.
+    #[cfg_attr(not(feature = "opencv"), default)]
+    Native,
+    /// Pure-Rust implementation correlating in the frequency domain, faster on large frames.
+    NativeFft,
+    /// OpenCV implementation, available with the `opencv` feature.
+    #[cfg(feature = "opencv")]
+    #[default]
+    OpenCv,
+}
+
+impl MatchingBackend {
+    /// Converts the image into the edge map that the templates are matched against.
+    pub fn pre_process_rgba(&self, image: &image::RgbaImage) -> Result<nd::Array2<f32>> {
+        let mut workspace = MatchWorkspace::<f32>::new();
+        self.pre_process_rgba_into(image, &mut workspace)?;
+        Ok(std::mem::take(&mut workspace.edges))
+    }
+
+    /// Same as [Self::pre_process_rgba], for OpenCV frames.
+    #[cfg(feature = "opencv")]
+    pub fn pre_process_mat(&self, mat: &cv::core::Mat) -> Result<nd::Array2<f32>> {
+        let mut workspace = MatchWorkspace::<f32>::new();
+        match self {
+            MatchingBackend::Native | MatchingBackend::NativeFft => {
+                use crate::TryFromCv;
+                let pixels = nd::ArrayView3::<u8>::try_from_cv(mat)?;
+                pre_process_pixels_into(&pixels, &mut workspace)?;
+            }
+            MatchingBackend::OpenCv => {
+                let mats = &mut workspace.mats;
+                pre_process_mat_into(mat, mats)?;
+                convert_mat_to_array2_into(&mats.canny, &mut mats.converted, &mut workspace.edges)?;
+            }
+        }
+        Ok(std::mem::take(&mut workspace.edges))
+    }
+
+    // Same as `pre_process_rgba`, leaving the edge map in `workspace.edges`.
+    pub(crate) fn pre_process_rgba_into<T: ResponseElement>(
+        &self,
+        image: &image::RgbaImage,
+        workspace: &mut MatchWorkspace<T>,
+    ) -> Result<()> {
+        match self {
+            MatchingBackend::Native | MatchingBackend::NativeFft => {
+                let (width, height) = image.dimensions();
+                let pixels = nd::ArrayView3::from_shape(
+                    (height as usize, width as usize, 4),
+                    image.as_raw(),
+                )?;
+                pre_process_pixels_into(&pixels, workspace)
+            }
+            #[cfg(feature = "opencv")]
+            MatchingBackend::OpenCv => {
+                // RGBA, as the pre-processing reads it.
+                let mat = crate::cv_convert::with_opencv_image::view_image_channels(image)?;
+                let mats = &mut workspace.mats;
+                pre_process_mat_into(&mat, mats)?;
+                convert_mat_to_array2_into(&mats.canny, &mut mats.converted, &mut workspace.edges)
+            }
+        }
+    }
+
+    /// Runs `TM_CCORR_NORMED` matching of a pre-processed template over a pre-processed image.
+    pub fn match_template(
+        &self,
+        edges: &nd::ArrayView2<f32>,
+        template: &nd::ArrayView2<f32>,
+    ) -> Result<nd::Array2<f32>> {
+        match self {
+            MatchingBackend::Native => match_template_ccorr_normed(edges, template),
+            MatchingBackend::NativeFft => {
+                let spectrum = TemplateSpectrum::new(template, fft_size_for(edges.dim()))?;
+                let mut res = match_templates_ccorr_normed_fft(edges, &[spectrum])?;
+                Ok(res.remove(0))
+            }
+            #[cfg(feature = "opencv")]
+            MatchingBackend::OpenCv => {
+                let edges = convert_array2_to_mat(edges)?;
+                let template = convert_array2_to_mat(template)?;
+                match_template_mat(&edges, &template)
+            }
+        }
+    }
+}
+
+// Response rows a backend computes together, from the frame rows starting at the first of them.
+// The tiles start at the top of whatever was matched, so matching rows cut out at a tile boundary
+// gives the same responses as the whole frame for the tiles the cut holds in full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RowTiling {
+    // Response rows of each tile.
+    pub rows: usize,
+    // Frame rows each tile is computed from.
+    pub span: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TemplateMatcher {
+    backend: MatchingBackend,
+    descriptors: Vec<MatchDescriptor>,
+    baked_templates: Vec<nd::Array2<f32>>,
+    // Cache key of each baked template, following the resizes it went through.
+    template_keys: Vec<u64>,
+    cache: Option<TemplateCache>,
+    // Only populated for `MatchingBackend::NativeFft`, see `prepare_frame_size`.
+    template_spectra: Vec<TemplateSpectrum>,
+    // The baked templates converted once for `MatchingBackend::OpenCv`, see `convert_template_mats`.
+    #[cfg(feature = "opencv")]
+    template_mats: Vec<cv::core::Mat>,
+}
+
+// FIXME: multiple template matching + nms 만 다루기
+impl TemplateMatcher {
+    pub fn new(descriptors: &[MatchDescriptor]) -> Result<Self> {
+        Self::with_backend(descriptors, MatchingBackend::default())
+    }
+
+    pub fn with_backend(descriptors: &[MatchDescriptor], backend: MatchingBackend) -> Result<Self> {
+        Self::with_cache(descriptors, backend, None)
+    }
+
+    /// Same as [Self::with_backend], but loads the pre-processed templates from `cache` when
+    /// available and stores the ones it had to make, including those of later resizes.
+    pub fn with_cache(
+        descriptors: &[MatchDescriptor],
+        backend: MatchingBackend,
+        cache: Option<TemplateCache>,
+    ) -> Result<Self> {
+        let template_keys = descriptors
+            .iter()
+            .map(|d| template_key(d, backend))
+            .collect::<Result<Vec<_>>>()?;
+        let baked_templates = descriptors
+            .iter()
+            .zip(&template_keys)
+            .map(|(d, &key)| {
+                bake(cache.as_ref(), key, || {
+                    backend.pre_process_rgba(&d.template)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            backend,
+            descriptors: descriptors.to_vec(),
+            #[cfg(feature = "opencv")]
+            template_mats: convert_template_mats(backend, &baked_templates)?,
+            baked_templates,
+            template_keys,
+            cache,
+            template_spectra: Vec::new(),
+        })
+    }
+
+    pub fn backend(&self) -> MatchingBackend {
+        self.backend
+    }
+
+    pub fn cache(&self) -> Option<&TemplateCache> {
+        self.cache.as_ref()
+    }
+
+    pub fn descriptors(&self) -> &[MatchDescriptor] {
+        &self.descriptors
+    }
+
+    pub fn descriptor_for(&self, label: &str) -> Option<&MatchDescriptor> {
+        self.descriptors.iter().find(|d| d.label == label)
+    }
+
+    /// Returns the pre-processed template that is actually used for matching.
+    pub fn template_for(&self, label: &str) -> Option<&nd::Array2<f32>> {
+        self.descriptors
+            .iter()
+            .position(|d| d.label == label)
+            .map(|i| &self.baked_templates[i])
+    }
+
+    /// Returns the `(width, height)` of the first template.
+    pub fn template_size(&self) -> Option<(usize, usize)> {
+        self.baked_templates.first().map(|t| (t.ncols(), t.nrows()))
+    }
+
+    // FIXME: 템플릿 resize는 따로 빼기..
+    pub fn with_resized_scale(&self, scale: f64) -> Result<Self> {
+        let mut tm = self.clone();
+        tm.resize_templates_scale(scale)?;
+        Ok(tm)
+    }
+
+    pub fn resize_templates_scale(&mut self, scale: f64) -> Result<()> {
+        for (template, key) in self
+            .baked_templates
+            .iter_mut()
+            .zip(self.template_keys.iter_mut())
+        {
+            *key = resized_template_key(*key, scale);
+            // This will produce the near-accurate result in terms of pixel patterns as the original template image provided
+            // For details: https://stackoverflow.com/questions/5358700/template-match-different-sizes-of-template-and-image
+            *template = bake(self.cache.as_ref(), *key, || {
+                resize_nearest(&template.view(), scale)
+            })?;
+        }
+        self.template_spectra.clear();
+        #[cfg(feature = "opencv")]
+        {
+            self.template_mats = convert_template_mats(self.backend, &self.baked_templates)?;
+        }
+        Ok(())
+    }
+
+    /// Precomputes whatever the backend can reuse across frames of `width` x `height`.
+    ///
+    /// For [MatchingBackend::NativeFft] these are the template spectra, which are kept until the
+    /// frame width or the template scale changes. Other backends don't need any preparation.
+    pub fn prepare_frame_size(&mut self, width: usize, _height: usize) -> Result<()> {
+        if self.backend != MatchingBackend::NativeFft {
+            return Ok(());
+        }
+        let fft_size = self.fft_tile_size(width);
+        if self.has_spectra_for(fft_size) {
+            return Ok(());
+        }
+        self.template_spectra = self
+            .baked_templates
+            .iter()
+            .map(|t| TemplateSpectrum::new(&t.view(), fft_size))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(())
+    }
+
+    /// Returns whether [Self::prepare_frame_size] was called for frames of `width` x `height`
+    /// since the templates last changed, always `true` for backends that don't need it.
+    pub fn is_prepared_for(&self, width: usize, _height: usize) -> bool {
+        self.backend != MatchingBackend::NativeFft
+            || self.has_spectra_for(self.fft_tile_size(width))
+    }
+
+    /// Returns how the response rows depend on the frame, or `None` when they may depend on all of
+    /// it, as with [MatchingBackend::OpenCv] whose transforms are sized after the frame.
+    pub(crate) fn row_tiling(&self) -> Option<RowTiling> {
+        let template_height = self.max_template_height();
+        match self.backend {
+            MatchingBackend::Native => Some(RowTiling {
+                rows: 1,
+                span: template_height,
+            }),
+            MatchingBackend::NativeFft => {
+                let (fft_rows, _) = fft_tile_size_for(template_height, 1);
+                Some(RowTiling {
+                    rows: fft_tile_rows(fft_rows, template_height),
+                    span: fft_rows,
+                })
+            }
+            #[cfg(feature = "opencv")]
+            MatchingBackend::OpenCv => None,
+        }
+    }
+
+    fn max_template_height(&self) -> usize {
+        self.baked_templates
+            .iter()
+            .map(|t| t.nrows())
+            .max()
+            .unwrap_or(1)
+    }
+
+    fn fft_tile_size(&self, width: usize) -> (usize, usize) {
+        fft_tile_size_for(self.max_template_height(), width)
+    }
+
+    fn has_spectra_for(&self, fft_size: (usize, usize)) -> bool {
+        !self.template_spectra.is_empty()
+            && self
+                .template_spectra
+                .iter()
+                .all(|s| s.fft_size() == fft_size)
+    }
+
+    pub fn match_templates(&self, input: &image::RgbaImage) -> Result<Vec<TemplateMatcherResult>> {
+        let mut workspace = MatchWorkspace::new();
+        self.match_templates_into(input, &mut workspace)?;
+        Ok(self.take_results(&mut workspace))
+    }
+
+    /// Same as [Self::match_templates], but keeps the intermediate buffers and the response maps
+    /// in `workspace` to reuse them on the next frame of the same size.
+    ///
+    /// Returns the response maps in the order of the descriptors.
+    pub fn match_templates_into<'a, T: ResponseElement>(
+        &self,
+        input: &image::RgbaImage,
+        workspace: &'a mut MatchWorkspace<T>,
+    ) -> Result<&'a [nd::Array2<T>]> {
+        #[cfg(feature = "opencv")]
+        if self.backend == MatchingBackend::OpenCv {
+            // RGBA, as the pre-processing reads it.
+            let mat = crate::cv_convert::with_opencv_image::view_image_channels(input)?;
+            return self.match_templates_mat_into(&mat, workspace);
+        }
+        self.backend.pre_process_rgba_into(input, workspace)?;
+        self.match_workspace_edges(workspace)
+    }
+
+    // Matches the edge map that pre-processing left in `workspace`.
+    fn match_workspace_edges<'a, T: ResponseElement>(
+        &self,
+        workspace: &'a mut MatchWorkspace<T>,
+    ) -> Result<&'a [nd::Array2<T>]> {
+        self.match_edges_into(None, workspace)?;
+        Ok(&workspace.responses)
+    }
+
+    /// Matches all templates against an edge map produced by [MatchingBackend::pre_process_rgba].
+    pub fn match_templates_pre_processed(
+        &self,
+        edges: &nd::ArrayView2<f32>,
+    ) -> Result<Vec<TemplateMatcherResult>> {
+        let mut workspace = MatchWorkspace::new();
+        self.match_edges_into(Some(edges), &mut workspace)?;
+        Ok(self.take_results(&mut workspace))
+    }
+
+    // Matches `edges`, or the edge map left in `workspace` without them, into the response maps of
+    // `workspace`.
+    fn match_edges_into<T: ResponseElement>(
+        &self,
+        edges: Option<&nd::ArrayView2<f32>>,
+        workspace: &mut MatchWorkspace<T>,
+    ) -> Result<()> {
+        let edges = match edges {
+            Some(edges) => edges.view(),
+            None => workspace.edges.view(),
+        };
+        let edges = &edges;
+        let sq_integral = &mut workspace.sq_integral;
+        let fft = &mut workspace.fft;
+        let responses = &mut workspace.responses;
+        match self.backend {
+            MatchingBackend::Native => {
+                // Shared by all templates, unlike `match_template_ccorr_normed`.
+                squared_integral_image_into(edges, sq_integral);
+                responses.resize_with(self.baked_templates.len(), Default::default);
+                for (template, res) in self.baked_templates.iter().zip(responses.iter_mut()) {
+                    match_template_ccorr_normed_into(
+                        edges,
+                        &template.view(),
+                        &sq_integral.view(),
+                        res,
+                    )?;
+                }
+                Ok(())
+            }
+            MatchingBackend::NativeFft => {
+                let fft_size = self.fft_tile_size(edges.ncols());
+                if self.has_spectra_for(fft_size) {
+                    return match_templates_ccorr_normed_fft_into(
+                        edges,
+                        &self.template_spectra,
+                        fft,
+                        sq_integral,
+                        responses,
+                    );
+                }
+                let spectra = self
+                    .baked_templates
+                    .iter()
+                    .map(|t| TemplateSpectrum::new(&t.view(), fft_size))
+                    .collect::<Result<Vec<_>>>()?;
+                match_templates_ccorr_normed_fft_into(edges, &spectra, fft, sq_integral, responses)
+            }
+            #[cfg(feature = "opencv")]
+            MatchingBackend::OpenCv => {
+                let mats = &mut workspace.mats;
+                convert_array2_to_mat_into(edges, &mut mats.edges)?;
+                responses.resize_with(self.template_mats.len(), Default::default);
+                for (template, res) in self.template_mats.iter().zip(responses.iter_mut()) {
+                    match_template_mat_into(&mats.edges, template, &mut mats.response)?;
+                    convert_mat_to_array2_into(&mats.response, &mut mats.converted, res)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Moves the response maps out of `workspace` into labelled results.
+    fn take_results(&self, workspace: &mut MatchWorkspace) -> Vec<TemplateMatcherResult> {
+        self.descriptors
+            .iter()
+            .zip(std::mem::take(&mut workspace.responses))
+            .map(|(descriptor, res)| TemplateMatcherResult::new(descriptor.label.clone(), res))
+            .collect()
+    }
+
+    #[cfg(feature = "opencv")]
+    pub fn match_templates_mat(&self, input: &cv::core::Mat) -> Result<Vec<TemplateMatcherResult>> {
+        let mut workspace = MatchWorkspace::new();
+        self.match_templates_mat_into(input, &mut workspace)?;
+        Ok(self.take_results(&mut workspace))
+    }
+
+    /// Same as [Self::match_templates_into], for OpenCV frames.
+    #[cfg(feature = "opencv")]
+    pub fn match_templates_mat_into<'a, T: ResponseElement>(
+        &self,
+        input: &cv::core::Mat,
+        workspace: &'a mut MatchWorkspace<T>,
+    ) -> Result<&'a [nd::Array2<T>]> {
+        match self.backend {
+            MatchingBackend::Native | MatchingBackend::NativeFft => {
+                use crate::TryFromCv;
+                let pixels = nd::ArrayView3::<u8>::try_from_cv(input)?;
+                pre_process_pixels_into(&pixels, workspace)?;
+                self.match_workspace_edges(workspace)
+            }
+            MatchingBackend::OpenCv => {
+                let MatchWorkspace {
+                    mats, responses, ..
+                } = workspace;
+                pre_process_mat_into(input, mats)?;
+                mats.canny
+                    .convert_to(&mut mats.edges, cv::core::CV_32F, 1.0, 0.0)?;
+
+                responses.resize_with(self.template_mats.len(), Default::default);
+                for (template, res) in self.template_mats.iter().zip(responses.iter_mut()) {
+                    match_template_mat_into(&mats.edges, template, &mut mats.response)?;
+                    convert_mat_to_array2_into(&mats.response, &mut mats.converted, res)?;
+                }
+                Ok(&workspace.responses)
+            }
+        }
+    }
+}
+
+fn bake(
+    cache: Option<&TemplateCache>,
+    key: u64,
+    make: impl FnOnce() -> Result<nd::Array2<f32>>,
+) -> Result<nd::Array2<f32>> {
+    match cache {
+        Some(cache) => cache.get_or_insert_with(key, make),
+        None => make(),
+    }
+}
+
+// Pre-processes `(row, col, channel)` pixels in RGB(A) order with the native backends, leaving the
+// edge map in `workspace.edges`.
+fn pre_process_pixels_into<T: ResponseElement>(
+    pixels: &nd::ArrayView3<u8>,
+    workspace: &mut MatchWorkspace<T>,
+) -> Result<()> {
+    convert_pixels_to_grayscale_into(pixels, &mut workspace.gray)?;
+    gaussian_blur_into(
+        &workspace.gray.view(),
+        BLUR_KERNEL_SIZE,
+        0.0,
+        &mut workspace.blur_pass,
+        &mut workspace.blurred,
+    )?;
+    canny_into(
+        &workspace.blurred.view(),
+        CANNY_LOW_THRESHOLD,
+        CANNY_HIGH_THRESHOLD,
+        true,
+        &mut workspace.canny,
+        &mut workspace.edges,
+    );
+    Ok(())
+}
+
+// Leaves the edge map in `mats.canny`.
+#[cfg(feature = "opencv")]
+fn pre_process_mat_into(mat: &cv::core::Mat, mats: &mut workspace::MatBuffers) -> Result<()> {
+    cv::imgproc::cvt_color(&mat, &mut mats.gray, cv::imgproc::COLOR_RGBA2GRAY, 0)?;
+    cv::imgproc::gaussian_blur(
+        &mats.gray,
+        &mut mats.blurred,
+        cv::core::Size::new(BLUR_KERNEL_SIZE as i32, BLUR_KERNEL_SIZE as i32),
+        0.0,
+        0.0,
+        0,
+    )?;
+    cv::imgproc::canny(
+        &mats.blurred,
+        &mut mats.canny,
+        CANNY_LOW_THRESHOLD as f64,
+        CANNY_HIGH_THRESHOLD as f64,
+        3,
+        true,
+    )?;
+    Ok(())
+}
+
+#[cfg(feature = "opencv")]
+fn match_template_mat(edges: &cv::core::Mat, template: &cv::core::Mat) -> Result<nd::Array2<f32>> {
+    let mut res = cv::core::Mat::default();
+    match_template_mat_into(edges, template, &mut res)?;
+    let mut arr = nd::Array2::zeros((0, 0));
+    convert_mat_to_array2_into(&res, &mut cv::core::Mat::default(), &mut arr)?;
+    Ok(arr)
+}
+
+#[cfg(feature = "opencv")]
+fn match_template_mat_into(
+    edges: &cv::core::Mat,
+    template: &cv::core::Mat,
+    res: &mut cv::core::Mat,
+) -> Result<()> {
+    cv::imgproc::match_template(
+        edges,
+        template,
+        res,
+        cv::imgproc::TM_CCORR_NORMED,
+        // INFO: We don't use mask here because somehow without mask, the result is more accurate
+        &cv::core::no_array(),
+    )?;
+    Ok(())
+}
+
+// Copies the single channel of `mat` into `arr`, going through `converted` for the conversion to
+// `f32`.
+#[cfg(feature = "opencv")]
+fn convert_mat_to_array2_into<T: ResponseElement>(
+    mat: &cv::core::Mat,
+    converted: &mut cv::core::Mat,
+    arr: &mut nd::Array2<T>,
+) -> Result<()> {
+    use crate::TryFromCv;
+    mat.convert_to(converted, cv::core::CV_32F, 1.0, 0.0)?;
+    let view = nd::ArrayView3::<f32>::try_from_cv(&*converted)?;
+    let view = crate::extract_response_map(view)?;
+    crate::imgproc::ensure_shape(arr, view.dim());
+    arr.zip_mut_with(&view, |el, &v| *el = T::from_confidence(v));
+    Ok(())
+}
+
+#[cfg(feature = "opencv")]
+fn convert_array2_to_mat(arr: &nd::ArrayView2<f32>) -> Result<cv::core::Mat> {
+    use crate::TryFromCv;
+    cv::core::Mat::try_from_cv(&arr.insert_axis(nd::Axis(2)))
+}
+
+// Converts the baked templates once for `MatchingBackend::OpenCv`, leaving them out for the others.
+#[cfg(feature = "opencv")]
+fn convert_template_mats(
+    backend: MatchingBackend,
+    baked_templates: &[nd::Array2<f32>],
+) -> Result<Vec<cv::core::Mat>> {
+    match backend {
+        MatchingBackend::OpenCv => baked_templates
+            .iter()
+            .map(|t| convert_array2_to_mat(&t.view()))
+            .collect(),
+        MatchingBackend::Native | MatchingBackend::NativeFft => Ok(Vec::new()),
+    }
+}
+
+// Same as `convert_array2_to_mat`, copying into `mat`, which is only reallocated when the shape
+// changes.
+#[cfg(feature = "opencv")]
+fn convert_array2_to_mat_into(arr: &nd::ArrayView2<f32>, mat: &mut cv::core::Mat) -> Result<()> {
+    let (rows, cols) = (arr.nrows() as i32, arr.ncols() as i32);
+    if mat.rows() != rows || mat.cols() != cols || mat.typ() != cv::core::CV_32FC1 {
+        *mat = cv::core::Mat::new_rows_cols_with_default(
+            rows,
+            cols,
+            cv::core::CV_32FC1,
+            cv::core::Scalar::all(0.0),
+        )?;
+    }
+    for (el, &v) in mat.data_typed_mut::<f32>()?.iter_mut().zip(arr.iter()) {
+        *el = v;
+    }
+    Ok(())
+}
+
+/// Template matched by [TemplateMatcher], labelled to tell its results apart.
+///
+/// Thresholds are applied by the search over all the responses rather than per template.
+#[derive(Debug, Clone)]
+pub struct MatchDescriptor {
+    pub label: String,
+    pub template: image::RgbaImage,
+    source_hash: Option<u64>,
+}
+
+impl MatchDescriptor {
+    pub fn new(label: String, template: image::RgbaImage) -> Self {
+        Self {
+            label,
+            template,
+            source_hash: None,
+        }
+    }
+
+    /// Keys the [TemplateCache] entries of the template on `hash`, see [hash_encoded_template],
+    /// rather than on the decoded template.
+    pub fn with_source_hash(mut self, hash: u64) -> Self {
+        self.source_hash = Some(hash);
+        self
+    }
+
+    pub fn source_hash(&self) -> Option<u64> {
+        self.source_hash
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TemplateMatcherResult {
+    label: String,
+    response: nd::Array2<f32>,
+}
+
+impl TemplateMatcherResult {
+    pub(crate) fn new(label: String, response: nd::Array2<f32>) -> Self {
+        Self { label, response }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Response map in `(row, col)` order.
+    pub fn response(&self) -> &nd::Array2<f32> {
+        &self.response
+    }
+
+    /// Response map in the `(x, y)` order that [crate::find_direction_commands] expects.
+    pub fn to_search_layout(&self) -> nd::ArrayView2<'_, f32> {
+        self.response.t()
+    }
+
+    pub fn min_max_loc(&self) -> (f32, f32, Point, Point) {
+        let mut min = (f32::INFINITY, Point::zeroed());
+        let mut max = (f32::NEG_INFINITY, Point::zeroed());
+        for ((y, x), &v) in self.response.indexed_iter() {
+            if v < min.0 {
+                min = (v, Point::new(x, y));
+            }
+            if v > max.0 {
+                max = (v, Point::new(x, y));
+            }
+        }
+        (min.0, max.0, min.1, max.1)
+    }
+
+    pub fn position(&self) -> Point {
+        let (_min_val, _max_val, _min_loc, max_loc) = self.min_max_loc();
+        max_loc
+    }
+}
+
+impl From<TemplateMatcherResult> for nd::Array2<f32> {
+    fn from(res: TemplateMatcherResult) -> Self {
+        res.response
+    }
+}
+
+impl AsRef<nd::Array2<f32>> for TemplateMatcherResult {
+    fn as_ref(&self) -> &nd::Array2<f32> {
+        &self.response
+    }
+}
@@ -0,0 +1,50 @@
+use super::FftBuffers;
+use crate::{imgproc::CannyBuffers, IntermediaryDirection, ResponseElement};
+use ndarray as nd;
+#[cfg(feature = "opencv")]
+use opencv as cv;
+
+/// Buffers of a match, kept between matches so that frames of the same size don't allocate them
+/// again, see [crate::TemplateMatcher::match_templates_into].
+///
+/// Each buffer is only reallocated when the frame size changes. A workspace serves a single match
+/// at a time, so workers matching in parallel need one each. The response maps are stored as `T`,
+/// see [crate::ResponsePrecision].
+#[derive(Debug, Default)]
+pub struct MatchWorkspace<T: ResponseElement = f32> {
+    pub(crate) gray: nd::Array2<f32>,
+    pub(crate) blur_pass: nd::Array2<f32>,
+    pub(crate) blurred: nd::Array2<f32>,
+    pub(crate) canny: CannyBuffers,
+    pub(crate) edges: nd::Array2<f32>,
+    pub(crate) sq_integral: nd::Array2<f64>,
+    pub(crate) fft: FftBuffers,
+    pub(crate) responses: Vec<nd::Array2<T>>,
+    pub(crate) direction_buffer: nd::Array2<IntermediaryDirection>,
+    #[cfg(feature = "opencv")]
+    pub(crate) mats: MatBuffers,
+}
+
+impl<T: ResponseElement> MatchWorkspace<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the response maps of the last match, in the order of the descriptors.
+    pub fn responses(&self) -> &[nd::Array2<T>] {
+        &self.responses
+    }
+}
+
+// OpenCV reallocates the output of its functions only when the size or the type changes, so
+// passing the same `Mat`s every time is enough to reuse them.
+#[cfg(feature = "opencv")]
+#[derive(Debug, Default)]
+pub(crate) struct MatBuffers {
+    pub(crate) gray: cv::core::Mat,
+    pub(crate) blurred: cv::core::Mat,
+    pub(crate) canny: cv::core::Mat,
+    pub(crate) edges: cv::core::Mat,
+    pub(crate) response: cv::core::Mat,
+    pub(crate) converted: cv::core::Mat,
+}
@@ -0,0 +1,83 @@
+use crate::TemplateMatcherResult;
+
+/// Finds the row bands of a frame that likely contain arrows, from the responses of a search on a
+/// downscaled copy of it.
+///
+/// `scale` is the size of the coarse frame relative to the full-resolution one. The returned bands
+/// are `(start, end)` row ranges in full resolution, grown by `padding` on both sides, clamped to
+/// `frame_height` and merged when they overlap.
+pub fn find_candidate_bands(
+    coarse_results: &[TemplateMatcherResult],
+    threshold: f32,
+    scale: f64,
+    template_height: usize,
+    padding: usize,
+    frame_height: usize,
+) -> Vec<(usize, usize)> {
+    let coarse_height = coarse_results
+        .iter()
+        .map(|res| res.response().nrows())
+        .max()
+        .unwrap_or(0);
+    let mut hit_rows = vec![false; coarse_height];
+    for res in coarse_results {
+        for (y, row) in res.response().rows().into_iter().enumerate() {
+            if row.iter().any(|&v| v >= threshold) {
+                hit_rows[y] = true;
+            }
+        }
+    }
+
+    let mut bands: Vec<(usize, usize)> = Vec::new();
+    for (y, _) in hit_rows.iter().enumerate().filter(|(_, &hit)| hit) {
+        let start = ((y as f64 / scale).floor() as usize).saturating_sub(padding);
+        let end = (((y + 1) as f64 / scale).ceil() as usize + template_height + padding)
+            .min(frame_height);
+        if start >= end {
+            continue;
+        }
+        match bands.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => bands.push((start, end)),
+        }
+    }
+    bands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray as nd;
+
+    fn result_with_hits(shape: (usize, usize), hits: &[(usize, usize)]) -> TemplateMatcherResult {
+        let mut response = nd::Array2::<f32>::zeros(shape);
+        for &(y, x) in hits {
+            response[[y, x]] = 1.0;
+        }
+        TemplateMatcherResult::new("up".to_owned(), response)
+    }
+
+    #[test]
+    fn test_find_candidate_bands() {
+        let results = [
+            result_with_hits((50, 20), &[(10, 3), (11, 8), (30, 5)]),
+            result_with_hits((50, 20), &[(12, 1)]),
+        ];
+        let bands = find_candidate_bands(&results, 0.9, 0.5, 8, 2, 110);
+        assert_eq!(bands, vec![(18, 36), (58, 72)]);
+    }
+
+    #[test]
+    fn test_find_candidate_bands_clamps_and_merges() {
+        let results = [result_with_hits((10, 10), &[(0, 0), (4, 0), (9, 0)])];
+        let bands = find_candidate_bands(&results, 0.9, 0.5, 6, 4, 20);
+        assert_eq!(bands, vec![(0, 20)]);
+    }
+
+    #[test]
+    fn test_find_candidate_bands_without_hits() {
+        let results = [result_with_hits((10, 10), &[])];
+        assert!(find_candidate_bands(&results, 0.9, 0.5, 6, 4, 20).is_empty());
+        assert!(find_candidate_bands(&[], 0.9, 0.5, 6, 4, 20).is_empty());
+    }
+}
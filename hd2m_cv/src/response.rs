@@ -0,0 +1,77 @@
+use half::f16;
+
+/// Element type response maps are stored in, trading the precision of the confidences for memory
+/// bandwidth.
+///
+/// Matching still computes in `f32`, only the stored maps are narrowed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponsePrecision {
+    #[default]
+    F32,
+    /// Half precision, within `5e-4` of the `f32` confidences around `1.0`.
+    F16,
+    /// Confidences quantized to `0..=255`, with negative correlations clamped to zero. Steps of
+    /// `1 / 255` are coarse for thresholds close to `1.0`, so those are better used with `F16`.
+    U8,
+}
+
+/// Element of a response map, converting to and from `f32` confidences.
+pub trait ResponseElement: Copy + Default + Send + Sync + 'static {
+    fn from_confidence(confidence: f32) -> Self;
+
+    fn to_confidence(self) -> f32;
+}
+
+impl ResponseElement for f32 {
+    fn from_confidence(confidence: f32) -> Self {
+        confidence
+    }
+
+    fn to_confidence(self) -> f32 {
+        self
+    }
+}
+
+impl ResponseElement for f16 {
+    fn from_confidence(confidence: f32) -> Self {
+        f16::from_f32(confidence)
+    }
+
+    fn to_confidence(self) -> f32 {
+        self.to_f32()
+    }
+}
+
+impl ResponseElement for u8 {
+    fn from_confidence(confidence: f32) -> Self {
+        // INFO: NaN saturates to zero, same as a flat region.
+        (confidence.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8
+    }
+
+    fn to_confidence(self) -> f32 {
+        self as f32 / u8::MAX as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_element_round_trip() {
+        for confidence in [0.0, 0.25, 0.5, 0.9, 0.987, 0.99, 1.0] {
+            assert_eq!(f32::from_confidence(confidence).to_confidence(), confidence);
+            let half = f16::from_confidence(confidence).to_confidence();
+            assert!((half - confidence).abs() <= 5e-4, "{half} != {confidence}");
+            let quantized = u8::from_confidence(confidence).to_confidence();
+            assert!(
+                (quantized - confidence).abs() <= 0.5 / 255.0 + 1e-6,
+                "{quantized} != {confidence}"
+            );
+        }
+        assert_eq!(u8::from_confidence(-0.5), 0);
+        assert_eq!(u8::from_confidence(1.5), u8::MAX);
+        assert_eq!(u8::from_confidence(f32::NAN), 0);
+        assert!(f16::from_confidence(f32::NAN).to_confidence().is_nan());
+    }
+}
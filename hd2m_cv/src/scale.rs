@@ -1,56 +1,84 @@
-use std::collections::BTreeMap;
+// Layout of the stratagem panel at the base screen size, measured from in-game captures.
+pub(crate) const PANEL_ORIGIN: (f64, f64) = (157.0, 142.0);
+pub(crate) const PANEL_ROW_PITCH: f64 = 69.0;
+pub(crate) const PANEL_ARROW_PITCH: f64 = 29.0;
+pub(crate) const PANEL_ICON_SIZE: f64 = 58.0;
+pub(crate) const PANEL_ICON_MARGIN: f64 = 20.0;
+pub(crate) const PANEL_NAME_HEIGHT: f64 = 16.0;
+// Part of the base screen that holds the panel with its longest codes, as `(x, y, width, height)`.
+const PANEL_ROI: (f64, f64, f64, f64) = (0.0, 0.0, 420.0, 670.0);
 
-#[derive(Debug, Clone)]
-pub struct AutoScale {
-    original_size: (usize, usize),
-    target_size: Option<(usize, usize)>,
-    variants: BTreeMap<String, usize>,
+/// Where the HUD ends up on a screen, from its resolution, aspect ratio and the in-game HUD scale.
+///
+/// The HUD keeps its proportions and fits inside the screen, so it follows the height on screens
+/// wider than the base one (e.g. ultrawide) and the width on taller ones (e.g. 16:10). The HUD
+/// scale applies on top of that.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HudLayout {
+    screen_size: (usize, usize),
+    base_screen_size: (usize, usize),
+    hud_scale: f64,
 }
 
-impl AutoScale {
-    pub fn new(width: usize, height: usize) -> Self {
+impl HudLayout {
+    /// Layout of a `screen_size` screen, where `base_screen_size` is the screen size the templates
+    /// and panel measurements were taken at.
+    pub fn new(screen_size: (usize, usize), base_screen_size: (usize, usize)) -> Self {
         Self {
-            original_size: (width, height),
-            target_size: None,
-            variants: BTreeMap::new(),
+            screen_size,
+            base_screen_size,
+            hud_scale: 1.0,
         }
     }
 
-    pub fn clone_with_target_size(&self, width: usize, height: usize) -> Self {
-        let mut new = self.clone();
-        new.set_target_size(width, height);
-        new
+    pub fn with_hud_scale(mut self, hud_scale: f64) -> Self {
+        self.hud_scale = hud_scale;
+        self
     }
 
-    pub fn set_target_size(&mut self, width: usize, height: usize) {
-        self.target_size = Some((width, height));
+    pub fn screen_size(&self) -> (usize, usize) {
+        self.screen_size
     }
 
-    pub fn add_variant(&mut self, name: &str, size: usize) {
-        self.variants.insert(name.to_string(), size);
+    pub fn base_screen_size(&self) -> (usize, usize) {
+        self.base_screen_size
     }
 
-    pub fn variant_scale(&self, variant: &str) -> Option<usize> {
-        let &size = self.variants.get(variant)?;
-        let (target_width, target_height) = self.target_size.unwrap_or(self.original_size);
-        let scale_x = target_width as f64 / self.original_size.0 as f64;
-        let scale_y = target_height as f64 / self.original_size.1 as f64;
-        let box_scale = scale_x.min(scale_y);
-        Some((size as f64 * box_scale) as usize)
+    pub fn hud_scale(&self) -> f64 {
+        self.hud_scale
     }
 
-    pub fn variant_scale_x(&self, variant: &str) -> Option<usize> {
-        let &size = self.variants.get(variant)?;
-        let (target_width, _) = self.target_size.unwrap_or(self.original_size);
-        let scale_x = target_width as f64 / self.original_size.0 as f64;
-        Some((size as f64 * scale_x) as usize)
+    pub fn aspect_ratio(&self) -> f64 {
+        self.screen_size.0 as f64 / self.screen_size.1 as f64
     }
 
-    pub fn variant_scale_y(&self, variant: &str) -> Option<usize> {
-        let &size = self.variants.get(variant)?;
-        let (_, target_height) = self.target_size.unwrap_or(self.original_size);
-        let scale_y = target_height as f64 / self.original_size.1 as f64;
-        Some((size as f64 * scale_y) as usize)
+    /// Returns the scale of the HUD relative to the base screen size, which is also the scale of
+    /// the templates.
+    pub fn scale(&self) -> f64 {
+        let scale_x = self.screen_size.0 as f64 / self.base_screen_size.0 as f64;
+        let scale_y = self.screen_size.1 as f64 / self.base_screen_size.1 as f64;
+        scale_x.min(scale_y) * self.hud_scale
+    }
+
+    /// Returns the part of the screen holding the stratagem panel as `(x, y, width, height)`,
+    /// clamped to the screen.
+    ///
+    /// The panel stays at the top-left corner of the screen regardless of the aspect ratio.
+    pub fn panel_roi(&self) -> (usize, usize, usize, usize) {
+        self.screen_rect(PANEL_ROI)
+    }
+
+    /// Returns where the `(x, y, width, height)` part of the HUD, measured at the base screen size
+    /// and a HUD scale of `1.0`, ends up on the screen, clamped to it.
+    pub fn screen_rect(&self, base_rect: (f64, f64, f64, f64)) -> (usize, usize, usize, usize) {
+        let scale = self.scale();
+        let (width, height) = self.screen_size;
+        let (x, y, roi_width, roi_height) = base_rect;
+        let left = ((x * scale) as usize).min(width);
+        let top = ((y * scale) as usize).min(height);
+        let right = (((x + roi_width) * scale).round() as usize).min(width);
+        let bottom = (((y + roi_height) * scale).round() as usize).min(height);
+        (left, top, right - left, bottom - top)
     }
 }
 
@@ -58,24 +86,46 @@ impl AutoScale {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_auto_scale() {
-        let mut scale = AutoScale::new(100, 100);
-        scale.set_target_size(200, 200);
-        scale.add_variant("small", 50);
-        scale.add_variant("medium", 100);
-        scale.add_variant("large", 150);
+    const BASE_SCREEN_SIZE: (usize, usize) = (2560, 1440);
 
-        assert_eq!(scale.variant_scale("small"), Some(100));
-        assert_eq!(scale.variant_scale("medium"), Some(200));
-        assert_eq!(scale.variant_scale("large"), Some(300));
-
-        assert_eq!(scale.variant_scale_x("small"), Some(100));
-        assert_eq!(scale.variant_scale_x("medium"), Some(200));
-        assert_eq!(scale.variant_scale_x("large"), Some(300));
+    #[test]
+    fn test_hud_layout_scale() {
+        assert_eq!(HudLayout::new((2560, 1440), BASE_SCREEN_SIZE).scale(), 1.0);
+        assert_eq!(HudLayout::new((1920, 1080), BASE_SCREEN_SIZE).scale(), 0.75);
+        assert_eq!(HudLayout::new((3840, 2160), BASE_SCREEN_SIZE).scale(), 1.5);
+        // Ultrawide follows the height, 16:10 follows the width.
+        assert_eq!(HudLayout::new((3440, 1440), BASE_SCREEN_SIZE).scale(), 1.0);
+        assert_eq!(HudLayout::new((5120, 1440), BASE_SCREEN_SIZE).scale(), 1.0);
+        assert_eq!(HudLayout::new((1920, 1200), BASE_SCREEN_SIZE).scale(), 0.75);
+        assert_eq!(
+            HudLayout::new((2560, 1440), BASE_SCREEN_SIZE)
+                .with_hud_scale(1.25)
+                .scale(),
+            1.25
+        );
+    }
 
-        assert_eq!(scale.variant_scale_y("small"), Some(100));
-        assert_eq!(scale.variant_scale_y("medium"), Some(200));
-        assert_eq!(scale.variant_scale_y("large"), Some(300));
+    #[test]
+    fn test_hud_layout_panel_roi() {
+        assert_eq!(
+            HudLayout::new((2560, 1440), BASE_SCREEN_SIZE).panel_roi(),
+            (0, 0, 420, 670)
+        );
+        assert_eq!(
+            HudLayout::new((1920, 1080), BASE_SCREEN_SIZE).panel_roi(),
+            (0, 0, 315, 503)
+        );
+        // Not stretched along with the width of an ultrawide screen.
+        assert_eq!(
+            HudLayout::new((3440, 1440), BASE_SCREEN_SIZE).panel_roi(),
+            (0, 0, 420, 670)
+        );
+        // Clamped to the screen.
+        assert_eq!(
+            HudLayout::new((800, 600), BASE_SCREEN_SIZE)
+                .with_hud_scale(3.0)
+                .panel_roi(),
+            (0, 0, 394, 600)
+        );
     }
 }
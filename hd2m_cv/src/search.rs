@@ -1,11 +1,15 @@
+use crate::ResponseElement;
 use ndarray::{self as nd, parallel::prelude::*};
 use std::collections::BTreeMap;
 
-pub fn find_direction_commands(
-    up: &nd::ArrayView2<f32>,
-    down: &nd::ArrayView2<f32>,
-    right: &nd::ArrayView2<f32>,
-    left: &nd::ArrayView2<f32>,
+/// Finds the rows of arrows in the response maps of the four templates, in the `(x, y)` layout.
+///
+/// The maps may hold any [ResponseElement], e.g. quantized confidences.
+pub fn find_direction_commands<T: ResponseElement>(
+    up: &nd::ArrayView2<T>,
+    down: &nd::ArrayView2<T>,
+    right: &nd::ArrayView2<T>,
+    left: &nd::ArrayView2<T>,
     threshold: Option<f32>,
     search_chunk_size: Option<usize>,
     discarding_distance_threshold: Option<f64>,
@@ -13,7 +17,7 @@ pub fn find_direction_commands(
     let threshold = threshold.unwrap_or(0.9);
     let search_chunk_size = search_chunk_size.unwrap_or(3);
     let discarding_distance_threshold = discarding_distance_threshold.unwrap_or(30.0);
-    let dir_buf = raw_mats_to_direction_buffer(up, right, down, left, threshold)?;
+    let dir_buf = raw_mats_to_direction_buffer(up, down, right, left, threshold)?;
     let commands = collect_direction_commands(
         &dir_buf.view(),
         search_chunk_size,
@@ -22,25 +26,45 @@ pub fn find_direction_commands(
     Ok(commands)
 }
 
-fn raw_mats_to_direction_buffer(
-    up: &nd::ArrayView2<f32>,
-    down: &nd::ArrayView2<f32>,
-    right: &nd::ArrayView2<f32>,
-    left: &nd::ArrayView2<f32>,
+/// Reduces the four response maps into the best direction of each position, or `None` when no
+/// response reaches the threshold.
+pub fn raw_mats_to_direction_buffer<T: ResponseElement>(
+    up: &nd::ArrayView2<T>,
+    down: &nd::ArrayView2<T>,
+    right: &nd::ArrayView2<T>,
+    left: &nd::ArrayView2<T>,
     threshold: f32,
 ) -> anyhow::Result<nd::Array2<IntermediaryDirection>> {
+    let mut buf = nd::Array2::from_elem((0, 0), None);
+    raw_mats_to_direction_buffer_into(up, down, right, left, threshold, &mut buf)?;
+    Ok(buf)
+}
+
+pub(crate) fn raw_mats_to_direction_buffer_into<T: ResponseElement>(
+    up: &nd::ArrayView2<T>,
+    down: &nd::ArrayView2<T>,
+    right: &nd::ArrayView2<T>,
+    left: &nd::ArrayView2<T>,
+    threshold: f32,
+    buf: &mut nd::Array2<IntermediaryDirection>,
+) -> anyhow::Result<()> {
     if up.shape() != right.shape() || up.shape() != down.shape() || up.shape() != left.shape() {
         return Err(anyhow::anyhow!("All mats must have the same shape"));
     }
 
-    let mut buf: nd::Array2<IntermediaryDirection> =
-        nd::Array2::from_elem(up.dim(), Default::default());
-    nd::Zip::from(&mut buf)
+    crate::imgproc::ensure_shape(buf, up.dim());
+    nd::Zip::from(buf)
         .and(up)
-        .and(right)
         .and(down)
+        .and(right)
         .and(left)
         .par_for_each(|buf, &up, &down, &right, &left| {
+            let (up, down, right, left) = (
+                up.to_confidence(),
+                down.to_confidence(),
+                right.to_confidence(),
+                left.to_confidence(),
+            );
             let max = up.max(down).max(right).max(left);
             *buf = if max.is_nan() || max < threshold {
                 None
@@ -58,14 +82,24 @@ fn raw_mats_to_direction_buffer(
             }
         });
 
-    Ok(buf)
+    Ok(())
 }
 
-fn collect_direction_commands(
+/// Finds the rows of arrows in a direction buffer made by [raw_mats_to_direction_buffer].
+pub(crate) fn collect_direction_commands(
     buf: &nd::ArrayView2<IntermediaryDirection>,
     search_chunk_size: usize,
     discarding_window_distance: f64,
 ) -> anyhow::Result<Vec<Vec<DirectionDescriptor>>> {
+    if search_chunk_size == 0 {
+        return Err(anyhow::anyhow!(
+            "Search chunk size must be greater than zero"
+        ));
+    }
+    // `axis_windows` spans the whole of the other axis, which panics when it is empty.
+    if buf.is_empty() {
+        return Ok(Vec::new());
+    }
     // Iterate over the windowed columns and collect the non-None directions.
     let chunks: Vec<Vec<DirectionDescriptor>> = buf
         .axis_windows(nd::Axis(1), search_chunk_size)
@@ -122,15 +156,12 @@ fn collect_direction_commands(
                 // Sanitize the too-close directions as well as `None` values.
                 .filter_map(|dir| {
                     let desc = dir?;
-                    let last_seen_point = last_seen_points
-                        .get(&desc.direction)
-                        .copied()
-                        .unwrap_or(Default::default());
-                    // Discard the direction if it's too close to the previous one.
-                    if !desc.position.is_zero()
-                        && last_seen_point.distance(desc.position) < discarding_window_distance
-                    {
-                        return None;
+                    // Discard the direction if it's too close to the previous one. The first one of
+                    // each direction has nothing to be close to, even next to the origin.
+                    if let Some(last_seen_point) = last_seen_points.get(&desc.direction) {
+                        if last_seen_point.distance(desc.position) < discarding_window_distance {
+                            return None;
+                        }
                     }
                     last_seen_points
                         .entry(desc.direction)
@@ -142,19 +173,11 @@ fn collect_direction_commands(
         })
         .collect();
 
-    let histogram: Vec<usize> = chunks
-        .clone()
-        .into_par_iter()
-        .map(|rows| rows.len())
-        .collect();
+    let histogram: Vec<usize> = chunks.iter().map(|rows| rows.len()).collect();
     let mut peaks: Vec<usize> = Vec::new();
     for (i, &el) in histogram.iter().enumerate() {
-        let previous_bar = if i > 0 { histogram[i - 1] } else { 0 };
-        let next_bar = if i < histogram.len() - 1 {
-            histogram[i + 1]
-        } else {
-            0
-        };
+        let previous_bar = i.checked_sub(1).map_or(0, |i| histogram[i]);
+        let next_bar = histogram.get(i + 1).copied().unwrap_or(0);
         if el >= previous_bar && el > next_bar {
             peaks.push(i);
         }
@@ -187,6 +210,47 @@ pub enum Direction {
     Left,
 }
 
+impl Direction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Right => "right",
+            Direction::Down => "down",
+            Direction::Left => "left",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "up" => Some(Direction::Up),
+            "right" => Some(Direction::Right),
+            "down" => Some(Direction::Down),
+            "left" => Some(Direction::Left),
+            _ => None,
+        }
+    }
+
+    /// Returns the letter of the direction used in written codes, e.g. `u` for [Direction::Up].
+    pub fn letter(&self) -> char {
+        match self {
+            Direction::Up => 'u',
+            Direction::Right => 'r',
+            Direction::Down => 'd',
+            Direction::Left => 'l',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Self> {
+        match letter.to_ascii_lowercase() {
+            'u' => Some(Direction::Up),
+            'r' => Some(Direction::Right),
+            'd' => Some(Direction::Down),
+            'l' => Some(Direction::Left),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, PartialOrd)]
 pub struct Point {
     pub x: usize,
@@ -219,7 +283,6 @@ mod tests {
 
     #[test]
     fn test_find_direction_commands() -> anyhow::Result<()> {
-        let now = std::time::Instant::now();
         // FIXME: Axis is reversed
         let up_arr = nd::Array2::<f32>::from_shape_vec(
             (15, 9), // x, y
@@ -377,8 +440,310 @@ mod tests {
             ]
         );
 
-        println!("Elapsed: {:?}", now.elapsed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_direction_commands_empty() -> anyhow::Result<()> {
+        let empty = nd::Array2::<f32>::zeros((0, 0));
+        let buf = find_direction_commands(
+            &empty.view(),
+            &empty.view(),
+            &empty.view(),
+            &empty.view(),
+            None,
+            None,
+            None,
+        )?;
+        assert!(buf.is_empty());
+
+        let zero_chunk = find_direction_commands(
+            &empty.view(),
+            &empty.view(),
+            &empty.view(),
+            &empty.view(),
+            None,
+            Some(0),
+            None,
+        );
+        assert!(zero_chunk.is_err());
+        Ok(())
+    }
 
+    #[test]
+    fn test_find_direction_commands_near_origin() -> anyhow::Result<()> {
+        // `(x, y)` maps with an arrow right next to the origin, then another one further along.
+        let mut up = nd::Array2::<f32>::zeros((60, 10));
+        up[[2, 3]] = 1.0;
+        up[[40, 3]] = 1.0;
+        let zeros = nd::Array2::<f32>::zeros(up.dim());
+        let buf = find_direction_commands(
+            &up.view(),
+            &zeros.view(),
+            &zeros.view(),
+            &zeros.view(),
+            Some(0.9),
+            Some(3),
+            Some(30.0),
+        )?;
+        assert_eq!(buf.len(), 1);
+        let positions: Vec<_> = buf[0].iter().map(|desc| desc.position).collect();
+        assert_eq!(positions, [Point::new(2, 3), Point::new(40, 3)]);
         Ok(())
     }
+
+    mod synthetic {
+        use super::*;
+        use proptest::prelude::*;
+
+        const DIRECTIONS: [Direction; 4] = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Right,
+            Direction::Left,
+        ];
+        // Covers the jitter and the 3x3 blob around each arrow.
+        const POSITION_TOLERANCE: usize = 2;
+
+        #[derive(Debug, Clone)]
+        struct SyntheticArrow {
+            direction: Direction,
+            position: Point,
+            jitter: (isize, isize),
+            peak: f32,
+            falloff: f32,
+            cross_talk: f32,
+        }
+
+        #[derive(Debug, Clone)]
+        struct SyntheticScene {
+            sequences: Vec<Vec<SyntheticArrow>>,
+            // Search layout, `(x, y)`.
+            size: (usize, usize),
+            threshold: f32,
+            noise_seed: u64,
+        }
+
+        fn direction_strategy() -> impl Strategy<Value = Direction> {
+            prop::sample::select(DIRECTIONS.to_vec())
+        }
+
+        fn arrow_strategy() -> impl Strategy<Value = (Direction, (isize, isize), f32, f32, f32)> {
+            (
+                direction_strategy(),
+                (-1isize..=1, -1isize..=1),
+                0.9f32..=1.0,
+                0.8f32..1.0,
+                0.0f32..0.9,
+            )
+        }
+
+        prop_compose! {
+            fn sequence_strategy(y: usize)(
+                x_start in 3usize..8,
+                spacing in 10usize..16,
+                arrows in prop::collection::vec(arrow_strategy(), 1..=8),
+            ) -> Vec<SyntheticArrow> {
+                arrows
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (direction, jitter, peak, falloff, cross_talk))| SyntheticArrow {
+                        direction,
+                        position: Point::new(x_start + i * spacing, y),
+                        jitter,
+                        peak,
+                        falloff,
+                        cross_talk,
+                    })
+                    .collect()
+            }
+        }
+
+        fn scene_strategy() -> impl Strategy<Value = SyntheticScene> {
+            (1usize..=3, 12usize..20)
+                .prop_flat_map(|(count, row_gap)| {
+                    let sequences: Vec<_> = (0..count)
+                        .map(|k| sequence_strategy(4 + k * row_gap))
+                        .collect();
+                    (sequences, 0.55f32..0.85, any::<u64>())
+                })
+                .prop_map(|(sequences, threshold, noise_seed)| {
+                    let width = sequences
+                        .iter()
+                        .flatten()
+                        .map(|arrow| arrow.position.x + 4)
+                        .max()
+                        .unwrap_or(0);
+                    let height = sequences
+                        .iter()
+                        .flatten()
+                        .map(|arrow| arrow.position.y + 4)
+                        .max()
+                        .unwrap_or(0);
+                    SyntheticScene {
+                        sequences,
+                        size: (width, height),
+                        threshold,
+                        noise_seed,
+                    }
+                })
+        }
+
+        // Scenes where the arrows of a row cover the same rows of the maps, as the ones of a panel do:
+        // no vertical jitter, and the whole blob of every arrow above the threshold.
+        fn aligned_scene_strategy() -> impl Strategy<Value = SyntheticScene> {
+            scene_strategy().prop_map(|mut scene| {
+                for arrow in scene.sequences.iter_mut().flatten() {
+                    arrow.jitter.1 = 0;
+                }
+                let weakest = scene
+                    .sequences
+                    .iter()
+                    .flatten()
+                    .map(|arrow| arrow.peak * arrow.falloff)
+                    .fold(f32::INFINITY, f32::min);
+                scene.threshold = scene.threshold.min(weakest * 0.99);
+                scene
+            })
+        }
+
+        // Deterministic background noise, always below the search threshold.
+        fn noise(seed: u64, index: usize) -> f32 {
+            let hash = (seed ^ index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            (hash >> 40) as f32 / (1u64 << 24) as f32 * 0.5
+        }
+
+        // Renders the scene into `[up, down, right, left]` response maps.
+        fn render(scene: &SyntheticScene) -> [nd::Array2<f32>; 4] {
+            let mut maps: [nd::Array2<f32>; 4] = std::array::from_fn(|i| {
+                nd::Array2::from_shape_fn(scene.size, |(x, y)| {
+                    noise(scene.noise_seed, (i * scene.size.0 + x) * scene.size.1 + y)
+                })
+            });
+            for arrow in scene.sequences.iter().flatten() {
+                let center_x = arrow.position.x as isize + arrow.jitter.0;
+                let center_y = arrow.position.y as isize + arrow.jitter.1;
+                for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        let value = if dx == 0 && dy == 0 {
+                            arrow.peak
+                        } else {
+                            arrow.peak * arrow.falloff
+                        };
+                        let index = [(center_x + dx) as usize, (center_y + dy) as usize];
+                        for (map, direction) in maps.iter_mut().zip(DIRECTIONS) {
+                            // The other templates respond to the arrow too, just weaker.
+                            let value = if direction == arrow.direction {
+                                value
+                            } else {
+                                value * arrow.cross_talk
+                            };
+                            map[index] = map[index].max(value);
+                        }
+                    }
+                }
+            }
+            maps
+        }
+
+        fn matches_arrow(desc: &DirectionDescriptor, arrow: &SyntheticArrow) -> bool {
+            desc.direction == arrow.direction
+                && desc.position.x.abs_diff(arrow.position.x) <= POSITION_TOLERANCE
+                && desc.position.y.abs_diff(arrow.position.y) <= POSITION_TOLERANCE
+        }
+
+        proptest! {
+            #[test]
+            fn test_recovers_synthetic_sequences(scene in scene_strategy()) {
+                let [up, down, right, left] = render(&scene);
+                let buf = find_direction_commands(
+                    &up.view(),
+                    &down.view(),
+                    &right.view(),
+                    &left.view(),
+                    Some(scene.threshold),
+                    Some(3),
+                    Some(4.0),
+                )
+                .unwrap();
+
+                for sequence in scene.sequences.iter() {
+                    prop_assert!(
+                        buf.iter().any(|row| {
+                            row.len() == sequence.len()
+                                && row.iter().zip(sequence).all(|(desc, arrow)| matches_arrow(desc, arrow))
+                        }),
+                        "Sequence {:?} not recovered from {:?}",
+                        sequence,
+                        buf
+                    );
+                }
+                // Arrows of a row jittered to different heights leave windows holding only some of
+                // them, which may show up as extra rows, but never with arrows that do not exist.
+                for desc in buf.iter().flatten() {
+                    prop_assert!(
+                        scene.sequences.iter().flatten().any(|arrow| matches_arrow(desc, arrow)),
+                        "Unexpected direction {:?}",
+                        desc
+                    );
+                }
+            }
+
+            #[test]
+            fn test_recovers_aligned_sequences_exactly(scene in aligned_scene_strategy()) {
+                let [up, down, right, left] = render(&scene);
+                let buf = find_direction_commands(
+                    &up.view(),
+                    &down.view(),
+                    &right.view(),
+                    &left.view(),
+                    Some(scene.threshold),
+                    Some(3),
+                    Some(4.0),
+                )
+                .unwrap();
+
+                // Each row is found exactly once, in order, and nothing else is.
+                prop_assert_eq!(buf.len(), scene.sequences.len(), "{:?}", buf);
+                for (row, sequence) in buf.iter().zip(&scene.sequences) {
+                    prop_assert!(
+                        row.len() == sequence.len()
+                            && row.iter().zip(sequence).all(|(desc, arrow)| matches_arrow(desc, arrow)),
+                        "Sequence {:?} not recovered as {:?}",
+                        sequence,
+                        row
+                    );
+                }
+            }
+
+            #[test]
+            fn test_find_direction_commands_never_panics(
+                (width, height) in (0usize..12, 0usize..12),
+                values in prop::collection::vec(
+                    prop_oneof![any::<f32>(), Just(f32::NAN), 0.0f32..=1.0],
+                    4 * 12 * 12,
+                ),
+                threshold in prop::option::of(any::<f32>()),
+                search_chunk_size in prop::option::of(0usize..16),
+                discarding_distance_threshold in prop::option::of(any::<f64>()),
+            ) {
+                let maps: Vec<nd::Array2<f32>> = values
+                    .chunks(12 * 12)
+                    .map(|chunk| {
+                        nd::Array2::from_shape_vec((width, height), chunk[..width * height].to_vec())
+                            .unwrap()
+                    })
+                    .collect();
+                let _ = find_direction_commands(
+                    &maps[0].view(),
+                    &maps[1].view(),
+                    &maps[2].view(),
+                    &maps[3].view(),
+                    threshold,
+                    search_chunk_size,
+                    discarding_distance_threshold,
+                );
+            }
+        }
+    }
 }
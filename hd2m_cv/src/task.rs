@@ -0,0 +1,118 @@
+use crate::Hd2mCvMatchResult;
+use anyhow::Result;
+use std::{
+    fmt,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll, Waker},
+};
+
+/// Error of a match whose [MatchFuture] was dropped, or that its caller otherwise cancelled,
+/// before it finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchCancelled;
+
+impl fmt::Display for MatchCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Match cancelled")
+    }
+}
+
+impl std::error::Error for MatchCancelled {}
+
+/// Result of a match running on the rayon thread pool, see [crate::Hd2mCvManager::match_async].
+///
+/// Works with any executor, and dropping it cancels the match.
+#[derive(Debug)]
+pub struct MatchFuture {
+    task: Arc<MatchTask>,
+}
+
+#[derive(Debug, Default)]
+struct MatchTask {
+    dropped: AtomicBool,
+    state: Mutex<MatchTaskState>,
+}
+
+#[derive(Debug, Default)]
+struct MatchTaskState {
+    result: Option<Result<Hd2mCvMatchResult>>,
+    waker: Option<Waker>,
+}
+
+impl MatchFuture {
+    /// Runs `job` on the rayon thread pool, passing it a check of whether the future was dropped.
+    pub(crate) fn spawn(
+        job: impl FnOnce(&dyn Fn() -> bool) -> Result<Hd2mCvMatchResult> + Send + 'static,
+    ) -> Self {
+        let task = Arc::new(MatchTask::default());
+        rayon::spawn({
+            let task = task.clone();
+            move || {
+                let is_dropped = || task.dropped.load(Ordering::Relaxed);
+                // INFO: A panic would abort the whole process on the rayon pool, so it is turned
+                // into the result instead.
+                let result = std::panic::catch_unwind(AssertUnwindSafe(|| job(&is_dropped)))
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("Matching panicked")));
+                let mut state = task.state.lock().unwrap_or_else(PoisonError::into_inner);
+                state.result = Some(result);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+        Self { task }
+    }
+}
+
+impl Future for MatchFuture {
+    type Output = Result<Hd2mCvMatchResult>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self
+            .task
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for MatchFuture {
+    fn drop(&mut self) {
+        self.task.dropped.store(true, Ordering::Relaxed);
+    }
+}
+
+// Minimal executor for the tests, which don't otherwise need an async runtime.
+#[cfg(test)]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
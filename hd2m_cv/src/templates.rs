@@ -0,0 +1,55 @@
+use crate::Direction;
+use anyhow::Result;
+
+/// Screen size the bundled templates were captured at.
+pub const BUNDLED_TEMPLATE_SCREEN_SIZE: (usize, usize) = (2560, 1440);
+
+/// Returns the bundled arrow template of `direction`, encoded as PNG.
+pub fn bundled_template_png(direction: Direction) -> &'static [u8] {
+    match direction {
+        Direction::Up => include_bytes!("../resources/up.png"),
+        Direction::Down => include_bytes!("../resources/down.png"),
+        Direction::Right => include_bytes!("../resources/right.png"),
+        Direction::Left => include_bytes!("../resources/left.png"),
+    }
+}
+
+/// Returns the [crate::hash_encoded_template] of the bundled arrow templates, in the order of
+/// [crate::Hd2mCvManagerConfig::template_source_hashes].
+pub fn bundled_template_source_hashes() -> [u64; 4] {
+    [
+        Direction::Up,
+        Direction::Down,
+        Direction::Right,
+        Direction::Left,
+    ]
+    .map(|direction| crate::hash_encoded_template(bundled_template_png(direction)))
+}
+
+/// Decodes the bundled arrow template of `direction`.
+pub fn load_bundled_template(direction: Direction) -> Result<image::RgbaImage> {
+    Ok(image::load_from_memory_with_format(
+        bundled_template_png(direction),
+        image::ImageFormat::Png,
+    )?
+    .to_rgba8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_bundled_template() -> Result<()> {
+        for direction in [
+            Direction::Up,
+            Direction::Down,
+            Direction::Right,
+            Direction::Left,
+        ] {
+            let template = load_bundled_template(direction)?;
+            assert_eq!(template.dimensions(), (20, 21), "{direction:?}");
+        }
+        Ok(())
+    }
+}
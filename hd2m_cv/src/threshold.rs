@@ -0,0 +1,177 @@
+use crate::ResponseElement;
+use ndarray as nd;
+
+const HISTOGRAM_BINS: usize = 256;
+
+/// How the search threshold is picked for each frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ThresholdMode {
+    /// Always uses the configured threshold.
+    ///
+    /// [select_threshold] doesn't know that threshold and returns its `floor` instead, so callers
+    /// picking the threshold for every mode pass the configured one as the floor in this mode.
+    #[default]
+    Fixed,
+    /// Otsu's method over the histogram of the best response of each position.
+    Otsu,
+    /// The given number of standard deviations above the mean of the best responses.
+    StdDev(f32),
+}
+
+/// Picks a threshold from the distribution of the responses, clamped to `floor..=ceiling`.
+///
+/// Only the best response of each position is considered, since that is what the threshold is
+/// compared against. Returns `floor` when there is nothing to pick from.
+///
+/// [ThresholdMode::Fixed] picks nothing from the responses, so it always returns `floor`, which is
+/// then expected to be the fixed threshold rather than the floor of the adaptive modes.
+pub fn select_threshold(
+    responses: &[nd::ArrayView2<f32>],
+    mode: ThresholdMode,
+    floor: f32,
+    ceiling: f32,
+) -> f32 {
+    select_threshold_from_scores(&best_responses(responses), mode, floor, ceiling)
+}
+
+/// Same as [select_threshold], but from scores already gathered with [best_responses], e.g. from
+/// several parts of a frame.
+pub fn select_threshold_from_scores(
+    scores: &[f32],
+    mode: ThresholdMode,
+    floor: f32,
+    ceiling: f32,
+) -> f32 {
+    let threshold = match mode {
+        ThresholdMode::Fixed => None,
+        ThresholdMode::Otsu => otsu_threshold(scores),
+        ThresholdMode::StdDev(k) => std_dev_threshold(scores, k),
+    };
+    threshold.unwrap_or(floor).clamp(floor, ceiling.max(floor))
+}
+
+/// Returns the best response of each position across the maps as `f32` confidences, skipping NaNs.
+pub fn best_responses<T: ResponseElement>(responses: &[nd::ArrayView2<T>]) -> Vec<f32> {
+    let Some(first) = responses.first() else {
+        return Vec::new();
+    };
+    if responses.iter().any(|res| res.dim() != first.dim()) {
+        // Falls back to pooling every response when the maps do not line up.
+        return responses
+            .iter()
+            .flat_map(|res| res.iter().map(|v| v.to_confidence()))
+            .filter(|v| !v.is_nan())
+            .collect();
+    }
+    let mut best = first.mapv(T::to_confidence);
+    for res in responses[1..].iter() {
+        nd::Zip::from(&mut best)
+            .and(res)
+            .for_each(|best, &v| *best = best.max(v.to_confidence()));
+    }
+    best.into_iter().filter(|v| !v.is_nan()).collect()
+}
+
+fn otsu_threshold(scores: &[f32]) -> Option<f32> {
+    if scores.is_empty() {
+        return None;
+    }
+    let mut histogram = [0usize; HISTOGRAM_BINS];
+    for &v in scores {
+        let bin = (v.clamp(0.0, 1.0) * (HISTOGRAM_BINS - 1) as f32).round() as usize;
+        histogram[bin] += 1;
+    }
+
+    let total = scores.len() as f64;
+    let total_sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| i as f64 * count as f64)
+        .sum();
+    let mut background_count = 0.0;
+    let mut background_sum = 0.0;
+    let mut best = None;
+    let mut best_variance = 0.0;
+    for (i, &count) in histogram.iter().enumerate() {
+        background_count += count as f64;
+        background_sum += i as f64 * count as f64;
+        let foreground_count = total - background_count;
+        if background_count == 0.0 || foreground_count == 0.0 {
+            continue;
+        }
+        let background_mean = background_sum / background_count;
+        let foreground_mean = (total_sum - background_sum) / foreground_count;
+        let variance =
+            background_count * foreground_count * (background_mean - foreground_mean).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = Some(i);
+        }
+    }
+    // Everything above the last background bin is foreground.
+    best.map(|i| (i + 1) as f32 / (HISTOGRAM_BINS - 1) as f32)
+}
+
+fn std_dev_threshold(scores: &[f32], k: f32) -> Option<f32> {
+    if scores.is_empty() {
+        return None;
+    }
+    let len = scores.len() as f64;
+    let mean = scores.iter().map(|&v| v as f64).sum::<f64>() / len;
+    let variance = scores
+        .iter()
+        .map(|&v| (v as f64 - mean).powi(2))
+        .sum::<f64>()
+        / len;
+    Some((mean + k as f64 * variance.sqrt()) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mostly background around 0.2, with a few peaks around 0.9.
+    fn bimodal() -> nd::Array2<f32> {
+        nd::Array2::from_shape_fn((40, 40), |(y, x)| {
+            if (y * 40 + x) % 50 == 0 {
+                0.9
+            } else {
+                0.2 + ((y * 7 + x * 13) % 10) as f32 * 0.01
+            }
+        })
+    }
+
+    #[test]
+    fn test_select_threshold_otsu() {
+        let res = bimodal();
+        let threshold = select_threshold(&[res.view()], ThresholdMode::Otsu, 0.0, 1.0);
+        assert!(threshold > 0.29 && threshold <= 0.9, "{threshold}");
+    }
+
+    #[test]
+    fn test_select_threshold_std_dev() {
+        let res = bimodal();
+        let threshold = select_threshold(&[res.view()], ThresholdMode::StdDev(3.0), 0.0, 1.0);
+        assert!(threshold > 0.29 && threshold <= 0.9, "{threshold}");
+    }
+
+    #[test]
+    fn test_select_threshold_clamps() {
+        let res = bimodal();
+        let low = nd::Array2::<f32>::zeros((40, 40));
+        assert_eq!(
+            select_threshold(&[res.view(), low.view()], ThresholdMode::Otsu, 0.6, 0.95),
+            0.6
+        );
+        assert_eq!(
+            select_threshold(&[res.view()], ThresholdMode::StdDev(100.0), 0.6, 0.95),
+            0.95
+        );
+        // The fixed threshold is passed as the floor.
+        assert_eq!(
+            select_threshold(&[res.view()], ThresholdMode::Fixed, 0.6, 0.95),
+            0.6
+        );
+        assert_eq!(select_threshold(&[], ThresholdMode::Otsu, 0.6, 0.95), 0.6);
+    }
+}
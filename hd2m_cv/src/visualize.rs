@@ -0,0 +1,416 @@
+use crate::{Direction, DirectionDescriptor, Hd2mCvDebugSnapshot, Point, TemplateMatcherResult};
+use anyhow::Result;
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+
+const ROI_COLOR: Rgba<u8> = Rgba([255, 0, 255, 255]);
+const ROW_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const REJECTED_COLOR: Rgba<u8> = Rgba([150, 150, 150, 255]);
+const ROW_PADDING: i64 = 3;
+
+// 3x5 bitmap glyphs for the labels, one bit per pixel from the top-left, row by row.
+const GLYPH_WIDTH: i64 = 3;
+const GLYPH_HEIGHT: i64 = 5;
+const DIGIT_GLYPHS: [u16; 10] = [
+    0b111_101_101_101_111,
+    0b010_110_010_010_111,
+    0b111_001_111_100_111,
+    0b111_001_111_001_111,
+    0b101_101_111_001_001,
+    0b111_100_111_001_111,
+    0b111_100_111_101_111,
+    0b111_001_010_010_010,
+    0b111_101_111_101_111,
+    0b111_101_111_001_111,
+];
+const DOT_GLYPH: u16 = 0b000_000_000_000_010;
+const HASH_GLYPH: u16 = 0b101_111_101_111_101;
+
+/// Everything to draw on top of a frame, see [draw_detection_overlay].
+#[derive(Debug, Clone, Default)]
+pub struct DetectionOverlay {
+    /// Region of the frame that was searched as `(x, y, width, height)`. Descriptors are relative
+    /// to it.
+    pub roi: Option<(usize, usize, usize, usize)>,
+    /// Accepted arrows, one row per stratagem.
+    pub rows: Vec<Vec<DirectionDescriptor>>,
+    /// Candidates above the threshold that did not make it into any row.
+    pub rejected: Vec<DirectionDescriptor>,
+    /// Size of the matched templates as `(width, height)`, used for the boxes.
+    pub template_size: (usize, usize),
+}
+
+/// Returns the color used for the boxes of a direction.
+pub fn direction_color(direction: Direction) -> Rgba<u8> {
+    match direction {
+        Direction::Up => Rgba([255, 64, 64, 255]),
+        Direction::Right => Rgba([64, 220, 64, 255]),
+        Direction::Down => Rgba([64, 140, 255, 255]),
+        Direction::Left => Rgba([255, 200, 0, 255]),
+    }
+}
+
+/// Renders the detection result onto a copy of the frame.
+///
+/// Arrows are boxed in their direction color with the confidence below, rows are outlined and
+/// numbered, and rejected candidates are boxed in gray.
+pub fn draw_detection_overlay(frame: &RgbaImage, overlay: &DetectionOverlay) -> RgbaImage {
+    let mut canvas = frame.clone();
+    let (offset_x, offset_y) = overlay
+        .roi
+        .map_or((0, 0), |(x, y, _, _)| (x as i64, y as i64));
+    let (template_width, template_height) = (
+        overlay.template_size.0.max(1) as i64,
+        overlay.template_size.1.max(1) as i64,
+    );
+    let label_scale = (template_height / 16).max(1);
+    let to_canvas = |position: Point| (offset_x + position.x as i64, offset_y + position.y as i64);
+
+    if let Some((x, y, width, height)) = overlay.roi {
+        draw_rect(
+            &mut canvas,
+            (x as i64, y as i64),
+            (width as i64, height as i64),
+            ROI_COLOR,
+        );
+    }
+
+    for desc in overlay.rejected.iter() {
+        let (x, y) = to_canvas(desc.position);
+        draw_rect(
+            &mut canvas,
+            (x, y),
+            (template_width, template_height),
+            REJECTED_COLOR,
+        );
+        draw_confidence(
+            &mut canvas,
+            desc.confidence,
+            (x, y + template_height + 1),
+            label_scale,
+            REJECTED_COLOR,
+        );
+    }
+
+    for (i, row) in overlay.rows.iter().enumerate() {
+        let bounds: Option<(i64, i64, i64, i64)> = row.iter().fold(None, |bounds, desc| {
+            let (x, y) = to_canvas(desc.position);
+            let (x2, y2) = (x + template_width, y + template_height);
+            Some(match bounds {
+                None => (x, y, x2, y2),
+                Some((l, t, r, b)) => (l.min(x), t.min(y), r.max(x2), b.max(y2)),
+            })
+        });
+        let Some((left, top, right, bottom)) = bounds else {
+            continue;
+        };
+        let (left, top) = (left - ROW_PADDING, top - ROW_PADDING);
+        let bottom = bottom + ROW_PADDING + (GLYPH_HEIGHT + 2) * label_scale;
+        draw_rect(
+            &mut canvas,
+            (left, top),
+            (right + ROW_PADDING - left, bottom - top),
+            ROW_COLOR,
+        );
+        let label = format!("#{i}");
+        let label_width = (GLYPH_WIDTH + 1) * label_scale * label.len() as i64;
+        // Left of the row, or right of it for rows at the left edge of the canvas.
+        let label_x = match left - label_width - 2 {
+            x if x < 0 => right + ROW_PADDING + 2,
+            x => x,
+        };
+        draw_text(
+            &mut canvas,
+            &label,
+            (label_x, top.max(0)),
+            label_scale,
+            ROW_COLOR,
+        );
+
+        for desc in row.iter() {
+            let (x, y) = to_canvas(desc.position);
+            let color = direction_color(desc.direction);
+            draw_rect(
+                &mut canvas,
+                (x, y),
+                (template_width, template_height),
+                color,
+            );
+            draw_confidence(
+                &mut canvas,
+                desc.confidence,
+                (x, y + template_height + 1),
+                label_scale,
+                color,
+            );
+        }
+    }
+
+    canvas
+}
+
+/// Same as [draw_detection_overlay], but saves the result to `path` in the format of its extension.
+pub fn save_detection_overlay(
+    frame: &RgbaImage,
+    overlay: &DetectionOverlay,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    draw_detection_overlay(frame, overlay).save(path)?;
+    Ok(())
+}
+
+/// Finds the candidates of the match results that are at least `threshold` but not part of any of
+/// the accepted `rows`.
+///
+/// Candidates closer than `min_distance` to a stronger one, or to an accepted arrow, are dropped.
+pub fn find_rejected_candidates(
+    results: &[TemplateMatcherResult],
+    rows: &[Vec<DirectionDescriptor>],
+    threshold: f32,
+    min_distance: f64,
+) -> Vec<DirectionDescriptor> {
+    let mut candidates: Vec<DirectionDescriptor> = results
+        .iter()
+        .filter_map(|res| Some((Direction::from_label(res.label())?, res)))
+        .flat_map(|(direction, res)| {
+            res.response()
+                .indexed_iter()
+                .filter(|(_, &v)| v >= threshold)
+                .map(move |((y, x), &confidence)| DirectionDescriptor {
+                    direction,
+                    position: Point::new(x, y),
+                    confidence,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    let mut rejected: Vec<DirectionDescriptor> = Vec::new();
+    for candidate in candidates {
+        let is_near =
+            |desc: &DirectionDescriptor| desc.position.distance(candidate.position) < min_distance;
+        if rows.iter().flatten().any(is_near) || rejected.iter().any(is_near) {
+            continue;
+        }
+        rejected.push(candidate);
+    }
+    rejected
+}
+
+/// Same as [find_rejected_candidates], over the snapshots of a run kept by
+/// [crate::Hd2mCvManager::debug_snapshots], each cut at its own threshold.
+///
+/// `rows` and the returned candidates are relative to the searched image, as the match results are.
+pub fn find_rejected_in_snapshots(
+    snapshots: &[Hd2mCvDebugSnapshot],
+    rows: &[Vec<DirectionDescriptor>],
+    min_distance: f64,
+) -> Vec<DirectionDescriptor> {
+    snapshots
+        .iter()
+        .flat_map(|snapshot| {
+            let offset = snapshot.row_offset;
+            // The maps of a band start at its first row, so the arrows above it are left out.
+            let band_rows: Vec<Vec<_>> = rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .filter_map(|desc| {
+                            let y = desc.position.y.checked_sub(offset)?;
+                            Some(DirectionDescriptor {
+                                position: Point::new(desc.position.x, y),
+                                ..*desc
+                            })
+                        })
+                        .collect()
+                })
+                .collect();
+            find_rejected_candidates(
+                &snapshot.results,
+                &band_rows,
+                snapshot.threshold,
+                min_distance,
+            )
+            .into_iter()
+            .map(move |mut desc| {
+                desc.position.y += offset;
+                desc
+            })
+        })
+        .collect()
+}
+
+fn draw_rect(
+    canvas: &mut RgbaImage,
+    (x, y): (i64, i64),
+    (width, height): (i64, i64),
+    color: Rgba<u8>,
+) {
+    for px in x..x + width {
+        put_pixel(canvas, (px, y), color);
+        put_pixel(canvas, (px, y + height - 1), color);
+    }
+    for py in y..y + height {
+        put_pixel(canvas, (x, py), color);
+        put_pixel(canvas, (x + width - 1, py), color);
+    }
+}
+
+fn draw_confidence(
+    canvas: &mut RgbaImage,
+    confidence: f32,
+    position: (i64, i64),
+    scale: i64,
+    color: Rgba<u8>,
+) {
+    draw_text(
+        canvas,
+        &format!("{:.2}", confidence.clamp(0.0, 9.99)),
+        position,
+        scale,
+        color,
+    );
+}
+
+// Only digits, `.` and `#` are supported, anything else is left blank.
+fn draw_text(canvas: &mut RgbaImage, text: &str, (x, y): (i64, i64), scale: i64, color: Rgba<u8>) {
+    for (i, ch) in text.chars().enumerate() {
+        let glyph = match ch {
+            '0'..='9' => DIGIT_GLYPHS[ch as usize - '0' as usize],
+            '.' => DOT_GLYPH,
+            '#' => HASH_GLYPH,
+            _ => continue,
+        };
+        let origin_x = x + i as i64 * (GLYPH_WIDTH + 1) * scale;
+        for gy in 0..GLYPH_HEIGHT {
+            for gx in 0..GLYPH_WIDTH {
+                let bit = GLYPH_WIDTH * GLYPH_HEIGHT - 1 - (gy * GLYPH_WIDTH + gx);
+                if glyph >> bit & 1 == 0 {
+                    continue;
+                }
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        put_pixel(
+                            canvas,
+                            (origin_x + gx * scale + sx, y + gy * scale + sy),
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn put_pixel(canvas: &mut RgbaImage, (x, y): (i64, i64), color: Rgba<u8>) {
+    if x >= 0 && y >= 0 && x < canvas.width() as i64 && y < canvas.height() as i64 {
+        canvas.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray as nd;
+
+    #[test]
+    fn test_draw_detection_overlay() {
+        let frame = RgbaImage::from_pixel(80, 60, Rgba([0, 0, 0, 255]));
+        let overlay = DetectionOverlay {
+            roi: Some((10, 5, 60, 50)),
+            rows: vec![vec![DirectionDescriptor {
+                direction: Direction::Up,
+                position: Point::new(10, 10),
+                confidence: 0.95,
+            }]],
+            rejected: vec![DirectionDescriptor {
+                direction: Direction::Left,
+                position: Point::new(40, 30),
+                confidence: 0.5,
+            }],
+            template_size: (8, 8),
+        };
+        let canvas = draw_detection_overlay(&frame, &overlay);
+
+        assert_eq!(*canvas.get_pixel(10, 5), ROI_COLOR);
+        assert_eq!(*canvas.get_pixel(20, 15), direction_color(Direction::Up));
+        assert_eq!(*canvas.get_pixel(27, 22), direction_color(Direction::Up));
+        assert_eq!(*canvas.get_pixel(50, 35), REJECTED_COLOR);
+        assert_eq!(*canvas.get_pixel(20 - ROW_PADDING as u32, 15), ROW_COLOR);
+        // The inside of the boxes is left untouched.
+        assert_eq!(*canvas.get_pixel(23, 18), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_draw_row_label_on_canvas() {
+        let frame = RgbaImage::from_pixel(40, 30, Rgba([0, 0, 0, 255]));
+        let overlay = DetectionOverlay {
+            rows: vec![vec![DirectionDescriptor {
+                direction: Direction::Up,
+                position: Point::new(0, 0),
+                confidence: 0.95,
+            }]],
+            template_size: (8, 8),
+            ..Default::default()
+        };
+        let canvas = draw_detection_overlay(&frame, &overlay);
+        // The top of `#`, right of the row since there is no room left of it.
+        let label_x = 8 + ROW_PADDING as u32 + 2;
+        assert_eq!(*canvas.get_pixel(label_x, 0), ROW_COLOR);
+        assert_eq!(*canvas.get_pixel(label_x + 1, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*canvas.get_pixel(label_x + 2, 0), ROW_COLOR);
+    }
+
+    #[test]
+    fn test_find_rejected_candidates() {
+        let mut response = nd::Array2::<f32>::zeros((20, 40));
+        response[[5, 5]] = 0.95;
+        response[[5, 6]] = 0.93;
+        response[[12, 30]] = 0.91;
+        let results = [TemplateMatcherResult::new("up".to_owned(), response)];
+        let rows = vec![vec![DirectionDescriptor {
+            direction: Direction::Up,
+            position: Point::new(5, 5),
+            confidence: 0.95,
+        }]];
+
+        let rejected = find_rejected_candidates(&results, &rows, 0.9, 3.0);
+        assert_eq!(
+            rejected,
+            vec![DirectionDescriptor {
+                direction: Direction::Up,
+                position: Point::new(30, 12),
+                confidence: 0.91,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_find_rejected_in_snapshots() {
+        let mut response = nd::Array2::<f32>::zeros((20, 40));
+        response[[2, 5]] = 0.95;
+        response[[2, 30]] = 0.8;
+        let snapshot = Hd2mCvDebugSnapshot {
+            row_offset: 10,
+            threshold: 0.7,
+            results: vec![TemplateMatcherResult::new("up".to_owned(), response)],
+            direction_buffer: nd::Array2::from_elem((0, 0), None),
+        };
+        let rows = vec![vec![DirectionDescriptor {
+            direction: Direction::Up,
+            position: Point::new(5, 12),
+            confidence: 0.95,
+        }]];
+
+        let rejected = find_rejected_in_snapshots(&[snapshot], &rows, 3.0);
+        assert_eq!(
+            rejected,
+            vec![DirectionDescriptor {
+                direction: Direction::Up,
+                position: Point::new(30, 12),
+                confidence: 0.8,
+            }]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Checks that steady-state runs of [Hd2mCvManager] reuse their buffers instead of allocating them
+//! on every frame.
+//!
+//! Kept in its own test binary since the allocator counts the allocations of the whole process.
+
+use hd2m_cv::{
+    render_panel_fixture, Direction, Hd2mCvManager, Hd2mCvManagerConfig, Hd2mCvSearchOptions,
+    MatchingBackend, PanelFixtureConfig, PanelFixtureTemplates, ResponsePrecision,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+// Counts the allocations of at least `LARGE_ALLOCATION` bytes while `COUNTING` is on.
+struct CountingAllocator;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static LARGE_ALLOCATION: AtomicUsize = AtomicUsize::new(usize::MAX);
+static LARGE_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn count(size: usize) {
+    if COUNTING.load(Ordering::Relaxed) && size >= LARGE_ALLOCATION.load(Ordering::Relaxed) {
+        LARGE_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_steady_state_allocations() -> anyhow::Result<()> {
+    let templates = PanelFixtureTemplates::bundled()?;
+    let panel = render_panel_fixture(
+        &templates,
+        &PanelFixtureConfig {
+            sequences: vec![vec![Direction::Down, Direction::Right, Direction::Up]],
+            screen_size: (2560, 1440),
+            ..Default::default()
+        },
+    )?
+    .image;
+    let frame = image::imageops::crop_imm(&panel, 100, 100, 200, 100).to_image();
+
+    let cases = [
+        (MatchingBackend::Native, ResponsePrecision::F32),
+        (MatchingBackend::NativeFft, ResponsePrecision::F32),
+        (MatchingBackend::NativeFft, ResponsePrecision::U8),
+    ]
+    .into_iter();
+    // OpenCV allocates its `Mat`s outside of the global allocator, so only the buffers on the Rust
+    // side are counted.
+    #[cfg(feature = "opencv")]
+    let cases = cases.chain([(MatchingBackend::OpenCv, ResponsePrecision::F32)]);
+    for (backend, precision) in cases {
+        // Half of a single response map of the frame, below the size of any of the per-frame
+        // buffers, the response maps included.
+        let response_element_size = match precision {
+            ResponsePrecision::F32 => 4,
+            ResponsePrecision::F16 => 2,
+            ResponsePrecision::U8 => 1,
+        };
+        LARGE_ALLOCATION.store(
+            (frame.width() * frame.height()) as usize * response_element_size / 2,
+            Ordering::Relaxed,
+        );
+        let manager = Hd2mCvManager::new(Hd2mCvManagerConfig {
+            search_options: Some(Hd2mCvSearchOptions {
+                threshold: Some(0.7),
+                response_precision: Some(precision),
+                ..Default::default()
+            }),
+            backend: Some(backend),
+            ..templates.manager_config()
+        })?;
+        manager.use_screen_size(2560, 1440)?;
+        // The first run allocates the buffers for the frame size.
+        let expected = manager.run_match_rgba(&frame)?;
+        assert!(!expected.commands.is_empty());
+
+        LARGE_ALLOCATIONS.store(0, Ordering::Relaxed);
+        COUNTING.store(true, Ordering::Relaxed);
+        let results = (0..3)
+            .map(|_| manager.run_match_rgba(&frame))
+            .collect::<anyhow::Result<Vec<_>>>();
+        COUNTING.store(false, Ordering::Relaxed);
+        for res in results? {
+            assert_eq!(res, expected);
+        }
+        assert_eq!(
+            LARGE_ALLOCATIONS.load(Ordering::Relaxed),
+            0,
+            "{backend:?} with {precision:?} allocated large buffers in steady state"
+        );
+    }
+    Ok(())
+}
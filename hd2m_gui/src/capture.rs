@@ -2,18 +2,21 @@ use crate::{
     feature::{CaptureManager, CaptureManagerConfig},
     util::Shutdown,
 };
-use hd2m_cv::{Direction, TryIntoCv};
+use anyhow::Result;
+use hd2m_cv::{ChannelOrder, Direction};
 use iced::{
     futures::{future, SinkExt},
     subscription, Subscription,
 };
 use opencv::{self as cv, prelude::*};
+use std::{path::PathBuf, sync::Arc};
 use tokio::sync::{mpsc, oneshot};
 
-const TEMPLATE_UP_IMAGE: &'static [u8] = include_bytes!("../resources/up.png");
-const TEMPLATE_DOWN_IMAGE: &'static [u8] = include_bytes!("../resources/down.png");
-const TEMPLATE_RIGHT_IMAGE: &'static [u8] = include_bytes!("../resources/right.png");
-const TEMPLATE_LEFT_IMAGE: &'static [u8] = include_bytes!("../resources/left.png");
+// Written by `hd2m_cli calibrate`, the defaults below are used when it is missing.
+const CALIBRATION_PATH: &'static str = "hd2m.toml";
+// Pre-processed templates, kept across launches in the cache directory of the platform so that
+// starting up doesn't make them again.
+const TEMPLATE_CACHE_DIR: &'static str = "hd2m/templates";
 
 #[derive(Debug, Clone)]
 pub enum Event {
@@ -34,43 +37,51 @@ pub fn capture_process_subscription(shutdown: Shutdown) -> Subscription<Event> {
         |mut output| async move {
             let mut state = State::Starting;
 
-            let mut manager = hd2m_cv::Hd2mCvManager::new(hd2m_cv::Hd2mCvManagerConfig {
-                template_up_image: image::load_from_memory_with_format(
-                    TEMPLATE_UP_IMAGE,
-                    image::ImageFormat::Png,
-                )
-                .unwrap()
-                .to_rgba8(),
-                template_down_image: image::load_from_memory_with_format(
-                    TEMPLATE_DOWN_IMAGE,
-                    image::ImageFormat::Png,
-                )
-                .unwrap()
-                .to_rgba8(),
-                template_right_image: image::load_from_memory_with_format(
-                    TEMPLATE_RIGHT_IMAGE,
-                    image::ImageFormat::Png,
-                )
-                .unwrap()
-                .to_rgba8(),
-                template_left_image: image::load_from_memory_with_format(
-                    TEMPLATE_LEFT_IMAGE,
-                    image::ImageFormat::Png,
-                )
-                .unwrap()
-                .to_rgba8(),
-                base_screen_size: (2560, 1440),
-                search_options: Some(hd2m_cv::Hd2mCvSearchOptions {
-                    threshold: Some(0.9),
-                    ..Default::default()
-                }),
-            })
-            .unwrap();
+            let calibration = hd2m_cv::Hd2mCvCalibration::load_if_exists(CALIBRATION_PATH)
+                .unwrap_or_else(|err| {
+                    println!("Failed to load {CALIBRATION_PATH}, using the defaults: {err}");
+                    None
+                });
+
+            // Only used with `HD2M_HDR_CAPTURE`.
+            let tone_map_options = hdr_tone_map_options();
+            let manager = Arc::new(
+                hd2m_cv::Hd2mCvManager::new(hd2m_cv::Hd2mCvManagerConfig {
+                    template_up_image: hd2m_cv::load_bundled_template(Direction::Up).unwrap(),
+                    template_down_image: hd2m_cv::load_bundled_template(Direction::Down).unwrap(),
+                    template_right_image: hd2m_cv::load_bundled_template(Direction::Right).unwrap(),
+                    template_left_image: hd2m_cv::load_bundled_template(Direction::Left).unwrap(),
+                    template_source_hashes: Some(hd2m_cv::bundled_template_source_hashes()),
+                    base_screen_size: calibration
+                        .as_ref()
+                        .map_or(hd2m_cv::BUNDLED_TEMPLATE_SCREEN_SIZE, |c| {
+                            c.base_screen_size
+                        }),
+                    hud_scale: calibration.as_ref().map(|c| c.hud_scale),
+                    search_options: Some(calibration.as_ref().map_or(
+                        hd2m_cv::Hd2mCvSearchOptions {
+                            threshold: Some(0.9),
+                            ..Default::default()
+                        },
+                        |c| c.search_options(),
+                    )),
+                    backend: Some(hd2m_cv::MatchingBackend::OpenCv),
+                    template_cache: open_template_cache(),
+                    tone_map_options: Some(tone_map_options.clone()),
+                })
+                .unwrap(),
+            );
+            // Keeps the response maps of each run for the rejected candidates of the overlay.
+            let debug_frame = std::env::var_os("HD2M_DEBUG_FRAME").is_some();
+            manager.set_debug(debug_frame);
+            // Match of the latest request, a newer request replacing and so cancelling it.
+            let mut pending_match: Option<PendingMatch> = None;
 
             let (capture_chan_tx, capture_chan_rx) = mpsc::channel(1);
             tokio::spawn({
                 let capture_manager = CaptureManager::new(CaptureManagerConfig {
                     window_title: "HELLDIVERS™ 2".to_owned(),
+                    hdr: std::env::var_os("HD2M_HDR_CAPTURE").is_some(),
                 })
                 .unwrap();
                 async move {
@@ -99,33 +110,56 @@ pub fn capture_process_subscription(shutdown: Shutdown) -> Subscription<Event> {
                                         let result = cap_rx.await.unwrap();
 
                                         let size = result.size().unwrap();
-                                        let cropped = cv::core::Mat::roi(
-                                            &result,
-                                            cv::core::Rect::new(
-                                                0,
-                                                0,
-                                                (size.width as f64 * 0.164) as i32,
-                                                (size.height as f64 * 0.465) as i32,
-                                            ),
-                                        ).unwrap().clone_pointee();
-
-                                        manager.use_screen_size(size.width as usize, size.height as usize).unwrap();
-                                        let res = manager.run_match_mat(&cropped).unwrap();
-
-                                        // let res = manager.run_match_rgba(&frame.to_rgba8()).unwrap();
+                                        let (width, height) = (size.width as usize, size.height as usize);
+                                        // The templates are resized for the frame by the match, on the rayon pool.
+                                        let roi = match &calibration {
+                                            Some(calibration) => calibration.crop_rect(width, height),
+                                            None => manager.layout_for(width, height).panel_roi(),
+                                        };
+                                        // The match reads the panel straight from the frame, so the frame is
+                                        // only copied for the overlay.
+                                        let frame = debug_frame
+                                            .then(|| result.try_clone())
+                                            .transpose()
+                                            .unwrap_or_else(|err| {
+                                                println!("Failed to copy the debug frame: {err}");
+                                                None
+                                            });
+
+                                        // Matched on the rayon pool, so that the inputs keep being handled meanwhile.
+                                        // HDR captures have their panel tone-mapped there as well.
+                                        pending_match = Some(PendingMatch {
+                                            result: manager.match_mat_roi_async(result, roi),
+                                            frame,
+                                            roi,
+                                        });
+                                    }
+                                }
+                            }
+                            res = async { (&mut pending_match.as_mut().unwrap().result).await }, if pending_match.is_some() => {
+                                let PendingMatch { frame, roi, .. } = pending_match.take().unwrap();
+                                match res {
+                                    Ok(res) => {
                                         println!(
-                                            "Res: {:?}",
-                                            res.iter()
+                                            "Res: {:?} (threshold: {})",
+                                            res.commands.iter()
                                                 .map(|e| e.iter().map(|e| e.direction).collect::<Vec<Direction>>())
-                                                .collect::<Vec<_>>()
+                                                .collect::<Vec<_>>(),
+                                            res.threshold
                                         );
-                                        // let frame: image::RgbaImage = cropped.clone().try_into_cv().unwrap();
-                                        // frame.save("frame.png").unwrap();
+                                        if let Some(frame) = frame {
+                                            if let Err(err) = save_debug_frame(&manager, &frame, roi, &tone_map_options, &res) {
+                                                println!("Failed to save the debug frame: {err}");
+                                            }
+                                        }
 
-                                        let _ = output.send(Event::ResultStratMacro(res.iter()
+                                        let _ = output.send(Event::ResultStratMacro(res.commands.iter()
                                         .map(|e| e.iter().map(|e| e.direction).collect::<Vec<Direction>>())
                                         .collect::<Vec<_>>())).await;
                                     }
+                                    // Only when its future was dropped, replaced by a newer request.
+                                    Err(err) if err.is::<hd2m_cv::MatchCancelled>() => {}
+                                    Err(err) => println!("Failed to match: {err}"),
                                 }
                             }
                             _ = shutdown.recv_shutdown() => {
@@ -140,6 +174,44 @@ pub fn capture_process_subscription(shutdown: Shutdown) -> Subscription<Event> {
     )
 }
 
+// Saves the detection overlay of `res` over `frame` to `frame.png`.
+fn save_debug_frame(
+    manager: &hd2m_cv::Hd2mCvManager,
+    frame: &cv::core::Mat,
+    roi: (usize, usize, usize, usize),
+    tone_map_options: &hd2m_cv::ToneMapOptions,
+    res: &hd2m_cv::Hd2mCvMatchResult,
+) -> Result<()> {
+    let frame: image::RgbaImage = if frame.typ() == cv::core::CV_16FC4 {
+        // Exposed for the panel, as in the match.
+        let frame = hd2m_cv::HdrFrame::from_mat(frame)?;
+        hd2m_cv::tone_map_hdr_frame(
+            &frame,
+            &hd2m_cv::ToneMapOptions {
+                roi: Some(roi),
+                ..tone_map_options.clone()
+            },
+        )?
+    } else {
+        hd2m_cv::mat_to_image(frame, ChannelOrder::Rgb)?
+    };
+    let template_size = manager.template_size().unwrap_or_default();
+    hd2m_cv::save_detection_overlay(
+        &frame,
+        &hd2m_cv::DetectionOverlay {
+            roi: Some(roi),
+            rows: res.commands.clone(),
+            rejected: hd2m_cv::find_rejected_in_snapshots(
+                &manager.debug_snapshots(),
+                &res.commands,
+                template_size.0 as f64,
+            ),
+            template_size,
+        },
+        "frame.png",
+    )
+}
+
 #[derive(Debug, Default)]
 enum State {
     #[default]
@@ -147,3 +219,68 @@ enum State {
     Ready(mpsc::Receiver<Input>),
     Closed,
 }
+
+// Match running on the rayon pool, along with what is needed to report it.
+struct PendingMatch {
+    result: hd2m_cv::MatchFuture,
+    // Only kept with `HD2M_DEBUG_FRAME`.
+    frame: Option<cv::core::Mat>,
+    roi: (usize, usize, usize, usize),
+}
+
+// Tone mapping of the HDR captures, from `HD2M_HDR_TONE_CURVE` (`clip`, `aces` or
+// `reinhard:<white>`) and `HD2M_HDR_EXPOSURE`, the clipping curve and the automatic exposure of the
+// panel by default.
+fn hdr_tone_map_options() -> hd2m_cv::ToneMapOptions {
+    let from_env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    hd2m_cv::ToneMapOptions {
+        curve: from_env("HD2M_HDR_TONE_CURVE").and_then(|curve| {
+            curve
+                .parse::<hd2m_cv::ToneCurve>()
+                .inspect_err(|err| {
+                    println!("Invalid HD2M_HDR_TONE_CURVE, using the default: {err}")
+                })
+                .ok()
+        }),
+        exposure: from_env("HD2M_HDR_EXPOSURE").and_then(|exposure| {
+            exposure
+                .parse::<f32>()
+                .inspect_err(|err| {
+                    println!("Invalid HD2M_HDR_EXPOSURE, using the automatic exposure: {err}")
+                })
+                .ok()
+        }),
+        ..Default::default()
+    }
+}
+
+// Opens the template cache, matching without it when there is no cache directory to put it in.
+fn open_template_cache() -> Option<hd2m_cv::TemplateCache> {
+    let Some(dir) = platform_cache_dir() else {
+        println!("No cache directory found, the templates won't be cached");
+        return None;
+    };
+    let dir = dir.join(TEMPLATE_CACHE_DIR);
+    hd2m_cv::TemplateCache::new(&dir)
+        .inspect_err(|err| {
+            println!(
+                "Failed to open the template cache in {}, the templates won't be cached: {err}",
+                dir.display()
+            )
+        })
+        .ok()
+}
+
+// `%LOCALAPPDATA%` on Windows, `$XDG_CACHE_HOME` or `~/.cache` elsewhere.
+fn platform_cache_dir() -> Option<PathBuf> {
+    let from_env = |name: &str| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    if cfg!(windows) {
+        from_env("LOCALAPPDATA")
+    } else {
+        from_env("XDG_CACHE_HOME").or_else(|| from_env("HOME").map(|home| home.join(".cache")))
+    }
+}
@@ -1,4 +1,4 @@
-use crate::util::convert_frame_to_mat;
+use crate::util::{convert_frame_to_mat, convert_hdr_frame_to_mat};
 use anyhow::{Error, Result};
 use opencv::{self as cv};
 use tokio::sync::{mpsc, oneshot};
@@ -14,17 +14,22 @@ pub type TriggerCaptureRx = mpsc::Receiver<oneshot::Sender<cv::core::Mat>>;
 #[derive(Debug)]
 pub struct CaptureManagerConfig {
     pub window_title: String,
+    /// Captures in 16-bit float for displays with HDR enabled, sending `CV_16FC4` frames whose
+    /// panel is tone-mapped by [hd2m_cv::Hd2mCvManager::match_mat_roi_async].
+    pub hdr: bool,
 }
 
 #[derive(Debug)]
 pub struct CaptureManager {
     window_title: String,
+    hdr: bool,
 }
 
 impl CaptureManager {
     pub fn new(config: CaptureManagerConfig) -> Result<Self> {
         Ok(Self {
             window_title: config.window_title,
+            hdr: config.hdr,
         })
     }
 
@@ -35,8 +40,15 @@ impl CaptureManager {
             window,
             CursorCaptureSettings::WithoutCursor,
             DrawBorderSettings::WithoutBorder,
-            ColorFormat::Rgba8,
-            CaptureConfig { trigger_capture_rx },
+            if self.hdr {
+                ColorFormat::Rgba16F
+            } else {
+                ColorFormat::Rgba8
+            },
+            CaptureConfig {
+                trigger_capture_rx,
+                hdr: self.hdr,
+            },
         )?;
         let _ = tokio::task::spawn_blocking(move || {
             Capture::start(settings)?;
@@ -50,11 +62,13 @@ impl CaptureManager {
 #[derive(Debug)]
 struct CaptureConfig {
     pub trigger_capture_rx: TriggerCaptureRx,
+    pub hdr: bool,
 }
 
 #[derive(Debug)]
 struct Capture {
     trigger_capture_rx: TriggerCaptureRx,
+    hdr: bool,
 }
 
 impl GraphicsCaptureApiHandler for Capture {
@@ -64,6 +78,7 @@ impl GraphicsCaptureApiHandler for Capture {
     fn new(config: CaptureConfig) -> Result<Self, Self::Error> {
         Ok(Self {
             trigger_capture_rx: config.trigger_capture_rx,
+            hdr: config.hdr,
         })
     }
 
@@ -75,7 +90,11 @@ impl GraphicsCaptureApiHandler for Capture {
         let msg = self.trigger_capture_rx.try_recv();
         match msg {
             Ok(cb) => {
-                let mat = convert_frame_to_mat(frame)?;
+                let mat = if self.hdr {
+                    convert_hdr_frame_to_mat(frame)?
+                } else {
+                    convert_frame_to_mat(frame)?
+                };
                 let _ = cb.send(mat);
                 Ok(())
             }
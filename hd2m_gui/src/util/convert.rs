@@ -1,17 +1,104 @@
 use anyhow::Result;
+use hd2m_cv::ChannelOrder;
 use opencv as cv;
-use std::os::raw::c_void;
+
+// Size of an `Rgba16F` pixel.
+const HDR_BYTES_PER_PIXEL: usize = 8;
 
 pub fn convert_frame_to_mat(from: &mut windows_capture::frame::Frame) -> Result<cv::core::Mat> {
     let mut data = from.buffer()?;
-    let mat = unsafe {
-        cv::core::Mat::new_rows_cols_with_data_unsafe(
-            data.height() as i32,
-            data.width() as i32,
-            cv::core::CV_8UC4,
-            data.as_raw_nopadding_buffer()?.as_ptr() as *mut c_void,
-            cv::core::Mat_AUTO_STEP,
-        )?
-    };
+    let (width, height) = (data.width(), data.height());
+    // INFO: The capture is set up with `ColorFormat::Rgba8`, which is also the order the matcher
+    // expects. The buffer is only borrowed from the frame, so the pixels are copied out of it.
+    let frame = image::ImageBuffer::<image::Rgba<u8>, &[u8]>::from_raw(
+        width,
+        height,
+        data.as_raw_nopadding_buffer()?,
+    )
+    .ok_or(anyhow::anyhow!("Frame buffer is shorter than its size"))?;
+    hd2m_cv::image_to_mat(&frame, ChannelOrder::Rgb)
+}
+
+/// Copies a frame captured with `ColorFormat::Rgba16F` into a `CV_16FC4` `Mat`, whose panel is
+/// tone-mapped by the match.
+pub fn convert_hdr_frame_to_mat(from: &mut windows_capture::frame::Frame) -> Result<cv::core::Mat> {
+    let mut data = from.buffer()?;
+    let (width, height) = (data.width() as usize, data.height() as usize);
+    let row_pitch = data.row_pitch() as usize;
+    // INFO: `as_raw_nopadding_buffer` takes 4 bytes per pixel, so its rows are half of the ones of
+    // `Rgba16F` frames. The rows are copied from the padded buffer instead.
+    copy_hdr_rows_to_mat(data.as_raw_buffer(), width, height, row_pitch)
+}
+
+// Copies `height` rows of `width` `Rgba16F` pixels, starting `row_pitch` bytes apart in `buffer`,
+// into a `CV_16FC4` `Mat`.
+fn copy_hdr_rows_to_mat(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    row_pitch: usize,
+) -> Result<cv::core::Mat> {
+    use cv::prelude::*;
+
+    anyhow::ensure!(width > 0 && height > 0, "Expect a non-empty frame");
+    let row_bytes = width * HDR_BYTES_PER_PIXEL;
+    anyhow::ensure!(
+        row_pitch >= row_bytes,
+        "Expect rows of at least {row_bytes} bytes, but get a row pitch of {row_pitch} bytes"
+    );
+    // The last row doesn't need its padding.
+    let len = (height - 1) * row_pitch + row_bytes;
+    anyhow::ensure!(
+        buffer.len() >= len,
+        "Expect {len} bytes for the frame, but get {} bytes",
+        buffer.len()
+    );
+    let mut mat = cv::core::Mat::new_rows_cols_with_default(
+        height as i32,
+        width as i32,
+        cv::core::CV_16FC4,
+        cv::core::Scalar::all(0.0),
+    )?;
+    let bytes = mat.data_bytes_mut()?;
+    for (row, src) in bytes
+        .chunks_exact_mut(row_bytes)
+        .zip(buffer.chunks(row_pitch))
+    {
+        row.copy_from_slice(&src[..row_bytes]);
+    }
     Ok(mat)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cv::prelude::*;
+
+    #[test]
+    fn test_copy_padded_hdr_rows() -> Result<()> {
+        // 3 pixels per row, padded to 32 bytes as GPUs align their rows.
+        let (width, height, row_pitch) = (3, 2, 32);
+        let mut buffer = vec![0xffu8; row_pitch * height];
+        for y in 0..height {
+            for x in 0..width * HDR_BYTES_PER_PIXEL {
+                buffer[y * row_pitch + x] = (y * 100 + x) as u8;
+            }
+        }
+
+        let mat = copy_hdr_rows_to_mat(&buffer, width, height, row_pitch)?;
+        assert_eq!(mat.typ(), cv::core::CV_16FC4);
+        assert_eq!((mat.cols(), mat.rows()), (3, 2));
+        let bytes = mat.data_bytes()?;
+        for y in 0..height {
+            let row = &bytes[y * 24..(y + 1) * 24];
+            assert_eq!(row, &buffer[y * row_pitch..y * row_pitch + 24]);
+        }
+
+        // The last row doesn't need its padding, but the others can't overlap.
+        assert!(copy_hdr_rows_to_mat(&buffer[..row_pitch + 24], width, height, row_pitch).is_ok());
+        assert!(copy_hdr_rows_to_mat(&buffer[..row_pitch + 23], width, height, row_pitch).is_err());
+        assert!(copy_hdr_rows_to_mat(&buffer, width, height, 16).is_err());
+        assert!(copy_hdr_rows_to_mat(&buffer, 0, height, row_pitch).is_err());
+        Ok(())
+    }
+}